@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+create table document_revisions (
+    id              char(21) primary key,
+    document_id     char(21) not null references documents(id),
+    user_id         char(21) not null references users(id),
+    blocks          jsonb not null,
+    created_at      timestamp not null default now()
+);
+
+create index document_revisions_document_id_idx on document_revisions (document_id, created_at);
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+
+drop index if exists blocks_last_edited_by_idx;
+
+alter table blocks
+    drop column if exists last_edited_by,
+    drop column if exists last_edited_at;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+alter table blocks
+    add column last_edited_by   char(21) references users(id),
+    add column last_edited_at   timestamp not null default now();
+
+create index blocks_last_edited_by_idx on blocks (document_id, last_edited_by);
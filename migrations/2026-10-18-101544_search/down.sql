@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+drop index if exists documents_search_idx;
+drop index if exists text_blocks_search_idx;
+drop function if exists html_escape(text);
@@ -0,0 +1,19 @@
+-- Your SQL goes here
+
+-- These expressions must match the ones used by the search query for
+-- postgres to pick up the indexes.
+
+create index documents_search_idx on documents using gin ((
+    setweight(to_tsvector('english', name), 'A') ||
+    setweight(to_tsvector('english', coalesce(description, '')), 'B')
+));
+
+create index text_blocks_search_idx on text_blocks using gin (
+    to_tsvector('english', coalesce(content, ''))
+);
+
+-- Snippets are returned as html with matches wrapped in <mark>, so the
+-- text they are cut from has to be escaped first.
+create or replace function html_escape(text) returns text as $$
+    select replace(replace(replace($1, '&', '&amp;'), '<', '&lt;'), '>', '&gt;');
+$$ language sql immutable strict;
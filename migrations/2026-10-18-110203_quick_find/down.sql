@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+drop index if exists repositories_name_trgm_idx;
+drop index if exists documents_name_trgm_idx;
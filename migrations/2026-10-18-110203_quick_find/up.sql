@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+create extension if not exists pg_trgm;
+
+create index repositories_name_trgm_idx on repositories using gin (name gin_trgm_ops);
+create index documents_name_trgm_idx on documents using gin (name gin_trgm_ops);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+alter table repositories add column published_at timestamp;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+create table uploads (
+    id           char(21) primary key,
+    user_id      char(21) not null references users(id),
+    key          text unique not null,
+    content_type text not null,
+    size         bigint not null,
+    created_at   timestamp not null default now()
+);
+
+create index uploads_user_id_idx on uploads (user_id);
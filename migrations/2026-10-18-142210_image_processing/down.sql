@@ -0,0 +1,16 @@
+-- This file should undo anything in `up.sql`
+
+alter table image_blocks
+    drop column if exists width,
+    drop column if exists height,
+    drop column if exists format,
+    drop column if exists srcset;
+
+drop table if exists upload_variants;
+
+drop index if exists uploads_unprocessed_idx;
+
+alter table uploads
+    drop column if exists width,
+    drop column if exists height,
+    drop column if exists processed_at;
@@ -0,0 +1,26 @@
+-- Your SQL goes here
+
+-- Uploads are only served once processed_at is set. Width and height stay
+-- null when the image couldn't be decoded.
+alter table uploads
+    add column width        integer,
+    add column height       integer,
+    add column processed_at timestamp;
+
+create index uploads_unprocessed_idx on uploads (created_at) where processed_at is null;
+
+create table upload_variants (
+    key       text primary key,
+    upload_id char(21) not null references uploads(id) on delete cascade,
+    width     integer not null,
+    height    integer not null,
+    size      bigint not null
+);
+
+create index upload_variants_upload_id_idx on upload_variants (upload_id);
+
+alter table image_blocks
+    add column width  integer,
+    add column height integer,
+    add column format text,
+    add column srcset text;
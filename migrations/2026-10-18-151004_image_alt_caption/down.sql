@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+alter table image_blocks
+    drop column if exists alt,
+    drop column if exists caption;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+-- Blank alt text and captions are stored as null so the accessibility
+-- report only has to look for nulls.
+alter table image_blocks
+    add column alt     text,
+    add column caption text;
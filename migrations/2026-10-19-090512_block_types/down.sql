@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+drop table if exists list_item_blocks, todo_blocks, quote_blocks, code_blocks, callout_blocks, divider_blocks;
+drop type if exists list_style, callout_variant;
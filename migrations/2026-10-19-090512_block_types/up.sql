@@ -0,0 +1,59 @@
+-- Your SQL goes here
+
+create type list_style as enum ('BULLETED', 'NUMBERED');
+
+create table list_item_blocks (
+    block_id        char(21) primary key references blocks(id),
+    style           list_style not null,
+    indent          integer not null default 0 check (indent between 0 and 8),
+    content         text,
+    updated_at      timestamp not null default now()
+);
+
+SELECT diesel_manage_updated_at('list_item_blocks');
+
+create table todo_blocks (
+    block_id        char(21) primary key references blocks(id),
+    checked         boolean not null default false,
+    indent          integer not null default 0 check (indent between 0 and 8),
+    content         text,
+    updated_at      timestamp not null default now()
+);
+
+SELECT diesel_manage_updated_at('todo_blocks');
+
+create table quote_blocks (
+    block_id        char(21) primary key references blocks(id),
+    content         text,
+    updated_at      timestamp not null default now()
+);
+
+SELECT diesel_manage_updated_at('quote_blocks');
+
+create table code_blocks (
+    block_id        char(21) primary key references blocks(id),
+    language        text,
+    content         text,
+    updated_at      timestamp not null default now()
+);
+
+SELECT diesel_manage_updated_at('code_blocks');
+
+create type callout_variant as enum ('NOTE', 'TIP', 'IMPORTANT', 'WARNING', 'CAUTION');
+
+create table callout_blocks (
+    block_id        char(21) primary key references blocks(id),
+    variant         callout_variant not null,
+    content         text,
+    updated_at      timestamp not null default now()
+);
+
+SELECT diesel_manage_updated_at('callout_blocks');
+
+-- Dividers have no content, the row only records the block's type.
+create table divider_blocks (
+    block_id        char(21) primary key references blocks(id),
+    updated_at      timestamp not null default now()
+);
+
+SELECT diesel_manage_updated_at('divider_blocks');
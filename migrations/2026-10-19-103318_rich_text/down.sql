@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+
+alter table text_blocks drop column if exists rich_text;
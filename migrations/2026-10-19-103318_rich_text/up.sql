@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- Runs of text with marks such as bold or a link. Null when the text has
+-- no marks, content always holds the plain text.
+alter table text_blocks add column rich_text jsonb;
@@ -0,0 +1,22 @@
+-- This file should undo anything in `up.sql`
+
+-- Nested blocks and toggles can't be kept in a flat list of blocks.
+create temporary table removed_blocks as
+    select id from blocks where parent_id is not null or id in (select block_id from toggle_blocks);
+
+delete from text_blocks where block_id in (select id from removed_blocks);
+delete from image_blocks where block_id in (select id from removed_blocks);
+delete from list_item_blocks where block_id in (select id from removed_blocks);
+delete from todo_blocks where block_id in (select id from removed_blocks);
+delete from quote_blocks where block_id in (select id from removed_blocks);
+delete from code_blocks where block_id in (select id from removed_blocks);
+delete from callout_blocks where block_id in (select id from removed_blocks);
+delete from divider_blocks where block_id in (select id from removed_blocks);
+drop table if exists toggle_blocks;
+delete from blocks where id in (select id from removed_blocks);
+
+drop table removed_blocks;
+
+alter table blocks drop constraint blocks_document_id_parent_id_line_number_key;
+alter table blocks add constraint blocks_document_id_line_number_key unique (document_id, line_number);
+alter table blocks drop column parent_id;
@@ -0,0 +1,20 @@
+-- Your SQL goes here
+
+-- Blocks can be nested under list items, to-dos and toggles. line_number
+-- is now the position among the blocks with the same parent, top level
+-- blocks have no parent.
+alter table blocks add column parent_id char(21) references blocks(id);
+
+alter table blocks drop constraint blocks_document_id_line_number_key;
+alter table blocks add constraint blocks_document_id_parent_id_line_number_key
+    unique nulls not distinct (document_id, parent_id, line_number);
+
+create index blocks_parent_id_idx on blocks (parent_id);
+
+create table toggle_blocks (
+    block_id        char(21) primary key references blocks(id),
+    content         text,
+    updated_at      timestamp not null default now()
+);
+
+SELECT diesel_manage_updated_at('toggle_blocks');
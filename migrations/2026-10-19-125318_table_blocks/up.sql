@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+-- Columns and rows are json validated by the server. Rows key their cells
+-- by column id so columns can be reordered without touching them.
+create table table_blocks (
+    block_id        char(21) primary key references blocks(id),
+    table_columns   jsonb not null default '[]',
+    table_rows      jsonb not null default '[]',
+    updated_at      timestamp not null default now()
+);
+
+SELECT diesel_manage_updated_at('table_blocks');
@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+
+drop table database_views;
+drop type view_layout;
+drop table property_values;
+drop table database_properties;
+drop type property_type;
+
+drop index documents_database_id_idx;
+alter table documents drop column database_id;
+alter table documents drop column is_database;
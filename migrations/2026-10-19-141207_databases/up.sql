@@ -0,0 +1,63 @@
+-- Your SQL goes here
+
+-- A database is a document whose rows are the documents with its id in
+-- database_id. Property values are stored in a column per type so
+-- queries can filter and sort on them.
+alter table documents add column is_database boolean not null default false;
+alter table documents add column database_id char(21) references documents(id);
+
+create index documents_database_id_idx on documents (database_id);
+
+create type property_type as enum ('SELECT', 'MULTI_SELECT', 'DATE', 'PERSON', 'NUMBER', 'URL');
+
+create table database_properties (
+    id              char(21) primary key,
+    database_id     char(21) not null references documents(id),
+    name            text not null,
+    property_type   property_type not null,
+    options         jsonb not null default '[]',
+    position        integer not null,
+    created_at      timestamp not null default now(),
+    updated_at      timestamp not null default now(),
+    unique (database_id, name)
+);
+
+SELECT diesel_manage_updated_at('database_properties');
+
+-- Only set values have a row.
+create table property_values (
+    document_id     char(21) not null references documents(id),
+    property_id     char(21) not null references database_properties(id),
+    text_value      text,
+    option_values   text[],
+    number_value    double precision,
+    date_value      date,
+    user_id         char(21) references users(id),
+    updated_at      timestamp not null default now(),
+    primary key (document_id, property_id)
+);
+
+create index property_values_property_id_idx on property_values (property_id);
+
+SELECT diesel_manage_updated_at('property_values');
+
+create type view_layout as enum ('TABLE', 'BOARD', 'CALENDAR');
+
+-- property_id is the select property a board is grouped by or the date
+-- property of a calendar. Filters and sorts are json as they are only
+-- read back whole.
+create table database_views (
+    id              char(21) primary key,
+    database_id     char(21) not null references documents(id),
+    name            text not null,
+    layout          view_layout not null,
+    property_id     char(21) references database_properties(id),
+    filters         jsonb not null default '[]',
+    sorts           jsonb not null default '[]',
+    created_at      timestamp not null default now(),
+    updated_at      timestamp not null default now()
+);
+
+create index database_views_database_id_idx on database_views (database_id);
+
+SELECT diesel_manage_updated_at('database_views');
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+create table math_blocks (
+    block_id        char(21) primary key references blocks(id),
+    content         text,
+    updated_at      timestamp not null default now()
+);
+
+SELECT diesel_manage_updated_at('math_blocks');
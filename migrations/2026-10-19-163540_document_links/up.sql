@@ -0,0 +1,46 @@
+-- Your SQL goes here
+
+-- A `[[Document]]` link in the text of a block. The target is resolved
+-- from the label when the link is first saved and kept by id after, so
+-- renaming or moving the target doesn't break it. Links whose label
+-- matches no document have no target until one with that name is made.
+create table document_links (
+    block_id            char(21) not null references blocks(id),
+    source_document_id  char(21) not null references documents(id),
+    target_document_id  char(21) references documents(id),
+    label               text not null,
+    created_at          timestamp not null default now(),
+    primary key (block_id, label)
+);
+
+create index document_links_source_document_id_idx on document_links (source_document_id);
+create index document_links_target_document_id_idx on document_links (target_document_id);
+
+-- links in blocks saved before links were tracked
+insert into document_links (block_id, source_document_id, target_document_id, label)
+select distinct on (texts.block_id, labels.label)
+    texts.block_id,
+    blocks.document_id,
+    (
+        select targets.id from documents targets
+        where targets.repository_id = sources.repository_id
+            and (lower(targets.name) = lower(labels.label) or targets.slug = labels.label)
+        order by targets.created_at
+        limit 1
+    ),
+    labels.label
+from (
+    select block_id, content from text_blocks
+    union all select block_id, content from list_item_blocks
+    union all select block_id, content from todo_blocks
+    union all select block_id, content from quote_blocks
+    union all select block_id, content from callout_blocks
+    union all select block_id, content from toggle_blocks
+) texts
+join blocks on blocks.id = texts.block_id
+join documents sources on sources.id = blocks.document_id
+cross join lateral (
+    select trim(matches[1]) as label
+    from regexp_matches(texts.content, '\[\[([^][\n]+)\]\]', 'g') matches
+) labels
+where labels.label <> '' and length(labels.label) <= 256;
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+
+create temporary table removed_blocks as select block_id as id from embed_blocks;
+
+drop table if exists embed_blocks;
+delete from blocks where id in (select id from removed_blocks);
+
+drop table removed_blocks;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+-- The source is resolved when the block is read. Deleting the source
+-- leaves the embed in place without one.
+create table embed_blocks (
+    block_id            char(21) primary key references blocks(id),
+    source_block_id     char(21) references blocks(id) on delete set null,
+    updated_at          timestamp not null default now()
+);
+
+create index embed_blocks_source_block_id_idx on embed_blocks (source_block_id);
+
+SELECT diesel_manage_updated_at('embed_blocks');
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+drop index documents_parent_id_idx;
+alter table documents drop column position;
+alter table documents drop column parent_id;
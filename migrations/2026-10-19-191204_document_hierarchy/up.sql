@@ -0,0 +1,16 @@
+-- Your SQL goes here
+
+-- Documents can be nested under another document of the same repository.
+-- Position orders the documents that share a parent.
+alter table documents add column parent_id char(21) references documents(id);
+alter table documents add column position integer not null default 0;
+
+update documents
+set position = numbered.position
+from (
+    select id, (row_number() over (partition by repository_id order by name) - 1)::integer as position
+    from documents
+) numbered
+where documents.id = numbered.id;
+
+create index documents_parent_id_idx on documents (repository_id, parent_id, position);
@@ -0,0 +1,21 @@
+-- Your SQL goes here
+
+-- Who can do what in a repository. repositories.user_id stays the user
+-- the repository was created by and whose site path it is under.
+create type member_role as enum ('OWNER', 'ADMIN', 'EDITOR', 'COMMENTER', 'VIEWER');
+
+create table repository_members (
+    repository_id   char(21) not null references repositories(id) on delete cascade,
+    user_id         char(21) not null references users(id) on delete cascade,
+    role            member_role not null,
+    created_at      timestamp not null default now(),
+    updated_at      timestamp not null default now(),
+    primary key (repository_id, user_id)
+);
+
+create index repository_members_user_id_idx on repository_members (user_id);
+
+SELECT diesel_manage_updated_at('repository_members');
+
+insert into repository_members (repository_id, user_id, role)
+select id, user_id, 'OWNER' from repositories;
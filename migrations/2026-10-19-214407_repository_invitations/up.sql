@@ -0,0 +1,19 @@
+-- Your SQL goes here
+
+-- Invitations for people who may not have an account yet. Only a hash of
+-- the token is kept, the token itself is in the mail. Emails are stored
+-- lowercase so an address can only be invited once per repository.
+create table repository_invitations (
+    id              char(21) primary key,
+    repository_id   char(21) not null references repositories(id) on delete cascade,
+    email           text not null,
+    role            member_role not null,
+    token_hash      char(64) unique not null,
+    invited_by      char(21) not null references users(id) on delete cascade,
+    expires_at      timestamp not null,
+    created_at      timestamp not null default now(),
+    updated_at      timestamp not null default now(),
+    unique (repository_id, email)
+);
+
+SELECT diesel_manage_updated_at('repository_invitations');
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+
+drop index if exists list_item_blocks_search_idx;
+drop index if exists todo_blocks_search_idx;
+drop index if exists quote_blocks_search_idx;
+drop index if exists code_blocks_search_idx;
+drop index if exists callout_blocks_search_idx;
+drop index if exists toggle_blocks_search_idx;
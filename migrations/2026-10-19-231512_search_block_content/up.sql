@@ -0,0 +1,28 @@
+-- Your SQL goes here
+
+-- The text of list items, to-dos, quotes, code, callouts and toggles is
+-- searched along with text blocks, with the same expression.
+
+create index list_item_blocks_search_idx on list_item_blocks using gin (
+    to_tsvector('english', coalesce(content, ''))
+);
+
+create index todo_blocks_search_idx on todo_blocks using gin (
+    to_tsvector('english', coalesce(content, ''))
+);
+
+create index quote_blocks_search_idx on quote_blocks using gin (
+    to_tsvector('english', coalesce(content, ''))
+);
+
+create index code_blocks_search_idx on code_blocks using gin (
+    to_tsvector('english', coalesce(content, ''))
+);
+
+create index callout_blocks_search_idx on callout_blocks using gin (
+    to_tsvector('english', coalesce(content, ''))
+);
+
+create index toggle_blocks_search_idx on toggle_blocks using gin (
+    to_tsvector('english', coalesce(content, ''))
+);
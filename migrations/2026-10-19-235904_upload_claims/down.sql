@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+
+alter table uploads drop column if exists claimed_at;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+-- Set by the worker processing the upload, so the row isn't kept locked
+-- while the image is decoded and stored. Claims older than a few minutes
+-- are taken over, the worker holding them stopped.
+alter table uploads add column claimed_at timestamp;
@@ -0,0 +1,107 @@
+use std::{fs, io, path::Path};
+
+use crate::{db::DBPool, models::{repository::{RepositoryOperation, RepositoryResult}, document::DocumentOperation, block::BlockOperation, embed::{EmbedOperation, Reader}, upload::UploadOperation}, helpers::{html, blob_store, site::{self, SiteLinks, SiteDocuments, SearchEntry}}};
+
+const USAGE: &str = "usage: server [export-site <repository slug> <output directory>]";
+
+/// Runs the subcommand named in `args`, returns false when there is none
+/// so the server should be started instead.
+pub fn run(pool: &DBPool, args: &[String]) -> io::Result<bool> {
+    match args {
+        [] => Ok(false),
+        [command, repository_slug, output_dir] if command == "export-site" => {
+            export_site(pool, repository_slug, Path::new(output_dir))?;
+            Ok(true)
+        },
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    }
+}
+
+/// Builds a static copy of a repository's site into `output_dir` using the
+/// same renderer as the live site.
+fn export_site(pool: &DBPool, repository_slug: &str, output_dir: &Path) -> io::Result<()> {
+    let mut conn = pool.get().map_err(io::Error::other)?;
+
+    let repository = match RepositoryOperation::find_by_slug(&mut conn, repository_slug) {
+        RepositoryResult::Repository(repository) => repository,
+        _ => return Err(io::Error::new(io::ErrorKind::NotFound, format!("repository {} not found", repository_slug))),
+    };
+
+    let document_list = DocumentOperation::find_by_repository(&mut conn, &repository.id);
+    let site_documents = SiteDocuments::new(&document_list);
+    let blob_store = blob_store::from_env();
+
+    fs::create_dir_all(output_dir)?;
+
+    let mut search_index = Vec::new();
+    let mut uploads = Vec::new();
+
+    for document in &document_list {
+        let path = site_documents.path(document);
+        let links = SiteLinks::static_from(path);
+
+        let mut block_list = BlockOperation::find_by_document_with_content(&mut conn, &document.id)
+            .map_err(io::Error::other)?;
+
+        EmbedOperation::resolve_all(&mut conn, Reader::Site(&repository.id), &mut block_list);
+
+        let rendered = html::render_with(&block_list, |url| {
+            if let Some(key) = blob_store::key_from_url(url) {
+                // same rule as the server, unprocessed uploads may still
+                // carry metadata
+                if UploadOperation::servable_content_type(&mut conn, key).is_none() {
+                    log::warn!("leaving out unprocessed image {} from {}", url, document.slug);
+                    return None;
+                }
+
+                uploads.push(key.to_string());
+                return Some(format!("{}uploads/{}", "../".repeat(path.matches('/').count()), key));
+            }
+
+            // other root relative urls point at this server which a static
+            // host won't have
+            let kept = html::safe_url(url).filter(|url| !url.starts_with('/'));
+
+            if kept.is_none() {
+                log::warn!("leaving out image {} from {}", url, document.slug);
+            }
+
+            kept.map(String::from)
+        });
+
+        let page = site::document_page(links, &repository, &site_documents, document, &rendered);
+        let destination = output_dir.join(format!("{}.html", path));
+
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(destination, page)?;
+
+        search_index.push(SearchEntry::new(SiteLinks::Static { depth: 0 }, &repository, path, document, &block_list));
+    }
+
+    for key in uploads {
+        let destination = output_dir.join("uploads").join(&key);
+
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match blob_store.get(&key) {
+            Ok(Some(data)) => fs::write(destination, data)?,
+            Ok(None) => log::warn!("image {} is missing from storage", key),
+            Err(err) => log::warn!("failed to copy image {}: {}", key, err),
+        }
+    }
+
+    fs::write(output_dir.join("index.html"), site::index_page(SiteLinks::Static { depth: 0 }, &repository, &site_documents))?;
+    fs::write(output_dir.join("search.html"), site::search_page(&repository, &site_documents))?;
+    fs::write(output_dir.join("search-index.json"), serde_json::to_string(&search_index)?)?;
+    fs::write(output_dir.join("search.js"), site::SEARCH_SCRIPT)?;
+    fs::write(output_dir.join("style.css"), site::STYLE)?;
+
+    log::info!("exported {} documents of {} to {}", document_list.len(), repository.slug, output_dir.display());
+
+    Ok(())
+}
@@ -1,18 +1,16 @@
-use std::sync::RwLock;
+use std::{io, sync::RwLock};
 
-use actix_web::{get, route, web, Error, HttpResponse, Responder, cookie::CookieJar, HttpRequest};
+use actix_multipart::Multipart;
+use actix_web::{get, post, route, web, Error, HttpResponse, HttpResponseBuilder, Responder, cookie::CookieJar, HttpRequest, http::header::{self, ContentDisposition, DispositionParam, DispositionType}, rt::task::spawn_blocking};
 use actix_web_lab::respond::Html;
+use futures_util::{stream, StreamExt};
 use juniper::http::{graphiql::graphiql_source, GraphQLRequest, playground::playground_source};
+use tokio::sync::mpsc;
+use zip::{ZipWriter, write::SimpleFileOptions};
 
-use crate::{db::DBPool, schemas::root::{Schema, Context, create_schema}};
+use crate::{jobs::ImageJobs, db::{DBPool, DBPooledConnection}, schemas::root::{Schema, Context, create_schema}, models::{user::{User, UserResult}, repository::{Repository, RepositoryOperation, RepositoryResult}, document::{self, DocumentOperation, DocumentResult}, block::BlockOperation, embed::{EmbedOperation, Reader}, upload::{UploadOperation, UploadError, MAX_UPLOAD_SIZE}}, helpers::{blob_store::BlobStore, mail::Mailer, auth::get_authed_user, permission::Permission, markdown, html, site::{self, SiteLinks, SiteDocuments}, zip_stream::ZipStream}};
 
-#[route("/graphql", method = "GET", method = "POST")]
-pub async fn graphql(
-    req: HttpRequest,
-    pool: web::Data<DBPool>,
-    schema: web::Data<Schema>,
-    data: web::Json<GraphQLRequest>,
-) -> Result<HttpResponse, Error> {
+fn request_cookie_jar(req: &HttpRequest) -> CookieJar {
     let mut jar = CookieJar::new();
 
     let cookies = req.cookies();
@@ -23,22 +21,432 @@ pub async fn graphql(
         }
     }
 
+    jar
+}
+
+fn set_cookies(response: &mut HttpResponseBuilder, jar: &CookieJar) {
+    for cookie in jar.delta() {
+        response.cookie(cookie.clone());
+    }
+}
+
+#[route("/graphql", method = "GET", method = "POST")]
+pub async fn graphql(
+    req: HttpRequest,
+    pool: web::Data<DBPool>,
+    blob_store: web::Data<dyn BlobStore>,
+    mailer: web::Data<dyn Mailer>,
+    schema: web::Data<Schema>,
+    data: web::Json<GraphQLRequest>,
+) -> Result<HttpResponse, Error> {
     let ctx = Context {
-        cookie_jar: RwLock::new(jar),
+        cookie_jar: RwLock::new(request_cookie_jar(&req)),
         db_pool: pool.get_ref().to_owned(),
+        blob_store: blob_store.into_inner(),
+        mailer: mailer.into_inner(),
     };
 
 
     let res = data.execute(&schema, &ctx).await;
     let mut http_response = HttpResponse::Ok();
 
-    let jar = ctx.cookie_jar.write().unwrap();
+    let jar = ctx.cookie_jar.read().unwrap();
+    set_cookies(&mut http_response, &jar);
 
-    for cookie in jar.delta() {
-        http_response.cookie(cookie.clone());
+    Ok(http_response.json(res))
+}
+
+/// Looks up a repository by slug for the signed in user, or the response
+/// to send back when it can't be read.
+fn readable_repository(conn: &mut DBPooledConnection, jar: &mut CookieJar, slug: &str) -> Result<(User, Repository), HttpResponse> {
+    let user = match get_authed_user(conn, jar) {
+        UserResult::User(user) => user,
+        _ => return Err(HttpResponse::Unauthorized().finish()),
+    };
+
+    match RepositoryOperation::find_by_slug(conn, slug) {
+        RepositoryResult::Repository(repository) if Permission::can_read_repository(conn, &user.id, &repository.id) =>
+            Ok((user, repository)),
+        RepositoryResult::GeneralError(_) | RepositoryResult::FieldErrors(_) | RepositoryResult::Repository(_) =>
+            Err(HttpResponse::NotFound().finish()),
     }
+}
 
-    Ok(http_response.json(res))
+#[get("/export/{repository}/{document}.md")]
+async fn export_document(req: HttpRequest, pool: web::Data<DBPool>, path: web::Path<(String, String)>) -> HttpResponse {
+    let (repository_slug, document_slug) = path.into_inner();
+
+    let mut conn = pool.get().unwrap();
+    let mut jar = request_cookie_jar(&req);
+
+    let (user, repository) = match readable_repository(&mut conn, &mut jar, &repository_slug) {
+        Ok(readable) => readable,
+        Err(response) => return response,
+    };
+
+    let document = match DocumentOperation::find_by_slug(&mut conn, &repository.id, &document_slug) {
+        DocumentResult::Document(document) => document,
+        _ => return HttpResponse::NotFound().finish(),
+    };
+
+    let Ok(markdown) = DocumentOperation::markdown(&mut conn, &document.id, Reader::User(&user.id)) else {
+        return HttpResponse::InternalServerError().finish();
+    };
+
+    let mut response = HttpResponse::Ok();
+    set_cookies(&mut response, &jar);
+
+    response
+        .content_type("text/markdown; charset=utf-8")
+        .body(markdown)
+}
+
+fn write_repository_zip(pool: &DBPool, user_id: &str, repository_id: &str, sink: ZipStream) -> zip::result::ZipResult<()> {
+    let mut conn = pool.get().map_err(io::Error::other)?;
+
+    let mut zip = ZipWriter::new(sink);
+    zip.set_flush_on_finish_file(true);
+
+    let document_list = DocumentOperation::find_by_repository(&mut conn, repository_id);
+    let paths = document::paths(&document_list);
+
+    // nested documents go in a folder named after the document they are
+    // nested under, next to its own file
+    for document in &document_list {
+        let markdown = DocumentOperation::markdown(&mut conn, &document.id, Reader::User(user_id))
+            .map_err(io::Error::other)?;
+
+        zip.start_file(format!("{}.md", paths[&document.id]), SimpleFileOptions::default())?;
+        io::Write::write_all(&mut zip, markdown::front_matter(document).as_bytes())?;
+        io::Write::write_all(&mut zip, markdown.as_bytes())?;
+    }
+
+    let mut sink = zip.finish()?;
+    io::Write::flush(&mut sink)?;
+
+    Ok(())
+}
+
+#[get("/export/{repository}.zip")]
+async fn export_repository(req: HttpRequest, pool: web::Data<DBPool>, path: web::Path<String>) -> HttpResponse {
+    let repository_slug = path.into_inner();
+
+    let mut conn = pool.get().unwrap();
+    let mut jar = request_cookie_jar(&req);
+
+    let (user, repository) = match readable_repository(&mut conn, &mut jar, &repository_slug) {
+        Ok(readable) => readable,
+        Err(response) => return response,
+    };
+
+    let (sender, receiver) = mpsc::channel(4);
+    let pool = pool.get_ref().to_owned();
+
+    spawn_blocking(move || {
+        let sink = ZipStream::new(sender.clone());
+
+        if let Err(err) = write_repository_zip(&pool, &user.id, &repository.id, sink) {
+            log::error!("failed to export repository {}: {}", repository.id, err);
+            let _ = sender.blocking_send(Err(io::Error::other(err)));
+        }
+    });
+
+    let body = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+
+    let mut response = HttpResponse::Ok();
+    set_cookies(&mut response, &jar);
+
+    response
+        .content_type("application/zip")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!("{}.zip", repository_slug))],
+        })
+        .streaming(body)
+}
+
+fn etag_matches(req: &HttpRequest, etag: &str) -> bool {
+    let Some(if_none_match) = req.headers().get(header::IF_NONE_MATCH) else {
+        return false;
+    };
+
+    if_none_match
+        .to_str()
+        .unwrap_or_default()
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == etag || tag == "*")
+}
+
+#[get("/render/{document}")]
+async fn render_document(req: HttpRequest, pool: web::Data<DBPool>, path: web::Path<String>) -> HttpResponse {
+    let document_id = path.into_inner();
+
+    let mut conn = pool.get().unwrap();
+    let mut jar = request_cookie_jar(&req);
+
+    let user = match get_authed_user(&mut conn, &mut jar) {
+        UserResult::User(user) => user,
+        _ => return HttpResponse::Unauthorized().finish(),
+    };
+
+    if !Permission::can_read_document(&mut conn, &user.id, &document_id) {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let document = match DocumentOperation::find(&mut conn, &document_id) {
+        DocumentResult::Document(document) => document,
+        _ => return HttpResponse::NotFound().finish(),
+    };
+
+    let Ok(mut block_list) = BlockOperation::find_by_document_with_content(&mut conn, &document.id) else {
+        return HttpResponse::InternalServerError().finish();
+    };
+
+    EmbedOperation::resolve_all(&mut conn, Reader::User(&user.id), &mut block_list);
+
+    let page = html::page(&document.name, &html::render(&block_list));
+    let etag = html::etag(&page);
+
+    let not_modified = etag_matches(&req, &etag);
+
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+
+    set_cookies(&mut response, &jar);
+
+    // Caches may keep the page but have to revalidate it with us, which
+    // checks permissions again and costs a 304 when nothing changed.
+    response
+        .insert_header((header::ETAG, etag.as_str()))
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .insert_header((header::VARY, "Cookie"));
+
+    if not_modified {
+        return response.finish();
+    }
+
+    response
+        .content_type("text/html; charset=utf-8")
+        .body(page)
+}
+
+/// Response for a page of a published site. Anyone may cache it but has to
+/// revalidate so unpublishing takes effect straight away.
+fn public_response(req: &HttpRequest, content_type: &str, body: String) -> HttpResponse {
+    let etag = html::etag(&body);
+
+    if etag_matches(req, &etag) {
+        return HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag.as_str()))
+            .insert_header((header::CACHE_CONTROL, "public, no-cache"))
+            .finish();
+    }
+
+    HttpResponse::Ok()
+        .insert_header((header::ETAG, etag.as_str()))
+        .insert_header((header::CACHE_CONTROL, "public, no-cache"))
+        .content_type(content_type)
+        .body(body)
+}
+
+#[get("/p/{owner}/{repository}")]
+async fn site_index(req: HttpRequest, pool: web::Data<DBPool>, path: web::Path<(String, String)>) -> HttpResponse {
+    let (owner_id, repository_slug) = path.into_inner();
+
+    let mut conn = pool.get().unwrap();
+
+    let Some(repository) = RepositoryOperation::find_published(&mut conn, &owner_id, &repository_slug) else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let document_list = DocumentOperation::find_by_repository(&mut conn, &repository.id);
+
+    public_response(&req, "text/html; charset=utf-8", site::index_page(SiteLinks::Live, &repository, &SiteDocuments::new(&document_list)))
+}
+
+#[get("/p/{owner}/{repository}/sitemap.xml")]
+async fn site_sitemap(req: HttpRequest, pool: web::Data<DBPool>, path: web::Path<(String, String)>) -> HttpResponse {
+    let (owner_id, repository_slug) = path.into_inner();
+
+    let mut conn = pool.get().unwrap();
+
+    let Some(repository) = RepositoryOperation::find_published(&mut conn, &owner_id, &repository_slug) else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let document_list = DocumentOperation::find_by_repository(&mut conn, &repository.id);
+
+    public_response(&req, "application/xml; charset=utf-8", site::sitemap(&repository, &SiteDocuments::new(&document_list)))
+}
+
+#[get("/p/{owner}/{repository}/feed.atom")]
+async fn site_feed(req: HttpRequest, pool: web::Data<DBPool>, path: web::Path<(String, String)>) -> HttpResponse {
+    let (owner_id, repository_slug) = path.into_inner();
+
+    let mut conn = pool.get().unwrap();
+
+    let Some(repository) = RepositoryOperation::find_published(&mut conn, &owner_id, &repository_slug) else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let document_list = DocumentOperation::find_by_repository(&mut conn, &repository.id);
+    let recently_updated = DocumentOperation::find_recently_updated(&mut conn, &repository.id, site::FEED_LENGTH);
+
+    public_response(&req, "application/atom+xml; charset=utf-8", site::feed(&repository, &SiteDocuments::new(&document_list), &recently_updated))
+}
+
+/// Documents are served at their path, the slugs of the documents they
+/// are nested under followed by their own. Old paths of moved documents
+/// redirect to where they are now.
+#[get("/p/{owner}/{repository}/{document:.+}")]
+async fn site_document(req: HttpRequest, pool: web::Data<DBPool>, path: web::Path<(String, String, String)>) -> HttpResponse {
+    let (owner_id, repository_slug, document_path) = path.into_inner();
+
+    let mut conn = pool.get().unwrap();
+
+    let Some(repository) = RepositoryOperation::find_published(&mut conn, &owner_id, &repository_slug) else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let document_slug = document_path.rsplit('/').next().unwrap_or_default();
+
+    let document = match DocumentOperation::find_by_slug(&mut conn, &repository.id, document_slug) {
+        DocumentResult::Document(document) => document,
+        _ => return HttpResponse::NotFound().finish(),
+    };
+
+    let document_list = DocumentOperation::find_by_repository(&mut conn, &repository.id);
+    let site_documents = SiteDocuments::new(&document_list);
+
+    if site_documents.path(&document) != document_path {
+        return HttpResponse::MovedPermanently()
+            .insert_header((header::LOCATION, SiteLinks::Live.document(&repository, site_documents.path(&document))))
+            .finish();
+    }
+
+    let Ok(mut block_list) = BlockOperation::find_by_document_with_content(&mut conn, &document.id) else {
+        return HttpResponse::InternalServerError().finish();
+    };
+
+    EmbedOperation::resolve_all(&mut conn, Reader::Site(&repository.id), &mut block_list);
+
+    let page = site::document_page(SiteLinks::Live, &repository, &site_documents, &document, &html::render(&block_list));
+
+    public_response(&req, "text/html; charset=utf-8", page)
+}
+
+/// Takes an image from the `file` field of a multipart form and stores it
+/// for the signed in user.
+#[post("/uploads")]
+async fn upload_image(req: HttpRequest, pool: web::Data<DBPool>, blob_store: web::Data<dyn BlobStore>, image_jobs: web::Data<ImageJobs>, mut payload: Multipart) -> HttpResponse {
+    let mut conn = pool.get().unwrap();
+    let mut jar = request_cookie_jar(&req);
+
+    let user = match get_authed_user(&mut conn, &mut jar) {
+        UserResult::User(user) => user,
+        _ => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let mut data = None;
+
+    while let Some(field) = payload.next().await {
+        let Ok(mut field) = field else {
+            return HttpResponse::BadRequest().body("malformed multipart body");
+        };
+
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let mut bytes = Vec::new();
+
+        while let Some(chunk) = field.next().await {
+            let Ok(chunk) = chunk else {
+                return HttpResponse::BadRequest().body("malformed multipart body");
+            };
+
+            // stop reading as soon as the limit is passed rather than
+            // buffering the whole body first
+            if bytes.len() + chunk.len() > MAX_UPLOAD_SIZE {
+                return HttpResponse::PayloadTooLarge().body(format!("file can't be more than {} bytes", MAX_UPLOAD_SIZE));
+            }
+
+            bytes.extend_from_slice(&chunk);
+        }
+
+        data = Some(bytes);
+        break;
+    }
+
+    let Some(data) = data else {
+        return HttpResponse::BadRequest().body("missing file field");
+    };
+
+    let result = web::block(move || UploadOperation::create(&mut conn, blob_store.as_ref(), &user.id, &data)).await;
+
+    let (mut response, message) = match result {
+        Ok(Ok(upload)) => {
+            image_jobs.notify();
+
+            let mut response = HttpResponse::Created();
+            set_cookies(&mut response, &jar);
+
+            return response.json(serde_json::json!({
+                "id": upload.id,
+                "url": upload.url(),
+                "contentType": upload.content_type,
+                "size": upload.size,
+                "ready": upload.ready(),
+            }));
+        },
+        Ok(Err(UploadError::TooLarge)) =>
+            (HttpResponse::PayloadTooLarge(), format!("file can't be more than {} bytes", MAX_UPLOAD_SIZE)),
+        Ok(Err(UploadError::QuotaExceeded)) =>
+            (HttpResponse::PayloadTooLarge(), "upload would exceed your storage quota".into()),
+        Ok(Err(UploadError::UnsupportedType)) =>
+            (HttpResponse::UnsupportedMediaType(), "only png, jpeg, gif and webp images can be uploaded".into()),
+        Ok(Err(err)) => {
+            log::error!("failed to store upload: {:?}", err);
+            (HttpResponse::InternalServerError(), String::new())
+        },
+        Err(_) => (HttpResponse::InternalServerError(), String::new()),
+    };
+
+    set_cookies(&mut response, &jar);
+    response.body(message)
+}
+
+#[get("/uploads/{key}")]
+async fn serve_upload(pool: web::Data<DBPool>, blob_store: web::Data<dyn BlobStore>, path: web::Path<String>) -> HttpResponse {
+    let key = path.into_inner();
+
+    let mut conn = pool.get().unwrap();
+
+    // only serve keys we handed out, and only once their metadata has
+    // been stripped
+    let Some(content_type) = UploadOperation::servable_content_type(&mut conn, &key) else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let data = match web::block(move || blob_store.get(&key)).await {
+        Ok(Ok(Some(data))) => data,
+        Ok(Ok(None)) => return HttpResponse::NotFound().finish(),
+        _ => return HttpResponse::InternalServerError().finish(),
+    };
+
+    // keys are never reused so the content at a url never changes
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((header::CACHE_CONTROL, "public, max-age=31536000, immutable"))
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header((header::CONTENT_SECURITY_POLICY, "default-src 'none'"))
+        .body(data)
 }
 
 #[get("/graphiql")]
@@ -55,6 +463,15 @@ pub fn register(config: &mut web::ServiceConfig) {
     config
         .app_data(web::Data::new(create_schema()))
         .service(graphql)
+        .service(export_document)
+        .service(export_repository)
+        .service(render_document)
+        .service(site_index)
+        .service(site_sitemap)
+        .service(site_feed)
+        .service(site_document)
+        .service(upload_image)
+        .service(serve_upload)
         .service(playground)
         .service(graphiql);
 }
@@ -40,7 +40,7 @@ pub fn decode_access_token(jwt: &str) -> Option<AccessTokenClaims> {
     let secret = std::env::var("JWT_SECRET")
         .expect("JWT_SECRET must be set");
 
-    let token = decode::<AccessTokenClaims>(&jwt, &DecodingKey::from_secret(secret.as_bytes()), &Validation::new(Algorithm::HS512));
+    let token = decode::<AccessTokenClaims>(jwt, &DecodingKey::from_secret(secret.as_bytes()), &Validation::new(Algorithm::HS512));
 
     match token {
         Ok(token) => Some(token.claims),
@@ -79,7 +79,7 @@ pub fn decode_refresh_token(jwt: &str) -> Option<RefreshTokenClaims> {
     let secret = std::env::var("JWT_SECRET")
         .expect("JWT_SECRET must be set");
 
-    let token = decode::<RefreshTokenClaims>(&jwt, &DecodingKey::from_secret(secret.as_bytes()), &Validation::new(Algorithm::HS512));
+    let token = decode::<RefreshTokenClaims>(jwt, &DecodingKey::from_secret(secret.as_bytes()), &Validation::new(Algorithm::HS512));
 
     match token {
         Ok(token) => Some(token.claims),
@@ -0,0 +1,231 @@
+use std::{env, fs, io::{self, Read}, path::{Component, Path, PathBuf}, sync::Arc};
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+/// Path blobs are served under by this server.
+pub const UPLOADS_PATH: &str = "/uploads/";
+
+/// Storage for uploaded files addressed by key. Calls block so they should
+/// be made off the async runtime.
+pub trait BlobStore: Send + Sync {
+    fn put(&self, key: &str, content_type: &str, data: &[u8]) -> io::Result<()>;
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+    fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+/// Server managed url for a blob.
+pub fn url(key: &str) -> String {
+    format!("{}{}", UPLOADS_PATH, key)
+}
+
+/// Key of a blob served by this server, `None` for other urls or for keys
+/// that would escape the storage directory.
+pub fn key_from_url(url: &str) -> Option<&str> {
+    let key = url.strip_prefix(UPLOADS_PATH)?;
+
+    let contained = !key.is_empty() && Path::new(key)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+
+    contained.then_some(key)
+}
+
+/// Picks the backend from `BLOB_STORE`, either `local` (the default) or
+/// `s3`.
+pub fn from_env() -> Arc<dyn BlobStore> {
+    match env::var("BLOB_STORE").as_deref() {
+        Ok("s3") => Arc::new(S3BlobStore::from_env()),
+        Ok("local") | Err(_) => Arc::new(LocalBlobStore::from_env()),
+        Ok(other) => panic!("unknown BLOB_STORE {}", other),
+    }
+}
+
+/// Keeps blobs as files in a directory.
+pub struct LocalBlobStore {
+    dir: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(dir: impl Into<PathBuf>) -> LocalBlobStore {
+        LocalBlobStore { dir: dir.into() }
+    }
+
+    pub fn from_env() -> LocalBlobStore {
+        LocalBlobStore::new(env::var("UPLOAD_DIR").unwrap_or_else(|_| "uploads".into()))
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        let url = url(key);
+
+        let key = key_from_url(&url)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid key"))?;
+
+        Ok(self.dir.join(key))
+    }
+}
+
+impl BlobStore for LocalBlobStore {
+    fn put(&self, key: &str, _content_type: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.path(key)?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, data)
+    }
+
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.path(key)?) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(key)?) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Keeps blobs in a bucket of an S3 compatible service such as MinIO.
+/// Requests use path style urls and are signed with signature version 4.
+pub struct S3BlobStore {
+    endpoint: String,
+    host: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent encodes everything but unreserved characters and `/`.
+fn uri_encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
+impl S3BlobStore {
+    pub fn new(endpoint: &str, bucket: &str, region: &str, access_key: &str, secret_key: &str) -> S3BlobStore {
+        let endpoint = endpoint.trim_end_matches('/');
+
+        let host = endpoint
+            .split_once("://")
+            .map_or(endpoint, |(_, rest)| rest)
+            .split('/')
+            .next()
+            .unwrap_or_default();
+
+        S3BlobStore {
+            endpoint: endpoint.into(),
+            host: host.into(),
+            bucket: bucket.into(),
+            region: region.into(),
+            access_key: access_key.into(),
+            secret_key: secret_key.into(),
+        }
+    }
+
+    pub fn from_env() -> S3BlobStore {
+        let var = |name: &str| env::var(name).unwrap_or_else(|_| panic!("{} must be defined", name));
+
+        S3BlobStore::new(
+            &var("S3_ENDPOINT"),
+            &var("S3_BUCKET"),
+            &env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".into()),
+            &var("S3_ACCESS_KEY"),
+            &var("S3_SECRET_KEY"),
+        )
+    }
+
+    fn request(&self, method: &str, key: &str, payload: &[u8]) -> ureq::Request {
+        let path = uri_encode_path(&format!("/{}/{}", self.bucket, key));
+        let payload_hash = sha256_hex(payload);
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, path, self.host, payload_hash, amz_date, signed_headers, payload_hash,
+        );
+
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date, scope, sha256_hex(canonical_request.as_bytes()),
+        );
+
+        let signing_key = ["s3", "aws4_request"].iter().fold(
+            hmac_sha256(&hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), &date), &self.region),
+            |key, part| hmac_sha256(&key, part),
+        );
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, hex::encode(hmac_sha256(&signing_key, &string_to_sign)),
+        );
+
+        ureq::request(method, &format!("{}{}", self.endpoint, path))
+            .set("x-amz-content-sha256", &payload_hash)
+            .set("x-amz-date", &amz_date)
+            .set("authorization", &authorization)
+    }
+}
+
+impl BlobStore for S3BlobStore {
+    fn put(&self, key: &str, content_type: &str, data: &[u8]) -> io::Result<()> {
+        self.request("PUT", key, data)
+            .set("content-type", content_type)
+            .send_bytes(data)
+            .map_err(io::Error::other)?;
+
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match self.request("GET", key, &[]).call() {
+            Ok(response) => {
+                let mut data = Vec::new();
+                response.into_reader().read_to_end(&mut data)?;
+                Ok(Some(data))
+            },
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        match self.request("DELETE", key, &[]).call() {
+            Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+}
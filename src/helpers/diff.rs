@@ -0,0 +1,213 @@
+use juniper::{GraphQLEnum, GraphQLObject};
+
+use crate::schemas::root::Context;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, GraphQLEnum)]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct TextSegment {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+/// Returns the index pairs of the longest common subsequence of `a` and `b`.
+/// Uses Hirschberg's algorithm so memory stays linear in the input, as
+/// text blocks can be long.
+pub fn lcs<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    lcs_into(a, b, 0, 0, &mut pairs);
+    pairs
+}
+
+fn lcs_into<T: PartialEq>(a: &[T], b: &[T], a_offset: usize, b_offset: usize, pairs: &mut Vec<(usize, usize)>) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+
+    pairs.extend((0..prefix).map(|k| (a_offset + k, b_offset + k)));
+
+    let (a_rest, b_rest) = (&a[prefix..], &b[prefix..]);
+    let suffix = a_rest.iter().rev().zip(b_rest.iter().rev()).take_while(|(x, y)| x == y).count();
+
+    let a_middle = &a_rest[..a_rest.len() - suffix];
+    let b_middle = &b_rest[..b_rest.len() - suffix];
+    let (a_offset, b_offset) = (a_offset + prefix, b_offset + prefix);
+
+    if a_middle.len() == 1 {
+        if let Some(j) = b_middle.iter().position(|y| *y == a_middle[0]) {
+            pairs.push((a_offset, b_offset + j));
+        }
+    } else if !a_middle.is_empty() && !b_middle.is_empty() {
+        let mid = a_middle.len() / 2;
+
+        let forward = lcs_lengths(a_middle[..mid].iter(), b_middle.iter());
+        let backward = lcs_lengths(a_middle[mid..].iter().rev(), b_middle.iter().rev());
+
+        let split = (0..=b_middle.len())
+            .max_by_key(|&j| (forward[j] + backward[b_middle.len() - j], std::cmp::Reverse(j)))
+            .unwrap_or_default();
+
+        lcs_into(&a_middle[..mid], &b_middle[..split], a_offset, b_offset, pairs);
+        lcs_into(&a_middle[mid..], &b_middle[split..], a_offset + mid, b_offset + split, pairs);
+    }
+
+    let a_suffix = a_offset + a_middle.len();
+    let b_suffix = b_offset + b_middle.len();
+
+    pairs.extend((0..suffix).map(|k| (a_suffix + k, b_suffix + k)));
+}
+
+/// Length of the longest common subsequence of all of `a` with each prefix
+/// of `b`, keeping a single row of the table.
+fn lcs_lengths<'a, T: PartialEq + 'a>(a: impl Iterator<Item = &'a T>, b: impl Iterator<Item = &'a T> + Clone) -> Vec<usize> {
+    let mut row = vec![0; b.clone().count() + 1];
+
+    for x in a {
+        let mut diagonal = 0;
+
+        for (j, y) in b.clone().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if x == y { diagonal + 1 } else { above.max(row[j]) };
+            diagonal = above;
+        }
+    }
+
+    row
+}
+
+/// Splits text into alternating runs of words and whitespace so that
+/// joining the tokens gives back the original text.
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut in_whitespace = None;
+
+    for (index, c) in text.char_indices() {
+        let whitespace = c.is_whitespace();
+
+        if in_whitespace.is_some_and(|prev| prev != whitespace) {
+            tokens.push(&text[start..index]);
+            start = index;
+        }
+
+        in_whitespace = Some(whitespace);
+    }
+
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+
+    tokens
+}
+
+fn push_segment(segments: &mut Vec<TextSegment>, op: DiffOp, text: &str) {
+    if let Some(last) = segments.last_mut() {
+        if last.op == op {
+            last.text.push_str(text);
+            return;
+        }
+    }
+
+    segments.push(TextSegment { op, text: text.into() });
+}
+
+/// Word level diff of two strings.
+pub fn diff_words(from: &str, to: &str) -> Vec<TextSegment> {
+    let from_tokens = tokenize(from);
+    let to_tokens = tokenize(to);
+
+    let mut segments = Vec::new();
+    let (mut i, mut j) = (0, 0);
+
+    for (match_i, match_j) in lcs(&from_tokens, &to_tokens) {
+        for token in &from_tokens[i..match_i] {
+            push_segment(&mut segments, DiffOp::Delete, token);
+        }
+
+        for token in &to_tokens[j..match_j] {
+            push_segment(&mut segments, DiffOp::Insert, token);
+        }
+
+        push_segment(&mut segments, DiffOp::Equal, from_tokens[match_i]);
+
+        i = match_i + 1;
+        j = match_j + 1;
+    }
+
+    for token in &from_tokens[i..] {
+        push_segment(&mut segments, DiffOp::Delete, token);
+    }
+
+    for token in &to_tokens[j..] {
+        push_segment(&mut segments, DiffOp::Insert, token);
+    }
+
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Length of the longest common subsequence from the full table.
+    fn lcs_length(a: &[char], b: &[char]) -> usize {
+        let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+        for i in 0..a.len() {
+            for j in 0..b.len() {
+                lengths[i + 1][j + 1] = if a[i] == b[j] {
+                    lengths[i][j] + 1
+                } else {
+                    lengths[i][j + 1].max(lengths[i + 1][j])
+                };
+            }
+        }
+
+        lengths[a.len()][b.len()]
+    }
+
+    #[test]
+    fn lcs_is_a_longest_common_subsequence() {
+        let cases = [
+            ("", ""),
+            ("abc", ""),
+            ("", "abc"),
+            ("abc", "abc"),
+            ("abcbdab", "bdcaba"),
+            ("xaxbxcx", "abc"),
+            ("the quick brown fox", "the slow brown dog"),
+            ("aaaa", "aa"),
+            ("abcdefgh", "hgfedcba"),
+        ];
+
+        for (a, b) in cases {
+            let a = a.chars().collect::<Vec<_>>();
+            let b = b.chars().collect::<Vec<_>>();
+            let pairs = lcs(&a, &b);
+
+            assert_eq!(pairs.len(), lcs_length(&a, &b), "{:?} {:?}", a, b);
+            assert!(pairs.iter().all(|&(i, j)| a[i] == b[j]));
+            assert!(pairs.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1));
+        }
+    }
+
+    #[test]
+    fn diff_words_gives_back_both_texts() {
+        let (from, to) = ("the quick brown fox jumps", "the slow brown fox  jumps high");
+        let segments = diff_words(from, to);
+
+        let text = |skip: DiffOp| segments
+            .iter()
+            .filter(|segment| segment.op != skip)
+            .map(|segment| segment.text.as_str())
+            .collect::<String>();
+
+        assert_eq!(text(DiffOp::Insert), from);
+        assert_eq!(text(DiffOp::Delete), to);
+        assert!(segments.iter().any(|segment| segment.op == DiffOp::Equal && segment.text.contains("brown fox")));
+    }
+}
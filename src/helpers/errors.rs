@@ -25,7 +25,7 @@ impl FieldError {
     }
 }
 
-#[derive(GraphQLObject)]
+#[derive(GraphQLObject, Default)]
 #[graphql(Context = Context)]
 pub struct FieldErrors {
     pub errors: Vec<FieldError>,
@@ -60,37 +60,38 @@ macro_rules! validation_result {
     ($name:ident, $for:ident) => {
         #[derive(juniper::GraphQLUnion)]
         #[graphql(context = Context)]
+        #[allow(clippy::large_enum_variant)]
         pub enum $name {
             $for($for),
-            FieldErrors(crate::helpers::errors::FieldErrors),
-            GeneralError(crate::helpers::errors::GeneralError),
+            FieldErrors($crate::helpers::errors::FieldErrors),
+            GeneralError($crate::helpers::errors::GeneralError),
         }
 
         impl $name {
             pub fn not_found(message: &str) -> $name {
-                $name::GeneralError(crate::helpers::errors::GeneralError {
-                    code: crate::helpers::errors::ErrorCode::NotFound,
+                $name::GeneralError($crate::helpers::errors::GeneralError {
+                    code: $crate::helpers::errors::ErrorCode::NotFound,
                     message: message.into()
                 })
             }
 
             pub fn unauthorized(message: &str) -> $name {
-                $name::GeneralError(crate::helpers::errors::GeneralError {
-                    code: crate::helpers::errors::ErrorCode::Unauthorized,
+                $name::GeneralError($crate::helpers::errors::GeneralError {
+                    code: $crate::helpers::errors::ErrorCode::Unauthorized,
                     message: message.into()
                 })
             }
 
             pub fn conflict(message: &str) -> $name {
-                $name::GeneralError(crate::helpers::errors::GeneralError {
-                    code: crate::helpers::errors::ErrorCode::Conflict,
+                $name::GeneralError($crate::helpers::errors::GeneralError {
+                    code: $crate::helpers::errors::ErrorCode::Conflict,
                     message: message.into()
                 })
             }
 
             pub fn server() -> $name {
-                $name::GeneralError(crate::helpers::errors::GeneralError {
-                    code: crate::helpers::errors::ErrorCode::ServerError,
+                $name::GeneralError($crate::helpers::errors::GeneralError {
+                    code: $crate::helpers::errors::ErrorCode::ServerError,
                     message: "something went wrong".into()
                 })
             }
@@ -0,0 +1,418 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+use juniper::GraphQLEnum;
+use sha2::{Digest, Sha256};
+
+use super::html::escape;
+
+/// Bumped whenever the output changes so stale cache entries aren't used.
+const HIGHLIGHTER_VERSION: &str = "1";
+const MAX_CACHED: usize = 2048;
+
+lazy_static::lazy_static! {
+    static ref CACHE: Mutex<HashMap<Vec<u8>, Arc<str>>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, GraphQLEnum)]
+pub enum HighlightTheme {
+    Light,
+    Dark,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TokenKind {
+    Plain,
+    Keyword,
+    Literal,
+    String,
+    Number,
+    Comment,
+}
+
+impl HighlightTheme {
+    fn background(self) -> &'static str {
+        match self {
+            HighlightTheme::Light => "#f6f8fa",
+            HighlightTheme::Dark => "#0d1117",
+        }
+    }
+
+    fn color(self, kind: TokenKind) -> &'static str {
+        match (self, kind) {
+            (HighlightTheme::Light, TokenKind::Plain) => "#24292f",
+            (HighlightTheme::Light, TokenKind::Keyword) => "#cf222e",
+            (HighlightTheme::Light, TokenKind::Literal | TokenKind::Number) => "#0550ae",
+            (HighlightTheme::Light, TokenKind::String) => "#0a3069",
+            (HighlightTheme::Light, TokenKind::Comment) => "#6e7781",
+            (HighlightTheme::Dark, TokenKind::Plain) => "#c9d1d9",
+            (HighlightTheme::Dark, TokenKind::Keyword) => "#ff7b72",
+            (HighlightTheme::Dark, TokenKind::Literal | TokenKind::Number) => "#79c0ff",
+            (HighlightTheme::Dark, TokenKind::String) => "#a5d6ff",
+            (HighlightTheme::Dark, TokenKind::Comment) => "#8b949e",
+        }
+    }
+}
+
+struct Language {
+    names: &'static [&'static str],
+    keywords: &'static [&'static str],
+    literals: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    /// Python's `"""` strings.
+    triple_quotes: bool,
+    /// `'` only delimits a single character, as in rust where it also
+    /// starts lifetimes.
+    char_quote: bool,
+    case_insensitive: bool,
+}
+
+const C_LIKE_COMMENTS: Option<(&str, &str)> = Some(("/*", "*/"));
+
+const JAVASCRIPT_KEYWORDS: &[&str] = &[
+    "async", "await", "break", "case", "catch", "class", "const", "continue", "debugger", "default", "delete", "do",
+    "else", "export", "extends", "finally", "for", "from", "function", "if", "import", "in", "instanceof", "let", "new",
+    "of", "return", "static", "super", "switch", "this", "throw", "try", "typeof", "var", "void", "while", "with", "yield",
+];
+
+const TYPESCRIPT_KEYWORDS: &[&str] = &[
+    "abstract", "any", "as", "async", "await", "boolean", "break", "case", "catch", "class", "const", "continue",
+    "declare", "default", "delete", "do", "else", "enum", "export", "extends", "finally", "for", "from", "function", "if",
+    "implements", "import", "in", "instanceof", "interface", "keyof", "let", "namespace", "never", "new", "number", "of",
+    "private", "protected", "public", "readonly", "return", "static", "string", "super", "switch", "this", "throw", "try",
+    "type", "typeof", "unknown", "var", "void", "while", "yield",
+];
+
+const C_KEYWORDS: &[&str] = &[
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum", "extern", "float",
+    "for", "goto", "if", "inline", "int", "long", "register", "restrict", "return", "short", "signed", "sizeof", "static",
+    "struct", "switch", "typedef", "union", "unsigned", "void", "volatile", "while",
+];
+
+const CPP_KEYWORDS: &[&str] = &[
+    "auto", "bool", "break", "case", "catch", "char", "class", "const", "constexpr", "continue", "default", "delete",
+    "do", "double", "else", "enum", "explicit", "extern", "float", "for", "friend", "if", "inline", "int", "long",
+    "namespace", "new", "noexcept", "operator", "override", "private", "protected", "public", "return", "short",
+    "signed", "sizeof", "static", "struct", "switch", "template", "this", "throw", "try", "typedef", "typename", "union",
+    "unsigned", "using", "virtual", "void", "volatile", "while",
+];
+
+const LANGUAGES: &[Language] = &[
+    Language {
+        names: &["rust", "rs"],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "fn", "for",
+            "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self",
+            "static", "struct", "super", "trait", "type", "unsafe", "use", "where", "while",
+        ],
+        literals: &["true", "false"],
+        line_comments: &["//"],
+        block_comment: C_LIKE_COMMENTS,
+        quotes: &['"', '\''],
+        triple_quotes: false,
+        char_quote: true,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["javascript", "js", "jsx", "mjs"],
+        keywords: JAVASCRIPT_KEYWORDS,
+        literals: &["true", "false", "null", "undefined", "NaN", "Infinity"],
+        line_comments: &["//"],
+        block_comment: C_LIKE_COMMENTS,
+        quotes: &['"', '\'', '`'],
+        triple_quotes: false,
+        char_quote: false,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["typescript", "ts", "tsx"],
+        keywords: TYPESCRIPT_KEYWORDS,
+        literals: &["true", "false", "null", "undefined", "NaN", "Infinity"],
+        line_comments: &["//"],
+        block_comment: C_LIKE_COMMENTS,
+        quotes: &['"', '\'', '`'],
+        triple_quotes: false,
+        char_quote: false,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["python", "py"],
+        keywords: &[
+            "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else", "except",
+            "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass",
+            "raise", "return", "try", "while", "with", "yield",
+        ],
+        literals: &["True", "False", "None"],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        triple_quotes: true,
+        char_quote: false,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["go", "golang"],
+        keywords: &[
+            "break", "case", "chan", "const", "continue", "default", "defer", "else", "fallthrough", "for", "func", "go",
+            "goto", "if", "import", "interface", "map", "package", "range", "return", "select", "struct", "switch", "type",
+            "var",
+        ],
+        literals: &["true", "false", "nil", "iota"],
+        line_comments: &["//"],
+        block_comment: C_LIKE_COMMENTS,
+        quotes: &['"', '\'', '`'],
+        triple_quotes: false,
+        char_quote: false,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["java"],
+        keywords: &[
+            "abstract", "boolean", "break", "byte", "case", "catch", "char", "class", "continue", "default", "do",
+            "double", "else", "enum", "extends", "final", "finally", "float", "for", "if", "implements", "import",
+            "instanceof", "int", "interface", "long", "new", "package", "private", "protected", "public", "record",
+            "return", "short", "static", "super", "switch", "synchronized", "this", "throw", "throws", "try", "var",
+            "void", "while",
+        ],
+        literals: &["true", "false", "null"],
+        line_comments: &["//"],
+        block_comment: C_LIKE_COMMENTS,
+        quotes: &['"', '\''],
+        triple_quotes: false,
+        char_quote: false,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["c", "h"],
+        keywords: C_KEYWORDS,
+        literals: &["NULL", "true", "false"],
+        line_comments: &["//"],
+        block_comment: C_LIKE_COMMENTS,
+        quotes: &['"', '\''],
+        triple_quotes: false,
+        char_quote: false,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["cpp", "c++", "cc", "hpp"],
+        keywords: CPP_KEYWORDS,
+        literals: &["true", "false", "nullptr", "NULL"],
+        line_comments: &["//"],
+        block_comment: C_LIKE_COMMENTS,
+        quotes: &['"', '\''],
+        triple_quotes: false,
+        char_quote: false,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["sql", "postgresql", "psql"],
+        keywords: &[
+            "add", "alter", "and", "as", "asc", "begin", "between", "by", "case", "check", "commit", "constraint", "create",
+            "default", "delete", "desc", "distinct", "drop", "else", "end", "exists", "foreign", "from", "full", "group",
+            "having", "in", "index", "inner", "insert", "into", "is", "join", "key", "left", "like", "limit", "not", "offset",
+            "on", "or", "order", "outer", "primary", "references", "returning", "right", "rollback", "select", "set",
+            "table", "then", "union", "unique", "update", "using", "values", "when", "where", "with",
+        ],
+        literals: &["null", "true", "false"],
+        line_comments: &["--"],
+        block_comment: C_LIKE_COMMENTS,
+        quotes: &['\''],
+        triple_quotes: false,
+        char_quote: false,
+        case_insensitive: true,
+    },
+    Language {
+        names: &["bash", "sh", "shell", "zsh"],
+        keywords: &[
+            "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if", "in", "local", "readonly",
+            "return", "select", "then", "until", "while",
+        ],
+        literals: &["true", "false"],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        triple_quotes: false,
+        char_quote: false,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["json"],
+        keywords: &[],
+        literals: &["true", "false", "null"],
+        line_comments: &[],
+        block_comment: None,
+        quotes: &['"'],
+        triple_quotes: false,
+        char_quote: false,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["css"],
+        keywords: &["important"],
+        literals: &[],
+        line_comments: &[],
+        block_comment: C_LIKE_COMMENTS,
+        quotes: &['"', '\''],
+        triple_quotes: false,
+        char_quote: false,
+        case_insensitive: false,
+    },
+];
+
+fn language(name: &str) -> Option<&'static Language> {
+    LANGUAGES
+        .iter()
+        .find(|language| language.names.iter().any(|known| known.eq_ignore_ascii_case(name)))
+}
+
+impl Language {
+    fn word_kind(&self, word: &str) -> TokenKind {
+        let is = |list: &[&str]| match self.case_insensitive {
+            true => list.iter().any(|known| known.eq_ignore_ascii_case(word)),
+            false => list.contains(&word),
+        };
+
+        if is(self.keywords) {
+            TokenKind::Keyword
+        } else if is(self.literals) {
+            TokenKind::Literal
+        } else {
+            TokenKind::Plain
+        }
+    }
+
+    /// Length in bytes of the string starting at the start of `rest`, up to
+    /// the end of the line when it isn't closed.
+    fn string_length(&self, rest: &str, quote: char) -> Option<usize> {
+        let triple = quote.to_string().repeat(3);
+
+        if self.triple_quotes && rest.starts_with(&triple) {
+            let end = rest[3..].find(&triple).map_or(rest.len(), |end| end + 6);
+            return Some(end);
+        }
+
+        let mut escaped = false;
+
+        for (index, c) in rest.char_indices().skip(1) {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                c if c == quote => return Some(index + c.len_utf8()),
+                '\n' if quote != '`' => return Some(index),
+                _ if self.char_quote && quote == '\'' && index > 2 => return None,
+                _ => {},
+            }
+        }
+
+        match self.char_quote && quote == '\'' {
+            true => None,
+            false => Some(rest.len()),
+        }
+    }
+
+    fn tokens<'a>(&self, code: &'a str) -> Vec<(TokenKind, &'a str)> {
+        let mut tokens: Vec<(TokenKind, &str)> = Vec::new();
+        let mut position = 0;
+
+        while position < code.len() {
+            let rest = &code[position..];
+            let c = rest.chars().next().unwrap();
+
+            let comment = self.line_comments
+                .iter()
+                .find(|marker| rest.starts_with(**marker))
+                .map(|_| rest.find('\n').unwrap_or(rest.len()))
+                .or_else(|| {
+                    let (open, close) = self.block_comment?;
+                    rest.starts_with(open).then(|| rest[open.len()..].find(close).map_or(rest.len(), |end| end + open.len() + close.len()))
+                });
+
+            let (kind, length) = if let Some(length) = comment {
+                (TokenKind::Comment, length)
+            } else if let Some(length) = self.quotes.contains(&c).then(|| self.string_length(rest, c)).flatten() {
+                (TokenKind::String, length)
+            } else if c.is_ascii_digit() {
+                let length = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.')).unwrap_or(rest.len());
+                (TokenKind::Number, length)
+            } else if c.is_alphabetic() || c == '_' || c == '$' {
+                let length = rest.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$')).unwrap_or(rest.len());
+                (self.word_kind(&rest[..length]), length)
+            } else {
+                (TokenKind::Plain, c.len_utf8())
+            };
+
+            match tokens.last_mut() {
+                Some((TokenKind::Plain, text)) if kind == TokenKind::Plain => *text = &code[position - text.len()..position + length],
+                _ => tokens.push((kind, &rest[..length])),
+            }
+
+            position += length;
+        }
+
+        tokens
+    }
+}
+
+fn render(language: Option<&str>, code: &str, theme: HighlightTheme) -> String {
+    let class = language
+        .map(|language| format!(" class=\"language-{}\"", escape(language)))
+        .unwrap_or_default();
+
+    let body = match language.and_then(self::language) {
+        Some(language) => language
+            .tokens(code)
+            .into_iter()
+            .map(|(kind, text)| match kind {
+                TokenKind::Plain => escape(text),
+                kind => format!("<span style=\"color:{}\">{}</span>", theme.color(kind), escape(text)),
+            })
+            .collect(),
+        None => escape(code),
+    };
+
+    format!(
+        "<pre style=\"background:{};color:{}\"><code{}>{}</code></pre>",
+        theme.background(),
+        theme.color(TokenKind::Plain),
+        class,
+        body,
+    )
+}
+
+/// Code as a `<pre>` block colored with inline styles, so it needs no
+/// stylesheet or script. Languages that aren't known are only escaped.
+/// Results are cached by a hash of their input.
+pub fn highlight(language: Option<&str>, code: &str, theme: HighlightTheme) -> Arc<str> {
+    // the same language in another casing, or an empty one, renders and
+    // caches the same
+    let language = language.filter(|language| !language.is_empty()).map(str::to_lowercase);
+    let language = language.as_deref();
+
+    let key = Sha256::new()
+        .chain_update(HIGHLIGHTER_VERSION)
+        .chain_update([0])
+        .chain_update(language.unwrap_or_default())
+        .chain_update([0])
+        .chain_update(format!("{:?}", theme))
+        .chain_update([0])
+        .chain_update(code)
+        .finalize()
+        .to_vec();
+
+    if let Some(html) = CACHE.lock().unwrap().get(&key) {
+        return html.clone();
+    }
+
+    let html: Arc<str> = render(language, code, theme).into();
+    let mut cache = CACHE.lock().unwrap();
+
+    // cheaper than tracking use, the cache fills up again with what is
+    // being read
+    if cache.len() >= MAX_CACHED {
+        cache.clear();
+    }
+
+    cache.insert(key, html.clone());
+    html
+}
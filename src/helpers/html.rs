@@ -0,0 +1,428 @@
+use sha2::{Digest, Sha256};
+
+use crate::models::{block::{Block, BlockContent, BlockTree, ListStyle, Tag}, rich_text::{self, TextRun}, table::{ColumnType, TableColumn, TableRow}};
+
+use super::{slug::slugify, highlight::{highlight, HighlightTheme}, math};
+
+pub struct Heading {
+    pub level: u8,
+    pub id: String,
+    pub text: String,
+}
+
+pub struct RenderedHtml {
+    pub headings: Vec<Heading>,
+    pub body: String,
+}
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Only lets through http(s) and root relative urls so stored urls can't
+/// run scripts through `javascript:` and friends.
+pub fn safe_url(url: &str) -> Option<&str> {
+    let url = url.trim();
+    let lowercase = url.to_lowercase();
+
+    let allowed = lowercase.starts_with("https://")
+        || lowercase.starts_with("http://")
+        || (url.starts_with('/') && !url.starts_with("//"));
+
+    allowed.then_some(url)
+}
+
+/// Strong ETag for a rendered response.
+pub fn etag(content: &str) -> String {
+    format!("\"{:x}\"", Sha256::digest(content.as_bytes()))
+}
+
+/// Renders blocks to semantic html. Headings get ids so the table of
+/// contents can link to them.
+pub fn render(blocks: &[(Block, Option<BlockContent>)]) -> RenderedHtml {
+    render_with(blocks, |url| safe_url(url).map(String::from))
+}
+
+/// Inline html for the runs of a text block. Links are checked again in
+/// case the stored json was written around the API.
+fn inline(runs: &[TextRun]) -> String {
+    let mut html = String::new();
+
+    for run in runs {
+        let mut text = match run.math {
+            true => math::to_mathml(&run.text, false).unwrap_or_else(|_| format!("<code>{}</code>", escape(&run.text))),
+            false => escape(&run.text),
+        };
+
+        for (set, element) in [(run.code, "code"), (run.italic, "em"), (run.bold, "strong"), (run.strike, "s")] {
+            if set {
+                text = format!("<{}>{}</{}>", element, text, element);
+            }
+        }
+
+        if let Some(link) = run.link.as_deref().and_then(rich_text::safe_link) {
+            text = format!("<a href=\"{}\">{}</a>", escape(&link), text);
+        }
+
+        html.push_str(&text);
+    }
+
+    html
+}
+
+/// Paragraphs of multi paragraph text, which are separated by blank lines.
+fn paragraphs(text: &str) -> String {
+    text.split("\n\n")
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| format!("<p>{}</p>\n", escape(paragraph)))
+        .collect()
+}
+
+/// Opens an item of a `list` at `indent`, closing or opening lists to get
+/// there. Items can only be one level deeper than the item before them.
+fn open_list_item(body: &mut String, lists: &mut Vec<&'static str>, list: &'static str, indent: i32, attributes: &str) {
+    let depth = (indent.max(0) as usize).min(lists.len());
+
+    while lists.len() > depth + 1 {
+        body.push_str(&format!("</li></{}>\n", lists.pop().unwrap()));
+    }
+
+    if lists.len() == depth + 1 {
+        if lists.last() == Some(&list) {
+            body.push_str("</li>\n");
+        } else {
+            body.push_str(&format!("</li></{}>\n", lists.pop().unwrap()));
+        }
+    }
+
+    if lists.len() == depth {
+        body.push_str(&format!("<{}>\n", list));
+        lists.push(list);
+    }
+
+    body.push_str(&format!("<li{}>", attributes));
+}
+
+/// Table with the column names as its header row.
+fn table(columns: &[TableColumn], rows: &[TableRow]) -> String {
+    let mut html = String::from("<table>\n<thead><tr>");
+
+    for column in columns {
+        html.push_str(&format!("<th scope=\"col\">{}</th>", escape(&column.name)));
+    }
+
+    html.push_str("</tr></thead>\n<tbody>\n");
+
+    for row in rows {
+        html.push_str("<tr>");
+
+        for column in columns {
+            let value = row.value(&column.id);
+
+            html.push_str(&match (column.column_type, value) {
+                (ColumnType::Checkbox, value) => format!(
+                    "<td><input type=\"checkbox\" disabled{}></td>",
+                    if value == Some("true") { " checked" } else { "" },
+                ),
+                (_, None) => String::from("<td></td>"),
+                (ColumnType::Number, Some(value)) => format!("<td class=\"number\">{}</td>", escape(value)),
+                (ColumnType::Date, Some(value)) => format!("<td><time datetime=\"{}\">{}</time></td>", escape(value), escape(value)),
+                (_, Some(value)) => format!("<td>{}</td>", escape(value)),
+            });
+        }
+
+        html.push_str("</tr>\n");
+    }
+
+    html.push_str("</tbody>\n</table>\n");
+    html
+}
+
+fn close_lists(body: &mut String, lists: &mut Vec<&'static str>) {
+    while let Some(list) = lists.pop() {
+        body.push_str(&format!("</li></{}>\n", list));
+    }
+}
+
+/// Same as `render` but image urls go through `image_url`, images it
+/// returns `None` for are left out.
+pub fn render_with(blocks: &[(Block, Option<BlockContent>)], mut image_url: impl FnMut(&str) -> Option<String>) -> RenderedHtml {
+    let mut renderer = Renderer { headings: Vec::new(), image_url: &mut image_url };
+    let body = renderer.blocks(&BlockTree::new(blocks), None);
+
+    RenderedHtml { headings: renderer.headings, body }
+}
+
+struct Renderer<'a> {
+    headings: Vec<Heading>,
+    image_url: &'a mut dyn FnMut(&str) -> Option<String>,
+}
+
+impl Renderer<'_> {
+    /// Blocks nested under a list item or to-do go inside its `<li>`.
+    fn nested<'b>(&mut self, tree: &BlockTree<'b>, parent_id: &'b str) -> String {
+        match self.blocks(tree, Some(parent_id)) {
+            nested if nested.is_empty() => nested,
+            nested => format!("\n{}", nested),
+        }
+    }
+
+    /// Renders the blocks with the given parent, the top level blocks for
+    /// `None`.
+    fn blocks<'b>(&mut self, tree: &BlockTree<'b>, parent_id: Option<&'b str>) -> String {
+        let mut body = String::new();
+        let mut lists: Vec<&'static str> = Vec::new();
+
+        for (block, content) in tree.children(parent_id).iter().copied() {
+            let Some(content) = content else {
+                continue;
+            };
+
+            let html = match content {
+                // consecutive list items and to-dos are gathered into lists
+                BlockContent::ListItemBlock(list_item_block) => {
+                    let list = match list_item_block.style {
+                        ListStyle::Bulleted => "ul",
+                        ListStyle::Numbered => "ol",
+                    };
+
+                    open_list_item(&mut body, &mut lists, list, list_item_block.indent, "");
+                    body.push_str(&escape(list_item_block.content.as_deref().unwrap_or_default()));
+                    body.push_str(&self.nested(tree, &block.id));
+                    continue;
+                },
+                BlockContent::TodoBlock(todo_block) => {
+                    let checked = if todo_block.checked { " checked" } else { "" };
+
+                    open_list_item(&mut body, &mut lists, "ul", todo_block.indent, " class=\"todo\"");
+                    body.push_str(&format!(
+                        "<input type=\"checkbox\" disabled{}> {}",
+                        checked,
+                        escape(todo_block.content.as_deref().unwrap_or_default()),
+                    ));
+                    body.push_str(&self.nested(tree, &block.id));
+                    continue;
+                },
+                BlockContent::TextBlock(text_block) => {
+                    let text = text_block.content.as_deref().unwrap_or_default();
+
+                    if text.trim().is_empty() {
+                        continue;
+                    }
+
+                    let level = match text_block.tag {
+                        Tag::H1 => 1,
+                        Tag::H2 => 2,
+                        Tag::H3 => 3,
+                        Tag::P => {
+                            close_lists(&mut body, &mut lists);
+                            body.push_str(&format!("<p>{}</p>\n", inline(&text_block.runs())));
+                            continue;
+                        },
+                    };
+
+                    let base = slugify(text);
+
+                    let id = (1..)
+                        .map(|n| if n == 1 { base.clone() } else { format!("{}-{}", base, n) })
+                        .find(|id| !self.headings.iter().any(|heading| &heading.id == id))
+                        .unwrap();
+
+                    let html = format!("<h{} id=\"{}\">{}</h{}>\n", level, escape(&id), inline(&text_block.runs()), level);
+
+                    self.headings.push(Heading { level, id, text: text.into() });
+
+                    html
+                },
+                BlockContent::ImageBlock(image_block) => {
+                    let Some(url) = image_block.url.as_deref().and_then(&mut self.image_url) else {
+                        continue;
+                    };
+
+                    let mut attributes = format!("src=\"{}\"", escape(&url));
+
+                    let srcset = image_block.srcset
+                        .as_deref()
+                        .unwrap_or_default()
+                        .split(", ")
+                        .filter_map(|candidate| {
+                            let (url, descriptor) = candidate.split_once(' ')?;
+                            Some(format!("{} {}", (self.image_url)(url)?, descriptor))
+                        })
+                        .collect::<Vec<_>>();
+
+                    if !srcset.is_empty() {
+                        attributes.push_str(&format!(" srcset=\"{}\" sizes=\"(max-width: 48rem) 100vw, 48rem\"", escape(&srcset.join(", "))));
+                    }
+
+                    if let (Some(width), Some(height)) = (image_block.width, image_block.height) {
+                        attributes.push_str(&format!(" width=\"{}\" height=\"{}\"", width, height));
+                    }
+
+                    // images without alt text are treated as decorative
+                    attributes.push_str(&format!(" alt=\"{}\"", escape(image_block.alt.as_deref().unwrap_or_default())));
+
+                    let caption = image_block.caption
+                        .as_deref()
+                        .map(|caption| format!("<figcaption>{}</figcaption>", escape(caption)))
+                        .unwrap_or_default();
+
+                    format!("<figure><img {} loading=\"lazy\">{}</figure>\n", attributes, caption)
+                },
+                BlockContent::QuoteBlock(quote_block) => {
+                    let paragraphs = paragraphs(quote_block.content.as_deref().unwrap_or_default());
+
+                    if paragraphs.is_empty() {
+                        continue;
+                    }
+
+                    format!("<blockquote>\n{}</blockquote>\n", paragraphs)
+                },
+                BlockContent::CodeBlock(code_block) => {
+                    let code = code_block.content.as_deref().unwrap_or_default();
+
+                    if code.is_empty() {
+                        continue;
+                    }
+
+                    format!("{}\n", highlight(code_block.language.as_deref(), code, HighlightTheme::Light))
+                },
+                BlockContent::MathBlock(math_block) => {
+                    let latex = math_block.content.as_deref().unwrap_or_default();
+
+                    if latex.trim().is_empty() {
+                        continue;
+                    }
+
+                    // invalid LaTeX can only be stored around the API, it
+                    // is shown as source rather than half rendered
+                    match math::to_mathml(latex, true) {
+                        Ok(mathml) => format!("{}\n", mathml),
+                        Err(_) => format!("<pre><code class=\"language-math\">{}</code></pre>\n", escape(latex)),
+                    }
+                },
+                // embeds are swapped for the content they show before
+                // rendering, the ones left can't be shown to the reader
+                BlockContent::EmbedBlock(_) => String::from("<p class=\"embed-unavailable\">Embedded block isn't available</p>\n"),
+                BlockContent::CalloutBlock(callout_block) => {
+                    let name = callout_block.variant.name().to_lowercase();
+
+                    format!(
+                        "<aside class=\"callout callout-{}\" role=\"note\">\n<p class=\"callout-title\">{}{}</p>\n{}</aside>\n",
+                        name,
+                        name[..1].to_uppercase(),
+                        &name[1..],
+                        paragraphs(callout_block.content.as_deref().unwrap_or_default()),
+                    )
+                },
+                // closed until opened, so the nested blocks start out hidden
+                BlockContent::ToggleBlock(toggle_block) => format!(
+                    "<details>\n<summary>{}</summary>\n{}</details>\n",
+                    escape(toggle_block.content.as_deref().unwrap_or_default()),
+                    self.blocks(tree, Some(&block.id)),
+                ),
+                BlockContent::TableBlock(table_block) => {
+                    let columns = table_block.column_list();
+
+                    if columns.is_empty() {
+                        continue;
+                    }
+
+                    table(&columns, &table_block.row_list())
+                },
+                BlockContent::DividerBlock(_) => String::from("<hr>\n"),
+            };
+
+            close_lists(&mut body, &mut lists);
+            body.push_str(&html);
+        }
+
+        close_lists(&mut body, &mut lists);
+
+        body
+    }
+}
+
+impl RenderedHtml {
+    /// Nested list of links to the headings, empty when there are none.
+    pub fn toc(&self) -> String {
+        if self.headings.is_empty() {
+            return String::new();
+        }
+
+        let mut toc = String::from("<nav class=\"toc\">");
+        let mut levels: Vec<u8> = Vec::new();
+
+        for heading in &self.headings {
+            match levels.last() {
+                Some(&top) if heading.level <= top => {
+                    toc.push_str("</li>");
+
+                    while levels.len() > 1 && heading.level <= levels[levels.len() - 2] {
+                        levels.pop();
+                        toc.push_str("</ol></li>");
+                    }
+
+                    *levels.last_mut().unwrap() = heading.level;
+                    toc.push_str("<li>");
+                },
+                _ => {
+                    levels.push(heading.level);
+                    toc.push_str("<ol><li>");
+                },
+            }
+
+            toc.push_str(&format!("<a href=\"#{}\">{}</a>", escape(&heading.id), escape(&heading.text)));
+        }
+
+        toc.push_str("</li>");
+
+        for depth in (0..levels.len()).rev() {
+            toc.push_str("</ol>");
+
+            if depth > 0 {
+                toc.push_str("</li>");
+            }
+        }
+
+        toc.push_str("</nav>\n");
+        toc
+    }
+}
+
+/// Wraps rendered blocks in a standalone html page.
+pub fn page(title: &str, rendered: &RenderedHtml) -> String {
+    format!(
+        concat!(
+            "<!DOCTYPE html>\n",
+            "<html lang=\"en\">\n",
+            "<head>\n",
+            "<meta charset=\"utf-8\">\n",
+            "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n",
+            "<title>{}</title>\n",
+            "</head>\n",
+            "<body>\n",
+            "{}",
+            "<article>\n{}</article>\n",
+            "</body>\n",
+            "</html>\n",
+        ),
+        escape(title),
+        rendered.toc(),
+        rendered.body,
+    )
+}
@@ -0,0 +1,124 @@
+use std::io::Cursor;
+
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageDecoder, ImageError, ImageReader, Limits};
+
+pub struct ImageFormat {
+    pub name: &'static str,
+    pub content_type: &'static str,
+    pub extension: &'static str,
+}
+
+pub const PNG: ImageFormat = ImageFormat { name: "png", content_type: "image/png", extension: "png" };
+pub const JPEG: ImageFormat = ImageFormat { name: "jpeg", content_type: "image/jpeg", extension: "jpg" };
+pub const GIF: ImageFormat = ImageFormat { name: "gif", content_type: "image/gif", extension: "gif" };
+pub const WEBP: ImageFormat = ImageFormat { name: "webp", content_type: "image/webp", extension: "webp" };
+
+/// Widths of the responsive variants, only ones narrower than the image
+/// itself are made.
+pub const VARIANT_WIDTHS: [u32; 3] = [320, 800, 1600];
+
+const MAX_DIMENSION: u32 = 12_000;
+const MAX_DECODE_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Works out the format from the leading bytes of the file rather than
+/// trusting the content type the client sent. Formats that can carry
+/// scripts, like svg, are never recognised.
+pub fn sniff(data: &[u8]) -> Option<ImageFormat> {
+    match data {
+        [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => Some(PNG),
+        [0xff, 0xd8, 0xff, ..] => Some(JPEG),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(GIF),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(WEBP),
+        _ => None,
+    }
+}
+
+pub struct Variant {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+pub struct ProcessedImage {
+    pub width: u32,
+    pub height: u32,
+    /// The original re-encoded without metadata, `None` when the original
+    /// bytes can be kept.
+    pub original: Option<Vec<u8>>,
+    /// WebP variants, narrowest first.
+    pub variants: Vec<Variant>,
+}
+
+fn decode(data: &[u8]) -> Result<DynamicImage, ImageError> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_BYTES);
+
+    let mut reader = ImageReader::new(Cursor::new(data)).with_guessed_format()?;
+    reader.limits(limits);
+
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+
+    // The orientation lives in the EXIF data that is about to be dropped,
+    // so it has to be applied to the pixels.
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    Ok(image)
+}
+
+fn encode_webp(image: &DynamicImage, quality: f32) -> Vec<u8> {
+    let rgba = image.to_rgba8();
+    webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height()).encode(quality).to_vec()
+}
+
+/// Decodes an upload, strips its metadata by re-encoding it and makes the
+/// responsive variants. Gifs are kept as they are so animations survive,
+/// the format has no EXIF.
+pub fn process(data: &[u8], format: &ImageFormat) -> Result<ProcessedImage, ImageError> {
+    let image = decode(data)?;
+
+    let original = match format.name {
+        "jpeg" => {
+            let mut encoded = Vec::new();
+            JpegEncoder::new_with_quality(&mut encoded, 90).encode_image(&image.to_rgb8())?;
+            Some(encoded)
+        },
+        "png" => {
+            let mut encoded = Cursor::new(Vec::new());
+            image.write_to(&mut encoded, image::ImageFormat::Png)?;
+            Some(encoded.into_inner())
+        },
+        "webp" => Some(encode_webp(&image, 90.0)),
+        _ => None,
+    };
+
+    let variants = VARIANT_WIDTHS
+        .iter()
+        .filter(|&&width| width < image.width())
+        .map(|&width| {
+            let resized = image.resize(width, MAX_DIMENSION, FilterType::CatmullRom);
+
+            Variant {
+                width: resized.width(),
+                height: resized.height(),
+                data: encode_webp(&resized, 80.0),
+            }
+        })
+        .collect();
+
+    Ok(ProcessedImage {
+        width: image.width(),
+        height: image.height(),
+        original,
+        variants,
+    })
+}
+
+pub fn format_from_content_type(content_type: &str) -> Option<ImageFormat> {
+    [PNG, JPEG, GIF, WEBP]
+        .into_iter()
+        .find(|format| format.content_type == content_type)
+}
@@ -0,0 +1,88 @@
+use std::{env, io, sync::Arc};
+
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct Mail<'a> {
+    pub to: &'a str,
+    pub subject: &'a str,
+    pub text: &'a str,
+}
+
+/// Sends mail to users. Calls block so they should be made off the async
+/// runtime.
+pub trait Mailer: Send + Sync {
+    fn send(&self, mail: &Mail) -> io::Result<()>;
+}
+
+/// Picks the transport from `MAIL_TRANSPORT`, either `log` (the default)
+/// or `http`.
+pub fn from_env() -> Arc<dyn Mailer> {
+    match env::var("MAIL_TRANSPORT").as_deref() {
+        Ok("http") => Arc::new(HttpMailer::from_env()),
+        Ok("log") | Err(_) => Arc::new(LogMailer),
+        Ok(other) => panic!("unknown MAIL_TRANSPORT {}", other),
+    }
+}
+
+/// Writes mail to the log instead of sending it, for development.
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send(&self, mail: &Mail) -> io::Result<()> {
+        log::info!("mail to {}: {}\n{}", mail.to, mail.subject, mail.text);
+        Ok(())
+    }
+}
+
+/// Posts mail as json to the API of a mail service or a relay in front of
+/// one, with `from`, `to`, `subject` and `text` fields.
+pub struct HttpMailer {
+    url: String,
+    token: Option<String>,
+    from: String,
+}
+
+#[derive(Serialize)]
+struct HttpMail<'a> {
+    from: &'a str,
+    #[serde(flatten)]
+    mail: &'a Mail<'a>,
+}
+
+impl HttpMailer {
+    pub fn new(url: &str, token: Option<&str>, from: &str) -> HttpMailer {
+        HttpMailer {
+            url: url.into(),
+            token: token.map(String::from),
+            from: from.into(),
+        }
+    }
+
+    pub fn from_env() -> HttpMailer {
+        let var = |name: &str| env::var(name).unwrap_or_else(|_| panic!("{} must be defined", name));
+
+        HttpMailer::new(
+            &var("MAIL_HTTP_URL"),
+            env::var("MAIL_HTTP_TOKEN").ok().as_deref(),
+            &var("MAIL_FROM"),
+        )
+    }
+}
+
+impl Mailer for HttpMailer {
+    fn send(&self, mail: &Mail) -> io::Result<()> {
+        let body = serde_json::to_vec(&HttpMail { from: &self.from, mail })?;
+        let mut request = ureq::post(&self.url).set("content-type", "application/json");
+
+        if let Some(token) = &self.token {
+            request = request.set("authorization", &format!("Bearer {}", token));
+        }
+
+        request
+            .send_bytes(&body)
+            .map_err(io::Error::other)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1365 @@
+use std::ops::Range;
+
+use juniper::GraphQLObject;
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag as MarkdownTag};
+
+use crate::{schemas::root::Context, models::{block::{Block, BlockContent, BlockTree, NewBlockContent, Tag, ListStyle, CalloutVariant, MAX_INDENT}, document::Document, rich_text::{self, TextRun}, table::{self, ColumnType, TableColumn, TableRow}}};
+
+use super::{errors::FieldErrors, html, math, validate::Validate};
+
+/// Something in the imported markdown that could not be mapped onto the
+/// block model exactly.
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct ImportWarning {
+    #[graphql(description = "Line in the markdown source where the construct starts")]
+    pub line: i32,
+    pub construct: String,
+    pub message: String,
+}
+
+pub struct ParsedMarkdown {
+    pub blocks: Vec<NewBlockContent>,
+    pub warnings: Vec<ImportWarning>,
+}
+
+struct PendingImage {
+    url: String,
+    alt: String,
+    title: String,
+}
+
+/// The kind of block whose text is being collected.
+enum Pending {
+    Text(Tag),
+    ListItem { style: ListStyle, indent: i32, checked: Option<bool> },
+    Quote,
+    Code { language: Option<String>, start: usize },
+    Cell,
+}
+
+/// A table being imported, the text of its header cells and of the cells
+/// of each row.
+struct PendingTable {
+    range: Range<usize>,
+    head: Vec<String>,
+    body: Vec<Vec<String>>,
+    in_head: bool,
+}
+
+/// Inline elements the collected text is inside of. Only text blocks keep
+/// them.
+#[derive(Default)]
+struct Marks {
+    bold: usize,
+    italic: usize,
+    strike: usize,
+    link: Option<String>,
+}
+
+/// A list or block quote being imported. When it turns out to hold
+/// something the block model can't represent, the blocks made from it are
+/// dropped again and it is kept as a paragraph instead.
+struct Container {
+    range: Range<usize>,
+    construct: &'static str,
+    blocks: usize,
+    warnings: usize,
+    /// Styles of the open lists, outermost first. Empty for block quotes.
+    lists: Vec<ListStyle>,
+    callout: Option<CalloutVariant>,
+}
+
+struct Importer<'a> {
+    source: &'a str,
+    blocks: Vec<NewBlockContent>,
+    warnings: Vec<ImportWarning>,
+    pending: Option<Pending>,
+    text: String,
+    runs: Vec<TextRun>,
+    marks: Marks,
+    image: Option<PendingImage>,
+    container: Option<Container>,
+    table: Option<PendingTable>,
+    html: Option<Range<usize>>,
+    skip_until: usize,
+    /// The `$` after inline math, which is left out of the text.
+    closing_dollar: bool,
+}
+
+fn construct_name(tag: &MarkdownTag) -> &'static str {
+    match tag {
+        MarkdownTag::Heading(..) => "heading level 4 to 6",
+        MarkdownTag::BlockQuote => "block quote",
+        MarkdownTag::CodeBlock(_) => "code block",
+        MarkdownTag::List(Some(_)) => "numbered list",
+        MarkdownTag::List(None) => "bulleted list",
+        MarkdownTag::FootnoteDefinition(_) => "footnote",
+        MarkdownTag::Table(_) => "table",
+        _ => "element",
+    }
+}
+
+/// Variant of a block quote written in GitHub's alert syntax, where the
+/// first line is a marker such as `[!NOTE]`.
+fn callout_variant(source: &str) -> Option<CalloutVariant> {
+    let first_line = source.lines().next()?.trim_start().strip_prefix('>')?.trim();
+    let name = first_line.strip_prefix("[!")?.strip_suffix(']')?;
+
+    CalloutVariant::ALL
+        .into_iter()
+        .find(|variant| variant.name().eq_ignore_ascii_case(name))
+}
+
+impl<'a> Importer<'a> {
+    fn new(source: &'a str) -> Importer<'a> {
+        Importer {
+            source,
+            blocks: Vec::new(),
+            warnings: Vec::new(),
+            pending: None,
+            text: String::new(),
+            runs: Vec::new(),
+            marks: Marks::default(),
+            image: None,
+            container: None,
+            table: None,
+            html: None,
+            skip_until: 0,
+            closing_dollar: false,
+        }
+    }
+
+    fn line(&self, offset: usize) -> i32 {
+        self.source[..offset].matches('\n').count() as i32 + 1
+    }
+
+    fn warn(&mut self, offset: usize, construct: &str, message: &str) {
+        let line = self.line(offset);
+
+        let duplicate = self.warnings
+            .iter()
+            .any(|warning| warning.line == line && warning.construct == construct);
+
+        if !duplicate {
+            self.warnings.push(ImportWarning {
+                line,
+                construct: construct.into(),
+                message: message.into(),
+            });
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        self.push_run(text, false, false);
+    }
+
+    fn push_run(&mut self, text: &str, code: bool, math: bool) {
+        let text = match std::mem::take(&mut self.closing_dollar) {
+            true => text.strip_prefix('$').unwrap_or(text),
+            false => text,
+        };
+
+        match &mut self.image {
+            Some(image) => image.alt.push_str(text),
+            None => {
+                self.text.push_str(text);
+                self.runs.push(TextRun {
+                    text: text.into(),
+                    bold: self.marks.bold > 0,
+                    italic: self.marks.italic > 0,
+                    code,
+                    strike: self.marks.strike > 0,
+                    math,
+                    link: self.marks.link.clone(),
+                });
+            },
+        }
+    }
+
+    /// Inline math is written as a code span between dollar signs, as on
+    /// GitHub and GitLab, so renderers without math still show it as code.
+    fn is_inline_math(&self, range: &Range<usize>, code: &str) -> bool {
+        let before = &self.source[..range.start];
+
+        before.ends_with('$')
+            && !before.ends_with("\\$")
+            && self.source[range.end..].starts_with('$')
+            && self.runs.last().is_some_and(|run| !run.code && run.text.ends_with('$'))
+            && math::to_mathml(code, false).is_ok()
+    }
+
+    fn push_math(&mut self, latex: &str) {
+        if let Some(run) = self.runs.last_mut() {
+            run.text.pop();
+            self.text.pop();
+        }
+
+        self.push_run(latex, false, true);
+        self.closing_dollar = true;
+    }
+
+    /// Inline elements are kept as marks in text blocks, other blocks only
+    /// get their text.
+    fn keeps_marks(&mut self, offset: usize, construct: &str, message: &str) -> bool {
+        if matches!(self.pending, Some(Pending::Text(_))) && self.image.is_none() {
+            return true;
+        }
+
+        self.warn(offset, construct, message);
+        false
+    }
+
+    fn start_mark(&mut self, offset: usize, tag: &MarkdownTag) {
+        let construct = match tag {
+            MarkdownTag::Emphasis => "emphasis",
+            MarkdownTag::Strong => "strong emphasis",
+            _ => "strikethrough",
+        };
+
+        if !self.keeps_marks(offset, construct, "imported as plain text") {
+            return;
+        }
+
+        match tag {
+            MarkdownTag::Emphasis => self.marks.italic += 1,
+            MarkdownTag::Strong => self.marks.bold += 1,
+            _ => self.marks.strike += 1,
+        }
+    }
+
+    fn start_link(&mut self, offset: usize, url: &str, title: &str) {
+        if !self.keeps_marks(offset, "link", "only the link text was imported") {
+            return;
+        }
+
+        if !title.is_empty() {
+            self.warn(offset, "link title", "left out");
+        }
+
+        self.marks.link = rich_text::safe_link(url);
+
+        if self.marks.link.is_none() {
+            self.warn(offset, "link", "target left out as it isn't a safe url");
+        }
+    }
+
+    /// Turns the collected text into a block of the pending kind.
+    fn flush_text(&mut self) {
+        let content = self.text.trim();
+
+        let block = match &self.pending {
+            Some(Pending::Code { language, start }) => {
+                let (language, start) = (language.clone(), *start);
+                let code = self.text.strip_suffix('\n').unwrap_or(&self.text);
+                let content = Some(code.to_string()).filter(|code| !code.is_empty());
+
+                match language.as_deref() {
+                    Some("math") if content.as_deref().is_none_or(|content| math::to_mathml(content, true).is_ok()) =>
+                        Some(NewBlockContent::Math { content }),
+                    Some("math") => {
+                        self.warn(start, "math block", "imported as a code block as it isn't valid LaTeX");
+                        Some(NewBlockContent::Code { language, content })
+                    },
+                    _ => Some(NewBlockContent::Code { language, content }),
+                }
+            },
+            Some(Pending::Quote) => match self.container.as_ref().and_then(|container| container.callout) {
+                Some(variant) => {
+                    // drop the [!NOTE] marker line
+                    let content = content.split_once('\n').map_or("", |(_, rest)| rest).trim();
+
+                    Some(NewBlockContent::Callout {
+                        variant,
+                        content: Some(content.to_string()).filter(|content| !content.is_empty()),
+                    })
+                },
+                None if !content.is_empty() => Some(NewBlockContent::Quote { content: Some(content.into()) }),
+                None => None,
+            },
+            Some(Pending::Cell) => {
+                if let Some(table) = &mut self.table {
+                    let content = content.to_string();
+
+                    match table.body.last_mut() {
+                        Some(cells) if !table.in_head => cells.push(content),
+                        _ => table.head.push(content),
+                    }
+                }
+
+                None
+            },
+            _ if content.is_empty() => None,
+            Some(Pending::Text(tag)) => Some(NewBlockContent::text(*tag, rich_text::trim(self.runs.clone()))),
+            Some(Pending::ListItem { style, indent, checked: None }) => Some(NewBlockContent::ListItem {
+                style: *style,
+                indent: *indent,
+                content: Some(content.into()),
+            }),
+            Some(Pending::ListItem { indent, checked: Some(checked), .. }) => Some(NewBlockContent::Todo {
+                checked: *checked,
+                indent: *indent,
+                content: Some(content.into()),
+            }),
+            None => None,
+        };
+
+        self.blocks.extend(block);
+        self.text.clear();
+        self.runs.clear();
+    }
+
+    /// Keeps the source of a construct the block model can't represent as
+    /// a plain paragraph so nothing is lost.
+    fn keep_as_paragraph(&mut self, range: Range<usize>, construct: &str) {
+        let content = self.source[range.clone()].trim_end();
+
+        self.blocks.push(NewBlockContent::Text {
+            tag: Tag::P,
+            content: Some(content.into()),
+            rich_text: None,
+        });
+
+        self.warn(range.start, construct, "kept as a plain paragraph");
+        self.skip_until = range.end;
+    }
+
+    fn keep_container_as_paragraph(&mut self) {
+        let Some(container) = self.container.take() else {
+            return;
+        };
+
+        self.blocks.truncate(container.blocks);
+        self.warnings.truncate(container.warnings);
+        self.pending = None;
+        self.image = None;
+        self.text.clear();
+        self.runs.clear();
+        self.marks = Marks::default();
+
+        self.keep_as_paragraph(container.range, container.construct);
+    }
+
+    fn flush_html(&mut self) {
+        if let Some(range) = self.html.take() {
+            self.keep_as_paragraph(range, "html");
+        }
+    }
+
+    fn start_container(&mut self, range: Range<usize>, construct: &'static str, lists: Vec<ListStyle>, callout: Option<CalloutVariant>) {
+        self.container = Some(Container {
+            range,
+            construct,
+            blocks: self.blocks.len(),
+            warnings: self.warnings.len(),
+            lists,
+            callout,
+        });
+    }
+
+    fn start_list(&mut self, range: Range<usize>, start: Option<u64>) {
+        let style = match start {
+            Some(_) => ListStyle::Numbered,
+            None => ListStyle::Bulleted,
+        };
+
+        if start.is_some_and(|start| start != 1) {
+            self.warn(range.start, "list start number", "numbering starts at 1");
+        }
+
+        match &mut self.container {
+            None => {
+                let construct = construct_name(&MarkdownTag::List(start));
+                self.start_container(range, construct, vec![style], None);
+            },
+            Some(container) if !container.lists.is_empty() && container.lists.len() <= MAX_INDENT as usize => {
+                container.lists.push(style);
+
+                // the parent item comes before its nested items
+                self.flush_text();
+                self.pending = None;
+            },
+            Some(_) => self.keep_container_as_paragraph(),
+        }
+    }
+
+    fn end_list(&mut self) {
+        if let Some(container) = &mut self.container {
+            container.lists.pop();
+
+            if container.lists.is_empty() {
+                self.container = None;
+            }
+        }
+    }
+
+    fn start_item(&mut self) {
+        let Some(container) = &self.container else {
+            return;
+        };
+
+        let Some(&style) = container.lists.last() else {
+            return self.keep_container_as_paragraph();
+        };
+
+        self.pending = Some(Pending::ListItem {
+            style,
+            indent: container.lists.len() as i32 - 1,
+            checked: None,
+        });
+    }
+
+    /// Paragraphs of list items and quotes are collected into one block.
+    /// Items can only have one.
+    fn start_container_paragraph(&mut self) {
+        match self.pending {
+            Some(Pending::ListItem { .. }) if self.text.is_empty() => {},
+            Some(Pending::Quote) => {
+                if !self.text.is_empty() {
+                    self.text.push_str("\n\n");
+                }
+            },
+            _ => self.keep_container_as_paragraph(),
+        }
+    }
+
+    fn start_table(&mut self, range: Range<usize>, alignments: &[Alignment]) {
+        if alignments.iter().any(|alignment| *alignment != Alignment::None) {
+            self.warn(range.start, "column alignment", "left out");
+        }
+
+        self.table = Some(PendingTable { range, head: Vec::new(), body: Vec::new(), in_head: false });
+    }
+
+    /// The column types are inferred from the cells, a table the block
+    /// model can't hold is kept as a paragraph.
+    fn end_table(&mut self) {
+        let Some(pending_table) = self.table.take() else {
+            return;
+        };
+
+        let (columns, rows) = table::from_markdown(&pending_table.head, &pending_table.body);
+
+        let mut errors = FieldErrors::new();
+        table::validate(&columns, &rows, &mut errors);
+
+        if errors.empty() {
+            self.blocks.push(NewBlockContent::Table { columns, rows });
+        } else {
+            self.keep_as_paragraph(pending_table.range, "table");
+        }
+    }
+
+    fn start_code_block(&mut self, range: Range<usize>, kind: CodeBlockKind) {
+        let language = match kind {
+            CodeBlockKind::Fenced(info) => info.split_whitespace().next().map(String::from),
+            CodeBlockKind::Indented => None,
+        };
+
+        let language = match language {
+            Some(language) if !Validate::is_language(&language) => {
+                self.warn(range.start, "code block language", "left out as it isn't a valid language name");
+                None
+            },
+            language => language,
+        };
+
+        self.pending = Some(Pending::Code { language, start: range.start });
+    }
+
+    fn event(&mut self, event: Event, range: Range<usize>) {
+        if range.start < self.skip_until {
+            return;
+        }
+
+        if self.pending.is_none() && !matches!(event, Event::Html(_)) {
+            self.flush_html();
+        }
+
+        let in_container = self.container.is_some();
+
+        match event {
+            Event::Start(MarkdownTag::Heading(level, ..)) if level <= HeadingLevel::H3 && !in_container => {
+                self.pending = Some(Pending::Text(match level {
+                    HeadingLevel::H1 => Tag::H1,
+                    HeadingLevel::H2 => Tag::H2,
+                    _ => Tag::H3,
+                }));
+            },
+            Event::Start(MarkdownTag::Paragraph) if !in_container => self.pending = Some(Pending::Text(Tag::P)),
+            Event::End(MarkdownTag::Heading(..)) | Event::End(MarkdownTag::Paragraph) if !in_container => {
+                self.flush_text();
+                self.pending = None;
+            },
+            Event::Start(MarkdownTag::Paragraph) => self.start_container_paragraph(),
+            Event::End(MarkdownTag::Paragraph) => {},
+            Event::Start(MarkdownTag::List(start)) => self.start_list(range, start),
+            Event::End(MarkdownTag::List(_)) => self.end_list(),
+            Event::Start(MarkdownTag::Item) => self.start_item(),
+            Event::TaskListMarker(is_checked) => {
+                if let Some(Pending::ListItem { checked, .. }) = &mut self.pending {
+                    *checked = Some(is_checked);
+                }
+            },
+            Event::Start(MarkdownTag::BlockQuote) if !in_container => {
+                let callout = callout_variant(&self.source[range.clone()]);
+                self.start_container(range, "block quote", Vec::new(), callout);
+                self.pending = Some(Pending::Quote);
+            },
+            Event::End(MarkdownTag::BlockQuote) => {
+                self.flush_text();
+                self.pending = None;
+                self.container = None;
+            },
+            Event::Start(MarkdownTag::CodeBlock(kind)) if !in_container => self.start_code_block(range, kind),
+            Event::End(MarkdownTag::Item) | Event::End(MarkdownTag::CodeBlock(_)) => {
+                self.flush_text();
+                self.pending = None;
+            },
+            Event::Rule if !in_container => self.blocks.push(NewBlockContent::Divider),
+            Event::Start(tag @ (MarkdownTag::Emphasis | MarkdownTag::Strong | MarkdownTag::Strikethrough)) => self.start_mark(range.start, &tag),
+            Event::Start(MarkdownTag::Link(_, url, title)) => self.start_link(range.start, &url, &title),
+            Event::End(MarkdownTag::Emphasis) => self.marks.italic = self.marks.italic.saturating_sub(1),
+            Event::End(MarkdownTag::Strong) => self.marks.bold = self.marks.bold.saturating_sub(1),
+            Event::End(MarkdownTag::Strikethrough) => self.marks.strike = self.marks.strike.saturating_sub(1),
+            Event::End(MarkdownTag::Link(..)) => self.marks.link = None,
+            Event::Start(MarkdownTag::Table(alignments)) if !in_container => self.start_table(range, &alignments),
+            Event::End(MarkdownTag::Table(_)) => self.end_table(),
+            Event::Start(MarkdownTag::TableHead) => {
+                if let Some(table) = &mut self.table {
+                    table.in_head = true;
+                }
+            },
+            Event::End(MarkdownTag::TableHead) => {
+                if let Some(table) = &mut self.table {
+                    table.in_head = false;
+                }
+            },
+            Event::Start(MarkdownTag::TableRow) => {
+                if let Some(table) = &mut self.table {
+                    table.body.push(Vec::new());
+                }
+            },
+            Event::Start(MarkdownTag::TableCell) => self.pending = Some(Pending::Cell),
+            Event::End(MarkdownTag::TableCell) => {
+                self.flush_text();
+                self.pending = None;
+            },
+            Event::Start(MarkdownTag::Image(..)) if self.table.is_some() => {
+                self.warn(range.start, "image in a table", "only the alt text was imported");
+            },
+            Event::Start(MarkdownTag::Image(_, url, title)) if !in_container => {
+                self.flush_text();
+                self.image = Some(PendingImage { url: url.to_string(), alt: String::new(), title: title.to_string() });
+            },
+            Event::End(MarkdownTag::Image(..)) => {
+                if let Some(image) = self.image.take() {
+                    let non_blank = |text: &str| Some(text.trim().to_string()).filter(|text| !text.is_empty());
+
+                    // the title is what gets shown with the image, so it
+                    // becomes the caption
+                    self.blocks.push(NewBlockContent::Image {
+                        url: Some(image.url),
+                        alt: non_blank(&image.alt),
+                        caption: non_blank(&image.title),
+                    });
+                }
+            },
+            Event::Start(_) | Event::Rule if in_container => self.keep_container_as_paragraph(),
+            Event::Start(tag) if self.pending.is_none() => self.keep_as_paragraph(range, construct_name(&tag)),
+            // text left over in an item after its nested list
+            Event::Html(_) | Event::Text(_) if self.pending.is_none() && in_container => self.keep_container_as_paragraph(),
+            Event::Html(_) if self.pending.is_none() => {
+                let start = self.html.as_ref().map_or(range.start, |html| html.start);
+                self.html = Some(start..range.end);
+            },
+            Event::Text(text) => self.push_text(&text),
+            Event::Code(code) => {
+                if self.keeps_marks(range.start, "inline code", "imported as plain text") {
+                    if self.is_inline_math(&range, &code) {
+                        self.push_math(&code);
+                    } else {
+                        self.push_run(&code, true, false);
+                    }
+                } else {
+                    self.push_text(&code);
+                }
+            },
+            Event::Html(html) => {
+                self.push_text(&html);
+                self.warn(range.start, "inline html", "imported as plain text");
+            },
+            Event::FootnoteReference(label) => {
+                self.push_text(&format!("[^{}]", label));
+                self.warn(range.start, "footnote reference", "imported as plain text");
+            },
+            Event::SoftBreak => self.push_text("\n"),
+            Event::HardBreak => {
+                self.push_text("\n");
+                self.warn(range.start, "hard line break", "imported as a soft line break");
+            },
+            _ => {},
+        }
+    }
+
+    fn finish(mut self) -> ParsedMarkdown {
+        self.flush_html();
+
+        ParsedMarkdown {
+            blocks: self.blocks,
+            warnings: self.warnings,
+        }
+    }
+}
+
+/// Maps CommonMark onto blocks. Headings up to level 3 and paragraphs
+/// become text blocks and images become image blocks, everything else is
+/// kept verbatim as a paragraph and reported in the warnings.
+pub fn parse(source: &str) -> ParsedMarkdown {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS;
+
+    let mut importer = Importer::new(source);
+
+    for (event, range) in Parser::new_ext(source, options).into_offset_iter() {
+        importer.event(event, range);
+    }
+
+    importer.finish()
+}
+
+/// Backslash escapes every ASCII punctuation character so the text is
+/// read back literally.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        if c.is_ascii_punctuation() {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// Whether `markdown` imports back as exactly `expected`.
+fn reads_back_as(markdown: &str, expected: &NewBlockContent) -> bool {
+    let parsed = parse(markdown);
+    parsed.warnings.is_empty() && parsed.blocks.len() == 1 && &parsed.blocks[0] == expected
+}
+
+/// Marks written with delimiters around the text, outermost first. Code
+/// is written per run as a code span instead.
+#[derive(Clone, PartialEq)]
+enum Mark {
+    Link(String),
+    Strike,
+    Bold,
+    Italic,
+}
+
+impl Mark {
+    fn of(run: &TextRun) -> Vec<Mark> {
+        let mut marks = Vec::new();
+
+        if let Some(link) = &run.link {
+            marks.push(Mark::Link(link.clone()));
+        }
+
+        for (mark, set) in [(Mark::Strike, run.strike), (Mark::Bold, run.bold), (Mark::Italic, run.italic)] {
+            if set {
+                marks.push(mark);
+            }
+        }
+
+        marks
+    }
+
+    fn opener(&self) -> &'static str {
+        match self {
+            Mark::Link(_) => "[",
+            Mark::Strike => "~~",
+            Mark::Bold => "**",
+            Mark::Italic => "*",
+        }
+    }
+
+    fn closer(&self) -> String {
+        match self {
+            Mark::Link(link) if link.contains(|c: char| c.is_whitespace() || "()<>\\".contains(c)) => {
+                let escaped = link.replace('\\', "\\\\").replace('<', "\\<").replace('>', "\\>");
+                format!("](<{}>)", escaped)
+            },
+            Mark::Link(link) => format!("]({})", link),
+            mark => mark.opener().into(),
+        }
+    }
+}
+
+fn code_span(code: &str) -> String {
+    let longest = code
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+
+    let fence = "`".repeat(longest + 1);
+    let padding = if code.starts_with('`') || code.ends_with('`') { " " } else { "" };
+
+    format!("{}{}{}{}{}", fence, padding, code, padding, fence)
+}
+
+/// Writes the runs with delimiters opened and closed as the marks change.
+/// Whitespace at the edges of a run is moved outside of the delimiters as
+/// emphasis can't start or end with it.
+fn render_runs(runs: &[TextRun], escaped: bool) -> String {
+    let mut markdown = String::new();
+    let mut open: Vec<Mark> = Vec::new();
+    let mut gap = String::new();
+
+    for run in runs {
+        let core = run.text.trim();
+
+        if core.is_empty() {
+            gap.push_str(&run.text);
+            continue;
+        }
+
+        let start = run.text.len() - run.text.trim_start().len();
+        gap.push_str(&run.text[..start]);
+
+        let marks = Mark::of(run);
+        let kept = open.iter().zip(&marks).take_while(|(open, mark)| open == mark).count();
+
+        for mark in open.drain(kept..).rev() {
+            markdown.push_str(&mark.closer());
+        }
+
+        markdown.push_str(&gap);
+        gap.clear();
+
+        for mark in &marks[kept..] {
+            markdown.push_str(mark.opener());
+        }
+
+        open = marks;
+
+        match (run.code, escaped) {
+            _ if run.math => markdown.push_str(&format!("${}$", code_span(core))),
+            (true, _) => markdown.push_str(&code_span(core)),
+            (false, true) => markdown.push_str(&escape(core)),
+            (false, false) => markdown.push_str(core),
+        }
+
+        gap.push_str(&run.text[start + core.len()..]);
+    }
+
+    for mark in open.drain(..).rev() {
+        markdown.push_str(&mark.closer());
+    }
+
+    markdown.push_str(&gap);
+    markdown
+}
+
+/// Whether `markdown` imports back as a text block with the same text and
+/// the same marks on everything but whitespace, which markdown can't mark
+/// at the edges of emphasis.
+fn reads_back_as_text(markdown: &str, expected: &NewBlockContent) -> bool {
+    let marked = |content: &NewBlockContent| match content {
+        NewBlockContent::Text { tag, content, rich_text } => {
+            let runs = rich_text.clone().unwrap_or_else(|| content.iter().map(|content| TextRun::plain(content)).collect());
+
+            let marks = runs
+                .iter()
+                .flat_map(|run| run.text.chars().filter(|c| !c.is_whitespace()).map(move |c| (c, Mark::of(run), run.code, run.math)))
+                .collect::<Vec<_>>();
+
+            Some((*tag, content.clone(), marks))
+        },
+        _ => None,
+    };
+
+    // A paragraph with a blank line in it can only be the verbatim source of
+    // a construct that was kept as a paragraph, which reads back the same
+    // with its warning. Escaped, it would split into several paragraphs.
+    let kept = matches!(expected, NewBlockContent::Text { tag: Tag::P, content: Some(content), .. } if content.contains("\n\n"));
+
+    let parsed = parse(markdown);
+
+    (kept || parsed.warnings.is_empty())
+        && parsed.blocks.len() == 1
+        && marked(&parsed.blocks[0]).is_some_and(|parsed| Some(parsed) == marked(expected))
+}
+
+fn render_text(tag: Tag, runs: Vec<TextRun>) -> String {
+    let prefix = match tag {
+        Tag::H1 => "# ",
+        Tag::H2 => "## ",
+        Tag::H3 => "### ",
+        Tag::P => "",
+    };
+
+    // headings can't span lines
+    let runs = match tag {
+        Tag::P => runs,
+        _ => runs
+            .into_iter()
+            .map(|run| TextRun { text: run.text.replace('\n', " "), ..run })
+            .collect(),
+    };
+
+    let expected = NewBlockContent::text(tag, runs.clone());
+    let markdown = format!("{}{}", prefix, render_runs(&runs, false));
+
+    // Only escape when the text would otherwise be read back differently,
+    // so importing and then exporting plain markdown gives the same text.
+    if reads_back_as_text(&markdown, &expected) {
+        return markdown;
+    }
+
+    format!("{}{}", prefix, render_runs(&runs, true))
+}
+
+fn render_image(url: &str, alt: Option<&str>, caption: Option<&str>) -> String {
+    let expected = NewBlockContent::Image {
+        url: Some(url.into()),
+        alt: alt.map(String::from),
+        caption: caption.map(String::from),
+    };
+
+    let image = |alt: &str, url: &str, caption: Option<&str>| match caption {
+        Some(caption) => format!("![{}]({} \"{}\")", alt, url, caption),
+        None => format!("![{}]({})", alt, url),
+    };
+
+    let markdown = image(alt.unwrap_or_default(), url, caption);
+
+    if reads_back_as(&markdown, &expected) {
+        return markdown;
+    }
+
+    let alt = escape(alt.unwrap_or_default());
+    let caption = caption.map(escape);
+    let markdown = image(&alt, url, caption.as_deref());
+
+    if reads_back_as(&markdown, &expected) {
+        return markdown;
+    }
+
+    image(&alt, &format!("<{}>", url.replace('<', "%3C").replace('>', "%3E")), caption.as_deref())
+}
+
+/// List item at the top level, `marker` is the bullet or number and `task`
+/// the to-do checkbox.
+fn render_list_item(marker: &str, task: &str, content: &str, expected: &NewBlockContent) -> String {
+    // continuation lines line up with the text so they stay in the item
+    let item = |content: &str| format!("{}{}{}", marker, task, content.replace('\n', &format!("\n{}", " ".repeat(marker.len()))));
+
+    let markdown = item(content);
+
+    if reads_back_as(&markdown, expected) {
+        return markdown;
+    }
+
+    item(&escape(content))
+}
+
+fn quote_lines(text: &str) -> String {
+    text.lines()
+        .map(|line| if line.is_empty() { String::from(">") } else { format!("> {}", line) })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_quote(content: &str) -> String {
+    let markdown = quote_lines(content);
+
+    if reads_back_as(&markdown, &NewBlockContent::Quote { content: Some(content.into()) }) {
+        return markdown;
+    }
+
+    quote_lines(&escape(content))
+}
+
+/// Callouts use GitHub's alert syntax, other renderers show them as a
+/// plain block quote.
+fn render_callout(variant: CalloutVariant, content: Option<&str>) -> String {
+    let expected = NewBlockContent::Callout { variant, content: content.map(String::from) };
+
+    let callout = |content: Option<&str>| match content {
+        Some(content) => format!("> [!{}]\n{}", variant.name(), quote_lines(content)),
+        None => format!("> [!{}]", variant.name()),
+    };
+
+    let markdown = callout(content);
+
+    if reads_back_as(&markdown, &expected) {
+        return markdown;
+    }
+
+    callout(content.map(escape).as_deref())
+}
+
+fn render_code(language: Option<&str>, content: Option<&str>) -> String {
+    let content = content.unwrap_or_default();
+
+    // the fence has to be longer than any run of backticks in the code
+    let longest_run = content
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+
+    let fence = "`".repeat(longest_run.max(2) + 1);
+
+    match content {
+        "" => format!("{}{}\n{}", fence, language.unwrap_or_default(), fence),
+        content => format!("{}{}\n{}\n{}", fence, language.unwrap_or_default(), content, fence),
+    }
+}
+
+fn render_table_cell(text: &str) -> String {
+    let text = text.replace('\n', " ");
+    let expected = NewBlockContent::Text { tag: Tag::P, content: Some(text.clone()), rich_text: None };
+
+    if !text.contains('|') && reads_back_as(&text, &expected) {
+        return text;
+    }
+
+    escape(&text)
+}
+
+/// GFM table with the column names as its header row. Checkboxes are
+/// written as `[x]` and `[ ]` so their column reads back as one.
+fn render_table(columns: &[TableColumn], rows: &[TableRow]) -> String {
+    let line = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+
+    let mut lines = vec![
+        line(columns.iter().map(|column| render_table_cell(&column.name)).collect()),
+        line(columns.iter().map(|_| String::from("---")).collect()),
+    ];
+
+    for row in rows {
+        let cells = columns
+            .iter()
+            .map(|column| match (column.column_type, row.value(&column.id)) {
+                (ColumnType::Checkbox, value) => String::from(if value == Some("true") { "[x]" } else { "[ ]" }),
+                (_, value) => render_table_cell(value.unwrap_or_default()),
+            })
+            .collect();
+
+        lines.push(line(cells));
+    }
+
+    lines.join("\n")
+}
+
+fn indent_lines(text: &str, indent: &str) -> String {
+    text.lines()
+        .map(|line| if line.is_empty() { String::new() } else { format!("{}{}", indent, line) })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Blocks nested under a list item or to-do are indented to line up with
+/// its text, nested lists stay tight.
+fn render_nested<'a>(tree: &BlockTree<'a>, parent_id: &'a str) -> String {
+    let nested = render_blocks(tree, Some(parent_id));
+
+    if nested.is_empty() {
+        return nested;
+    }
+
+    let tight = matches!(
+        tree.children(Some(parent_id)).first(),
+        Some((_, Some(BlockContent::ListItemBlock(_) | BlockContent::TodoBlock(_)))),
+    );
+
+    format!("{}{}", if tight { "\n" } else { "\n\n" }, indent_lines(&nested, "    "))
+}
+
+/// Markdown has no collapsible sections, GitHub and most other renderers
+/// show html `<details>` with the markdown inside it.
+fn render_toggle<'a>(tree: &BlockTree<'a>, id: &'a str, content: Option<&str>) -> String {
+    let summary = format!("<details>\n<summary>{}</summary>", html::escape(content.unwrap_or_default()));
+
+    match render_blocks(tree, Some(id)) {
+        nested if nested.is_empty() => format!("{}\n</details>", summary),
+        nested => format!("{}\n\n{}\n\n</details>", summary, nested),
+    }
+}
+
+/// Renders blocks back to CommonMark, one block per paragraph. Consecutive
+/// list items and to-dos form tight lists, nested items are indented four
+/// spaces per level.
+pub fn render(blocks: &[(Block, Option<BlockContent>)]) -> String {
+    let markdown = render_blocks(&BlockTree::new(blocks), None);
+
+    if markdown.is_empty() {
+        return markdown;
+    }
+
+    format!("{}\n", markdown)
+}
+
+fn render_blocks<'a>(tree: &BlockTree<'a>, parent_id: Option<&'a str>) -> String {
+    let mut markdown = String::new();
+    let mut list_depth: Option<i32> = None;
+
+    for (block, content) in tree.children(parent_id).iter().copied() {
+        let Some(content) = content else {
+            continue;
+        };
+
+        // items can only be one level deeper than the item before them
+        let clamp_indent = |indent: i32| indent.clamp(0, list_depth.map_or(0, |depth| depth + 1));
+
+        let (rendered, depth) = match content {
+            BlockContent::TextBlock(text_block) => {
+                let content = text_block.content.as_deref().unwrap_or_default();
+
+                if content.trim().is_empty() {
+                    continue;
+                }
+
+                (render_text(text_block.tag, text_block.runs()), None)
+            },
+            BlockContent::ImageBlock(image_block) => {
+                let Some(url) = image_block.url.as_deref() else {
+                    continue;
+                };
+
+                (render_image(url, image_block.alt.as_deref(), image_block.caption.as_deref()), None)
+            },
+            BlockContent::ListItemBlock(list_item_block) => {
+                let Some(content) = list_item_block.content.as_deref().filter(|content| !content.trim().is_empty()) else {
+                    continue;
+                };
+
+                let marker = match list_item_block.style {
+                    ListStyle::Bulleted => "- ",
+                    ListStyle::Numbered => "1. ",
+                };
+
+                let expected = NewBlockContent::ListItem { style: list_item_block.style, indent: 0, content: Some(content.into()) };
+
+                let rendered = format!("{}{}", render_list_item(marker, "", content, &expected), render_nested(tree, &block.id));
+
+                (rendered, Some(clamp_indent(list_item_block.indent)))
+            },
+            BlockContent::TodoBlock(todo_block) => {
+                let Some(content) = todo_block.content.as_deref().filter(|content| !content.trim().is_empty()) else {
+                    continue;
+                };
+
+                let task = if todo_block.checked { "[x] " } else { "[ ] " };
+                let expected = NewBlockContent::Todo { checked: todo_block.checked, indent: 0, content: Some(content.into()) };
+
+                let rendered = format!("{}{}", render_list_item("- ", task, content, &expected), render_nested(tree, &block.id));
+
+                (rendered, Some(clamp_indent(todo_block.indent)))
+            },
+            BlockContent::QuoteBlock(quote_block) => {
+                let content = quote_block.content.as_deref().unwrap_or_default();
+
+                if content.trim().is_empty() {
+                    continue;
+                }
+
+                (render_quote(content), None)
+            },
+            BlockContent::CodeBlock(code_block) => (render_code(code_block.language.as_deref(), code_block.content.as_deref()), None),
+            BlockContent::MathBlock(math_block) => (render_code(Some("math"), math_block.content.as_deref()), None),
+            BlockContent::EmbedBlock(_) => continue,
+            BlockContent::CalloutBlock(callout_block) => (render_callout(callout_block.variant, callout_block.content.as_deref()), None),
+            BlockContent::ToggleBlock(toggle_block) => (render_toggle(tree, &block.id, toggle_block.content.as_deref()), None),
+            BlockContent::TableBlock(table_block) => {
+                let columns = table_block.column_list();
+
+                if columns.is_empty() {
+                    continue;
+                }
+
+                (render_table(&columns, &table_block.row_list()), None)
+            },
+            BlockContent::DividerBlock(_) => (String::from("---"), None),
+        };
+
+        if !markdown.is_empty() {
+            markdown.push_str(if list_depth.is_some() && depth.is_some() { "\n" } else { "\n\n" });
+        }
+
+        markdown.push_str(&indent_lines(&rendered, &"    ".repeat(depth.unwrap_or_default() as usize)));
+        list_depth = depth;
+    }
+
+    markdown
+}
+
+/// YAML front matter describing a document, for use in exported files.
+pub fn front_matter(document: &Document) -> String {
+    let quote = |value: &str| serde_json::to_string(value).unwrap();
+
+    let mut front_matter = String::from("---\n");
+
+    front_matter.push_str(&format!("name: {}\n", quote(&document.name)));
+
+    if let Some(description) = &document.description {
+        front_matter.push_str(&format!("description: {}\n", quote(description)));
+    }
+
+    front_matter.push_str(&format!("created_at: {}\n", document.created_at.format("%Y-%m-%dT%H:%M:%S")));
+    front_matter.push_str(&format!("updated_at: {}\n", document.updated_at.format("%Y-%m-%dT%H:%M:%S")));
+    front_matter.push_str("---\n\n");
+
+    front_matter
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use crate::models::block::{TextBlock, ImageBlock, ListItemBlock, TodoBlock, QuoteBlock, CodeBlock, MathBlock, CalloutBlock, TableBlock, DividerBlock};
+
+    use super::*;
+
+    /// The blocks as they'd be stored for `render`, without a database.
+    fn stored(blocks: Vec<NewBlockContent>) -> Vec<(Block, Option<BlockContent>)> {
+        let updated_at = NaiveDateTime::default();
+
+        blocks
+            .into_iter()
+            .enumerate()
+            .map(|(line_number, new_content)| {
+                let block_id = format!("block{}", line_number);
+
+                let content = match new_content {
+                    NewBlockContent::Text { tag, content, rich_text } => BlockContent::TextBlock(TextBlock {
+                        block_id: block_id.clone(),
+                        tag,
+                        content,
+                        updated_at,
+                        rich_text: rich_text.map(|runs| serde_json::to_value(runs).unwrap()),
+                    }),
+                    NewBlockContent::Image { url, alt, caption } => BlockContent::ImageBlock(ImageBlock {
+                        block_id: block_id.clone(),
+                        url,
+                        updated_at,
+                        width: None,
+                        height: None,
+                        format: None,
+                        srcset: None,
+                        alt,
+                        caption,
+                    }),
+                    NewBlockContent::ListItem { style, indent, content } =>
+                        BlockContent::ListItemBlock(ListItemBlock { block_id: block_id.clone(), style, indent, content, updated_at }),
+                    NewBlockContent::Todo { checked, indent, content } =>
+                        BlockContent::TodoBlock(TodoBlock { block_id: block_id.clone(), checked, indent, content, updated_at }),
+                    NewBlockContent::Quote { content } => BlockContent::QuoteBlock(QuoteBlock { block_id: block_id.clone(), content, updated_at }),
+                    NewBlockContent::Code { language, content } =>
+                        BlockContent::CodeBlock(CodeBlock { block_id: block_id.clone(), language, content, updated_at }),
+                    NewBlockContent::Math { content } => BlockContent::MathBlock(MathBlock { block_id: block_id.clone(), content, updated_at }),
+                    NewBlockContent::Callout { variant, content } =>
+                        BlockContent::CalloutBlock(CalloutBlock { block_id: block_id.clone(), variant, content, updated_at }),
+                    NewBlockContent::Table { columns, rows } => BlockContent::TableBlock(TableBlock {
+                        block_id: block_id.clone(),
+                        table_columns: serde_json::to_value(columns).unwrap(),
+                        table_rows: serde_json::to_value(rows).unwrap(),
+                        updated_at,
+                    }),
+                    NewBlockContent::Divider => BlockContent::DividerBlock(DividerBlock { block_id: block_id.clone(), updated_at }),
+                    content => panic!("markdown doesn't import {:?}", content),
+                };
+
+                let block = Block {
+                    id: block_id,
+                    document_id: String::from("document"),
+                    line_number: line_number as i32,
+                    created_at: updated_at,
+                    last_edited_by: None,
+                    last_edited_at: updated_at,
+                    parent_id: None,
+                };
+
+                (block, Some(content))
+            })
+            .collect()
+    }
+
+    /// Tables get new column and row ids on every import, cells refer to
+    /// their column by position instead.
+    fn without_ids(blocks: Vec<NewBlockContent>) -> Vec<NewBlockContent> {
+        blocks
+            .into_iter()
+            .map(|block| match block {
+                NewBlockContent::Table { columns, rows } => {
+                    let position = |id: &str| columns.iter().position(|column| column.id == id).unwrap().to_string();
+
+                    let rows = rows
+                        .iter()
+                        .map(|row| TableRow {
+                            id: String::new(),
+                            cells: row
+                                .cells
+                                .iter()
+                                .map(|cell| table::TableCell { column_id: position(&cell.column_id), ..cell.clone() })
+                                .collect(),
+                        })
+                        .collect();
+
+                    let columns = columns
+                        .into_iter()
+                        .map(|column| TableColumn { id: String::new(), ..column })
+                        .collect();
+
+                    NewBlockContent::Table { columns, rows }
+                },
+                block => block,
+            })
+            .collect()
+    }
+
+    /// Imports `markdown`, exports the blocks and imports the export again,
+    /// which has to give the same blocks and export the same way.
+    fn round_trip(markdown: &str) -> Vec<NewBlockContent> {
+        let exported = render(&stored(parse(markdown).blocks));
+        let reimported = parse(&exported);
+
+        assert_eq!(without_ids(reimported.blocks), without_ids(parse(markdown).blocks), "{:?} exported as {:?}", markdown, exported);
+        assert_eq!(render(&stored(parse(&exported).blocks)), exported, "{:?}", markdown);
+
+        without_ids(parse(markdown).blocks)
+    }
+
+    #[test]
+    fn headings_round_trip() {
+        let blocks = round_trip("# One\n\n## Two\n\n### Three\n");
+
+        assert_eq!(blocks, vec![
+            NewBlockContent::text(Tag::H1, vec![TextRun::plain("One")]),
+            NewBlockContent::text(Tag::H2, vec![TextRun::plain("Two")]),
+            NewBlockContent::text(Tag::H3, vec![TextRun::plain("Three")]),
+        ]);
+    }
+
+    #[test]
+    fn rich_text_round_trips() {
+        let blocks = round_trip("Some **bold**, *italic*, ~~struck~~, `code` and a [link](https://example.com) with 1 \\* 2.\n");
+
+        let NewBlockContent::Text { tag: Tag::P, rich_text: Some(runs), .. } = &blocks[0] else {
+            panic!("expected a paragraph with marks, got {:?}", blocks);
+        };
+
+        assert!(runs.iter().any(|run| run.bold && run.text == "bold"));
+        assert!(runs.iter().any(|run| run.italic && run.text == "italic"));
+        assert!(runs.iter().any(|run| run.strike && run.text == "struck"));
+        assert!(runs.iter().any(|run| run.code && run.text == "code"));
+        assert!(runs.iter().any(|run| run.link.as_deref() == Some("https://example.com")));
+        assert!(runs.iter().any(|run| run.text.contains("1 * 2.")));
+    }
+
+    #[test]
+    fn image_round_trips() {
+        let blocks = round_trip("![A *small* cat](https://example.com/cat.png \"The cat, \\\"Tom\\\"\")\n");
+
+        assert_eq!(blocks, vec![NewBlockContent::Image {
+            url: Some(String::from("https://example.com/cat.png")),
+            alt: Some(String::from("A small cat")),
+            caption: Some(String::from("The cat, \"Tom\"")),
+        }]);
+    }
+
+    #[test]
+    fn lists_round_trip() {
+        let blocks = round_trip("- one\n- two\n    - nested\n\n1. first\n2. second\n");
+
+        assert_eq!(blocks, vec![
+            NewBlockContent::ListItem { style: ListStyle::Bulleted, indent: 0, content: Some(String::from("one")) },
+            NewBlockContent::ListItem { style: ListStyle::Bulleted, indent: 0, content: Some(String::from("two")) },
+            NewBlockContent::ListItem { style: ListStyle::Bulleted, indent: 1, content: Some(String::from("nested")) },
+            NewBlockContent::ListItem { style: ListStyle::Numbered, indent: 0, content: Some(String::from("first")) },
+            NewBlockContent::ListItem { style: ListStyle::Numbered, indent: 0, content: Some(String::from("second")) },
+        ]);
+    }
+
+    #[test]
+    fn todos_round_trip() {
+        let blocks = round_trip("- [ ] open\n- [x] done\n");
+
+        assert_eq!(blocks, vec![
+            NewBlockContent::Todo { checked: false, indent: 0, content: Some(String::from("open")) },
+            NewBlockContent::Todo { checked: true, indent: 0, content: Some(String::from("done")) },
+        ]);
+    }
+
+    #[test]
+    fn quote_round_trips() {
+        let blocks = round_trip("> Quoted\n> text\n");
+
+        assert_eq!(blocks, vec![NewBlockContent::Quote { content: Some(String::from("Quoted\ntext")) }]);
+    }
+
+    #[test]
+    fn callout_round_trips() {
+        let blocks = round_trip("> [!WARNING]\n> Mind the step\n");
+
+        assert!(matches!(&blocks[..], [NewBlockContent::Callout { content: Some(content), .. }] if content == "Mind the step"), "{:?}", blocks);
+    }
+
+    #[test]
+    fn code_round_trips() {
+        let blocks = round_trip("```rust\nfn main() {\n    println!(\"```\");\n}\n```\n");
+
+        assert_eq!(blocks, vec![NewBlockContent::Code {
+            language: Some(String::from("rust")),
+            content: Some(String::from("fn main() {\n    println!(\"```\");\n}")),
+        }]);
+    }
+
+    #[test]
+    fn math_round_trips() {
+        let blocks = round_trip("```math\n\\frac{a}{b}\n```\n");
+
+        assert_eq!(blocks, vec![NewBlockContent::Math { content: Some(String::from("\\frac{a}{b}")) }]);
+    }
+
+    #[test]
+    fn table_round_trips() {
+        let blocks = round_trip("| Name | Done |\n| --- | --- |\n| a \\| b | [x] |\n| c | [ ] |\n");
+
+        let [NewBlockContent::Table { columns, rows }] = &blocks[..] else {
+            panic!("expected a table, got {:?}", blocks);
+        };
+
+        assert_eq!(columns.iter().map(|column| column.name.as_str()).collect::<Vec<_>>(), ["Name", "Done"]);
+        assert_eq!(columns[1].column_type, ColumnType::Checkbox);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].value("0"), Some("a | b"));
+    }
+
+    #[test]
+    fn divider_round_trips() {
+        let blocks = round_trip("Above\n\n---\n\nBelow\n");
+
+        assert_eq!(blocks[1], NewBlockContent::Divider);
+    }
+
+    #[test]
+    fn kept_constructs_round_trip() {
+        let blocks = round_trip("1. one\n\n   para in item\n2. two\n");
+
+        assert_eq!(blocks, vec![NewBlockContent::Text {
+            tag: Tag::P,
+            content: Some(String::from("1. one\n\n   para in item\n2. two")),
+            rich_text: None,
+        }]);
+
+        round_trip("Text[^1]\n\n[^1]: A footnote\n\n   with a second paragraph\n");
+    }
+}
@@ -0,0 +1,850 @@
+use super::{errors::{FieldErrors, FieldError}, html::escape};
+
+pub const MAX_MATH_LENGTH: usize = 4096;
+const MAX_DEPTH: usize = 32;
+
+const GREEK: &[(&str, &str)] = &[
+    ("alpha", "α"), ("beta", "β"), ("gamma", "γ"), ("delta", "δ"), ("epsilon", "ϵ"), ("varepsilon", "ε"),
+    ("zeta", "ζ"), ("eta", "η"), ("theta", "θ"), ("vartheta", "ϑ"), ("iota", "ι"), ("kappa", "κ"),
+    ("lambda", "λ"), ("mu", "μ"), ("nu", "ν"), ("xi", "ξ"), ("pi", "π"), ("varpi", "ϖ"), ("rho", "ρ"),
+    ("varrho", "ϱ"), ("sigma", "σ"), ("varsigma", "ς"), ("tau", "τ"), ("upsilon", "υ"), ("phi", "ϕ"),
+    ("varphi", "φ"), ("chi", "χ"), ("psi", "ψ"), ("omega", "ω"),
+];
+
+/// Capital greek letters are written upright.
+const CAPITAL_GREEK: &[(&str, &str)] = &[
+    ("Gamma", "Γ"), ("Delta", "Δ"), ("Theta", "Θ"), ("Lambda", "Λ"), ("Xi", "Ξ"), ("Pi", "Π"),
+    ("Sigma", "Σ"), ("Upsilon", "Υ"), ("Phi", "Φ"), ("Psi", "Ψ"), ("Omega", "Ω"),
+];
+
+const SYMBOLS: &[(&str, &str)] = &[
+    ("infty", "∞"), ("partial", "∂"), ("nabla", "∇"), ("ell", "ℓ"), ("hbar", "ℏ"), ("emptyset", "∅"),
+    ("varnothing", "∅"), ("aleph", "ℵ"), ("Re", "ℜ"), ("Im", "ℑ"), ("wp", "℘"),
+];
+
+const OPERATORS: &[(&str, &str)] = &[
+    ("pm", "±"), ("mp", "∓"), ("times", "×"), ("div", "÷"), ("cdot", "⋅"), ("ast", "∗"), ("circ", "∘"),
+    ("bullet", "∙"), ("star", "⋆"), ("dagger", "†"), ("leq", "≤"), ("le", "≤"), ("geq", "≥"), ("ge", "≥"),
+    ("neq", "≠"), ("ne", "≠"), ("approx", "≈"), ("equiv", "≡"), ("sim", "∼"), ("simeq", "≃"), ("cong", "≅"),
+    ("propto", "∝"), ("ll", "≪"), ("gg", "≫"), ("in", "∈"), ("notin", "∉"), ("ni", "∋"), ("subset", "⊂"),
+    ("supset", "⊃"), ("subseteq", "⊆"), ("supseteq", "⊇"), ("cup", "∪"), ("cap", "∩"), ("setminus", "∖"),
+    ("wedge", "∧"), ("land", "∧"), ("vee", "∨"), ("lor", "∨"), ("oplus", "⊕"), ("otimes", "⊗"),
+    ("to", "→"), ("rightarrow", "→"), ("leftarrow", "←"), ("gets", "←"), ("leftrightarrow", "↔"),
+    ("Rightarrow", "⇒"), ("Leftarrow", "⇐"), ("Leftrightarrow", "⇔"), ("implies", "⟹"), ("iff", "⟺"),
+    ("mapsto", "↦"), ("uparrow", "↑"), ("downarrow", "↓"), ("ldots", "…"), ("dots", "…"), ("cdots", "⋯"),
+    ("vdots", "⋮"), ("ddots", "⋱"), ("mid", "∣"), ("parallel", "∥"), ("perp", "⊥"), ("angle", "∠"),
+    ("forall", "∀"), ("exists", "∃"), ("neg", "¬"), ("lnot", "¬"), ("colon", ":"), ("backslash", "∖"),
+    ("langle", "⟨"), ("rangle", "⟩"), ("lfloor", "⌊"), ("rfloor", "⌋"), ("lceil", "⌈"), ("rceil", "⌉"),
+    ("vert", "|"), ("lvert", "|"), ("rvert", "|"), ("Vert", "‖"), ("lVert", "‖"), ("rVert", "‖"),
+];
+
+/// Large operators, the ones that are `true` take their scripts above and
+/// below when displayed.
+const LARGE_OPERATORS: &[(&str, &str, bool)] = &[
+    ("sum", "∑", true), ("prod", "∏", true), ("coprod", "∐", true), ("bigcup", "⋃", true),
+    ("bigcap", "⋂", true), ("bigoplus", "⨁", true), ("bigotimes", "⨂", true), ("bigvee", "⋁", true),
+    ("bigwedge", "⋀", true), ("int", "∫", false), ("iint", "∬", false), ("iiint", "∭", false),
+    ("oint", "∮", false),
+];
+
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh", "log",
+    "ln", "lg", "exp", "det", "dim", "ker", "deg", "gcd", "arg", "hom", "Pr",
+];
+
+/// Functions that take their scripts below them when displayed, like
+/// large operators.
+const LIMIT_FUNCTIONS: &[(&str, &str)] = &[
+    ("lim", "lim"), ("liminf", "lim inf"), ("limsup", "lim sup"), ("max", "max"), ("min", "min"),
+    ("sup", "sup"), ("inf", "inf"),
+];
+
+const ACCENTS: &[(&str, &str, bool)] = &[
+    ("hat", "^", false), ("widehat", "^", false), ("bar", "¯", false), ("overline", "‾", false),
+    ("vec", "→", false), ("overrightarrow", "→", false), ("dot", "˙", false), ("ddot", "¨", false),
+    ("tilde", "~", false), ("widetilde", "~", false), ("check", "ˇ", false), ("breve", "˘", false),
+    ("underline", "_", true),
+];
+
+const SPACES: &[(&str, &str)] = &[
+    (",", "0.1667em"), (":", "0.2222em"), (">", "0.2222em"), (";", "0.2778em"), ("!", "-0.1667em"),
+    (" ", "0.25em"), ("quad", "1em"), ("qquad", "2em"),
+];
+
+const DELIMITERS: &[(&str, &str)] = &[
+    ("{", "{"), ("}", "}"), ("|", "‖"), ("langle", "⟨"), ("rangle", "⟩"), ("lfloor", "⌊"), ("rfloor", "⌋"),
+    ("lceil", "⌈"), ("rceil", "⌉"), ("vert", "|"), ("lvert", "|"), ("rvert", "|"), ("Vert", "‖"),
+    ("lVert", "‖"), ("rVert", "‖"),
+];
+
+/// Environments and the fences around them.
+const ENVIRONMENTS: &[(&str, &str, &str)] = &[
+    ("matrix", "", ""), ("pmatrix", "(", ")"), ("bmatrix", "[", "]"), ("Bmatrix", "{", "}"),
+    ("vmatrix", "|", "|"), ("Vmatrix", "‖", "‖"), ("cases", "{", ""), ("aligned", "", ""),
+    ("gathered", "", ""),
+];
+
+fn lookup<'a>(table: &[(&str, &'a str)], name: &str) -> Option<&'a str> {
+    table.iter().find(|(known, _)| *known == name).map(|(_, value)| *value)
+}
+
+#[derive(Clone, Copy)]
+enum Variant {
+    Upright,
+    Bold,
+    Script,
+    Fraktur,
+    DoubleStruck,
+    SansSerif,
+    Monospace,
+}
+
+impl Variant {
+    fn of(command: &str) -> Option<Variant> {
+        match command {
+            "mathrm" => Some(Variant::Upright),
+            "mathbf" => Some(Variant::Bold),
+            "mathcal" | "mathscr" => Some(Variant::Script),
+            "mathfrak" => Some(Variant::Fraktur),
+            "mathbb" => Some(Variant::DoubleStruck),
+            "mathsf" => Some(Variant::SansSerif),
+            "mathtt" => Some(Variant::Monospace),
+            _ => None,
+        }
+    }
+
+    /// The letter or digit from the mathematical alphanumeric symbols
+    /// block. Some letters were in unicode before that block and are left
+    /// out of it.
+    fn apply(self, c: char) -> char {
+        let exception = match (self, c) {
+            (Variant::Script, 'B') => Some('ℬ'),
+            (Variant::Script, 'E') => Some('ℰ'),
+            (Variant::Script, 'F') => Some('ℱ'),
+            (Variant::Script, 'H') => Some('ℋ'),
+            (Variant::Script, 'I') => Some('ℐ'),
+            (Variant::Script, 'L') => Some('ℒ'),
+            (Variant::Script, 'M') => Some('ℳ'),
+            (Variant::Script, 'R') => Some('ℛ'),
+            (Variant::Script, 'e') => Some('ℯ'),
+            (Variant::Script, 'g') => Some('ℊ'),
+            (Variant::Script, 'o') => Some('ℴ'),
+            (Variant::Fraktur, 'C') => Some('ℭ'),
+            (Variant::Fraktur, 'H') => Some('ℌ'),
+            (Variant::Fraktur, 'I') => Some('ℑ'),
+            (Variant::Fraktur, 'R') => Some('ℜ'),
+            (Variant::Fraktur, 'Z') => Some('ℨ'),
+            (Variant::DoubleStruck, 'C') => Some('ℂ'),
+            (Variant::DoubleStruck, 'H') => Some('ℍ'),
+            (Variant::DoubleStruck, 'N') => Some('ℕ'),
+            (Variant::DoubleStruck, 'P') => Some('ℙ'),
+            (Variant::DoubleStruck, 'Q') => Some('ℚ'),
+            (Variant::DoubleStruck, 'R') => Some('ℝ'),
+            (Variant::DoubleStruck, 'Z') => Some('ℤ'),
+            _ => None,
+        };
+
+        let (capitals, digits) = match self {
+            Variant::Upright => return c,
+            Variant::Bold => (0x1d400, Some(0x1d7ce)),
+            Variant::Script => (0x1d49c, None),
+            Variant::Fraktur => (0x1d504, None),
+            Variant::DoubleStruck => (0x1d538, Some(0x1d7d8)),
+            Variant::SansSerif => (0x1d5a0, Some(0x1d7e2)),
+            Variant::Monospace => (0x1d670, Some(0x1d7f6)),
+        };
+
+        let code = match c {
+            'A'..='Z' => Some(capitals + (c as u32 - 'A' as u32)),
+            'a'..='z' => Some(capitals + 26 + (c as u32 - 'a' as u32)),
+            '0'..='9' => digits.map(|digits| digits + (c as u32 - '0' as u32)),
+            _ => None,
+        };
+
+        exception
+            .or_else(|| code.and_then(char::from_u32))
+            .unwrap_or(c)
+    }
+}
+
+enum Node {
+    Identifier { text: String, upright: bool },
+    Number(String),
+    Operator { text: String, large: bool, limits: bool, fence: bool },
+    Text(String),
+    Space(&'static str),
+    Row(Vec<Node>),
+    Fraction { numerator: Box<Node>, denominator: Box<Node>, line: bool },
+    Root { radicand: Box<Node>, index: Option<Box<Node>> },
+    Scripts { base: Box<Node>, sub: Option<Box<Node>>, sup: Option<Box<Node>> },
+    Accent { base: Box<Node>, accent: &'static str, under: bool },
+    Table { rows: Vec<Vec<Node>>, align: &'static str },
+}
+
+impl Node {
+    fn operator(text: &str) -> Node {
+        Node::Operator { text: text.into(), large: false, limits: false, fence: false }
+    }
+
+    fn fence(text: &str) -> Node {
+        Node::Operator { text: text.into(), large: false, limits: false, fence: true }
+    }
+
+    fn restyle(&mut self, variant: Variant) {
+        match self {
+            Node::Identifier { text, upright } => {
+                *text = text.chars().map(|c| variant.apply(c)).collect();
+                *upright = true;
+            },
+            Node::Number(text) => *text = text.chars().map(|c| variant.apply(c)).collect(),
+            Node::Row(nodes) => nodes.iter_mut().for_each(|node| node.restyle(variant)),
+            Node::Scripts { base, .. } | Node::Accent { base, .. } => base.restyle(variant),
+            _ => {},
+        }
+    }
+
+    fn render(&self, mathml: &mut String) {
+        match self {
+            Node::Identifier { text, upright } if *upright && text.chars().count() == 1 =>
+                mathml.push_str(&format!("<mi mathvariant=\"normal\">{}</mi>", escape(text))),
+            Node::Identifier { text, .. } => mathml.push_str(&format!("<mi>{}</mi>", escape(text))),
+            Node::Number(text) => mathml.push_str(&format!("<mn>{}</mn>", escape(text))),
+            Node::Operator { text, large, limits, fence } => {
+                let mut attributes = String::new();
+
+                for (set, attribute) in [(*large, " largeop=\"true\""), (*limits, " movablelimits=\"true\""), (*fence, " fence=\"true\" stretchy=\"true\"")] {
+                    if set {
+                        attributes.push_str(attribute);
+                    }
+                }
+
+                mathml.push_str(&format!("<mo{}>{}</mo>", attributes, escape(text)));
+            },
+            Node::Text(text) => mathml.push_str(&format!("<mtext>{}</mtext>", escape(text))),
+            Node::Space(width) => mathml.push_str(&format!("<mspace width=\"{}\"/>", width)),
+            Node::Row(nodes) if nodes.len() == 1 => nodes[0].render(mathml),
+            Node::Row(nodes) => {
+                mathml.push_str("<mrow>");
+                nodes.iter().for_each(|node| node.render(mathml));
+                mathml.push_str("</mrow>");
+            },
+            Node::Fraction { numerator, denominator, line } => {
+                mathml.push_str(if *line { "<mfrac>" } else { "<mfrac linethickness=\"0\">" });
+                numerator.render(mathml);
+                denominator.render(mathml);
+                mathml.push_str("</mfrac>");
+            },
+            Node::Root { radicand, index: None } => {
+                mathml.push_str("<msqrt>");
+                radicand.render(mathml);
+                mathml.push_str("</msqrt>");
+            },
+            Node::Root { radicand, index: Some(index) } => {
+                mathml.push_str("<mroot>");
+                radicand.render(mathml);
+                index.render(mathml);
+                mathml.push_str("</mroot>");
+            },
+            Node::Scripts { base, sub, sup } => {
+                let limits = matches!(**base, Node::Operator { limits: true, .. });
+
+                let element = match (sub.is_some(), sup.is_some(), limits) {
+                    (true, true, true) => "munderover",
+                    (true, false, true) => "munder",
+                    (false, _, true) => "mover",
+                    (true, true, false) => "msubsup",
+                    (true, false, false) => "msub",
+                    (false, _, false) => "msup",
+                };
+
+                mathml.push_str(&format!("<{}>", element));
+                base.render(mathml);
+                sub.iter().chain(sup).for_each(|script| script.render(mathml));
+                mathml.push_str(&format!("</{}>", element));
+            },
+            Node::Accent { base, accent, under: false } => {
+                mathml.push_str("<mover accent=\"true\">");
+                base.render(mathml);
+                mathml.push_str(&format!("<mo>{}</mo></mover>", escape(accent)));
+            },
+            Node::Accent { base, accent, under: true } => {
+                mathml.push_str("<munder accentunder=\"true\">");
+                base.render(mathml);
+                mathml.push_str(&format!("<mo>{}</mo></munder>", escape(accent)));
+            },
+            Node::Table { rows, align } => {
+                mathml.push_str(&format!("<mtable columnalign=\"{}\">", align));
+
+                for row in rows {
+                    mathml.push_str("<mtr>");
+
+                    for cell in row {
+                        mathml.push_str("<mtd>");
+                        cell.render(mathml);
+                        mathml.push_str("</mtd>");
+                    }
+
+                    mathml.push_str("</mtr>");
+                }
+
+                mathml.push_str("</mtable>");
+            },
+        }
+    }
+}
+
+/// What ended a row of nodes.
+enum Stop {
+    End,
+    Group,
+    Bracket,
+    Cell,
+    Line,
+    Right,
+    EndEnvironment,
+}
+
+impl Stop {
+    fn unexpected(&self) -> String {
+        match self {
+            Stop::End => "is missing a closing }".into(),
+            Stop::Group => "has a } without an opening {".into(),
+            Stop::Bracket => "has a ] where it wasn't expected".into(),
+            Stop::Cell => "can only use & inside an environment such as aligned".into(),
+            Stop::Line => "can only use \\\\ inside an environment such as aligned".into(),
+            Stop::Right => "has a \\right without a \\left".into(),
+            Stop::EndEnvironment => "has an \\end without a \\begin".into(),
+        }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    position: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    /// The name of a command whose backslash was just read, either letters
+    /// or a single other character.
+    fn command_name(&mut self) -> Result<&'a str, String> {
+        let rest = &self.source[self.position..];
+
+        let length = match rest.chars().next() {
+            Some(c) if c.is_ascii_alphabetic() => rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len()),
+            Some(c) => c.len_utf8(),
+            None => return Err("ends with a lone \\".into()),
+        };
+
+        self.position += length;
+        Ok(&rest[..length])
+    }
+
+    /// Nodes up to the end of a group, cell, line or environment, whichever
+    /// comes first. `]` only ends the row for a root's index.
+    fn row(&mut self, in_brackets: bool) -> Result<(Vec<Node>, Stop), String> {
+        self.depth += 1;
+
+        if self.depth > MAX_DEPTH {
+            return Err(format!("can't be nested more than {} levels deep", MAX_DEPTH));
+        }
+
+        let mut nodes = Vec::new();
+
+        let stop = loop {
+            self.skip_whitespace();
+
+            let Some(c) = self.peek() else {
+                break Stop::End;
+            };
+
+            match c {
+                '}' => {
+                    self.position += 1;
+                    break Stop::Group;
+                },
+                ']' if in_brackets => {
+                    self.position += 1;
+                    break Stop::Bracket;
+                },
+                '&' => {
+                    self.position += 1;
+                    break Stop::Cell;
+                },
+                '^' | '_' => {
+                    self.position += 1;
+                    let script = self.argument()?;
+                    Self::attach(&mut nodes, script, c == '^')?;
+                },
+                '%' => {
+                    let rest = &self.source[self.position..];
+                    self.position += rest.find('\n').unwrap_or(rest.len());
+                },
+                '\\' => {
+                    let start = self.position;
+                    self.position += 1;
+
+                    match self.command_name()? {
+                        "\\" => break Stop::Line,
+                        "right" => break Stop::Right,
+                        "end" => break Stop::EndEnvironment,
+                        _ => {
+                            self.position = start;
+                            nodes.push(self.atom()?);
+                        },
+                    }
+                },
+                _ => nodes.push(self.atom()?),
+            }
+        };
+
+        self.depth -= 1;
+        Ok((nodes, stop))
+    }
+
+    fn attach(nodes: &mut Vec<Node>, script: Node, superscript: bool) -> Result<(), String> {
+        let base = nodes.pop().unwrap_or(Node::Row(Vec::new()));
+
+        let (base, mut sub, mut sup) = match base {
+            Node::Scripts { base, sub, sup } => (base, sub, sup),
+            base => (Box::new(base), None, None),
+        };
+
+        let slot = if superscript { &mut sup } else { &mut sub };
+
+        if slot.is_some() {
+            return Err(format!("has a double {}", if superscript { "superscript" } else { "subscript" }));
+        }
+
+        *slot = Some(Box::new(script));
+        nodes.push(Node::Scripts { base, sub, sup });
+
+        Ok(())
+    }
+
+    fn group(&mut self) -> Result<Node, String> {
+        match self.row(false)? {
+            (nodes, Stop::Group) => Ok(Node::Row(nodes)),
+            (_, stop) => Err(stop.unexpected()),
+        }
+    }
+
+    /// A `{group}` or a single token, as taken by scripts and commands.
+    fn argument(&mut self) -> Result<Node, String> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some('{') => {
+                self.position += 1;
+                self.group()
+            },
+            Some('}' | '&' | '^' | '_') | None => Err("is missing an argument".into()),
+            Some(_) => self.atom(),
+        }
+    }
+
+    /// The raw text of a `{group}`, for commands that take text.
+    fn text_argument(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+
+        if self.next_char() != Some('{') {
+            return Err("needs text in braces after \\text and similar commands".into());
+        }
+
+        let mut text = String::new();
+        let mut depth = 0;
+
+        loop {
+            match self.next_char() {
+                None => return Err(Stop::End.unexpected()),
+                Some('\\') => match self.next_char() {
+                    Some(c) if !c.is_ascii_alphabetic() => text.push(c),
+                    Some(c) => {
+                        text.push('\\');
+                        text.push(c);
+                    },
+                    None => return Err(Stop::End.unexpected()),
+                },
+                Some('{') => {
+                    depth += 1;
+                    text.push('{');
+                },
+                Some('}') if depth == 0 => return Ok(text),
+                Some('}') => {
+                    depth -= 1;
+                    text.push('}');
+                },
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    /// The delimiter after `\left`, `\right` and `\big`, `None` for `.`.
+    fn delimiter(&mut self) -> Result<Option<String>, String> {
+        self.skip_whitespace();
+
+        match self.next_char() {
+            Some('.') => Ok(None),
+            Some(c) if "()[]|/".contains(c) => Ok(Some(c.to_string())),
+            Some('\\') => {
+                let name = self.command_name()?;
+                lookup(DELIMITERS, name)
+                    .map(|delimiter| Some(delimiter.to_string()))
+                    .ok_or_else(|| format!("can't use \\{} as a delimiter", name))
+            },
+            _ => Err("needs a delimiter such as ( or [ after \\left and \\right".into()),
+        }
+    }
+
+    fn environment_name(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+
+        if self.peek() != Some('{') {
+            return Err("needs the environment name in braces after \\begin and \\end".into());
+        }
+
+        self.text_argument()
+    }
+
+    fn environment(&mut self) -> Result<Node, String> {
+        let name = self.environment_name()?;
+
+        let Some((_, open, close)) = ENVIRONMENTS.iter().find(|(known, ..)| *known == name) else {
+            return Err(format!("uses the unknown environment {}", name));
+        };
+
+        let mut rows = Vec::new();
+        let mut cells = Vec::new();
+
+        loop {
+            let (nodes, stop) = self.row(false)?;
+            cells.push(Node::Row(nodes));
+
+            match stop {
+                Stop::Cell => {},
+                Stop::Line => rows.push(std::mem::take(&mut cells)),
+                Stop::EndEnvironment => {
+                    let end = self.environment_name()?;
+
+                    if end != name {
+                        return Err(format!("has \\begin{{{}}} ended by \\end{{{}}}", name, end));
+                    }
+
+                    rows.push(cells);
+                    break;
+                },
+                Stop::End => return Err(format!("is missing \\end{{{}}}", name)),
+                stop => return Err(stop.unexpected()),
+            }
+        }
+
+        // a \\ after the last row
+        if rows.len() > 1 && rows.last().is_some_and(|row| matches!(row.as_slice(), [Node::Row(nodes)] if nodes.is_empty())) {
+            rows.pop();
+        }
+
+        let align = match name.as_str() {
+            "cases" => "left",
+            "aligned" => "right left",
+            _ => "center",
+        };
+
+        let table = Node::Table { rows, align };
+
+        if open.is_empty() && close.is_empty() {
+            return Ok(table);
+        }
+
+        let mut nodes = vec![Node::fence(open), table];
+
+        if !close.is_empty() {
+            nodes.push(Node::fence(close));
+        }
+
+        Ok(Node::Row(nodes))
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let Some(c) = self.next_char() else {
+            return Err("is missing an argument".into());
+        };
+
+        let node = match c {
+            '{' => self.group()?,
+            '\\' => {
+                let name = self.command_name()?;
+                self.command(name)?
+            },
+            '0'..='9' | '.' if c != '.' || self.peek().is_some_and(|c| c.is_ascii_digit()) => {
+                let start = self.position - 1;
+                let rest = &self.source[start..];
+                let length = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+                self.position = start + length;
+
+                Node::Number(rest[..length].into())
+            },
+            '~' => Node::Space("0.25em"),
+            '$' => return Err("can't contain $, leave out the delimiters around the math".into()),
+            '#' => return Err("can't contain #".into()),
+            '-' => Node::operator("−"),
+            '*' => Node::operator("∗"),
+            '\'' => Node::operator("′"),
+            c if c.is_alphabetic() => Node::Identifier { text: c.into(), upright: false },
+            c => Node::operator(&c.to_string()),
+        };
+
+        Ok(node)
+    }
+
+    fn command(&mut self, name: &str) -> Result<Node, String> {
+        if let Some(letter) = lookup(GREEK, name).or_else(|| lookup(SYMBOLS, name)) {
+            return Ok(Node::Identifier { text: letter.into(), upright: false });
+        }
+
+        if let Some(letter) = lookup(CAPITAL_GREEK, name) {
+            return Ok(Node::Identifier { text: letter.into(), upright: true });
+        }
+
+        if let Some(operator) = lookup(OPERATORS, name) {
+            return Ok(Node::operator(operator));
+        }
+
+        if let Some((_, operator, limits)) = LARGE_OPERATORS.iter().find(|(known, ..)| *known == name) {
+            return Ok(Node::Operator { text: (*operator).into(), large: true, limits: *limits, fence: false });
+        }
+
+        if FUNCTIONS.contains(&name) {
+            return Ok(Node::Identifier { text: name.into(), upright: true });
+        }
+
+        if let Some(function) = lookup(LIMIT_FUNCTIONS, name) {
+            return Ok(Node::Operator { text: function.into(), large: false, limits: true, fence: false });
+        }
+
+        if let Some(width) = lookup(SPACES, name) {
+            return Ok(Node::Space(width));
+        }
+
+        if let Some((_, accent, under)) = ACCENTS.iter().find(|(known, ..)| *known == name) {
+            let base = self.argument()?;
+            return Ok(Node::Accent { base: Box::new(base), accent, under: *under });
+        }
+
+        if let Some(variant) = Variant::of(name) {
+            let mut node = self.argument()?;
+            node.restyle(variant);
+            return Ok(node);
+        }
+
+        let node = match name {
+            "{" | "}" | "%" | "&" | "#" | "$" | "_" => Node::operator(name),
+            "|" => Node::operator("‖"),
+            "frac" | "dfrac" | "tfrac" => Node::Fraction {
+                numerator: Box::new(self.argument()?),
+                denominator: Box::new(self.argument()?),
+                line: true,
+            },
+            "binom" => Node::Row(vec![
+                Node::fence("("),
+                Node::Fraction {
+                    numerator: Box::new(self.argument()?),
+                    denominator: Box::new(self.argument()?),
+                    line: false,
+                },
+                Node::fence(")"),
+            ]),
+            "sqrt" => {
+                self.skip_whitespace();
+
+                let index = match self.peek() {
+                    Some('[') => {
+                        self.position += 1;
+
+                        match self.row(true)? {
+                            (nodes, Stop::Bracket) => Some(Box::new(Node::Row(nodes))),
+                            (_, Stop::End) => return Err("is missing the ] after the index of \\sqrt".into()),
+                            (_, stop) => return Err(stop.unexpected()),
+                        }
+                    },
+                    _ => None,
+                };
+
+                Node::Root { radicand: Box::new(self.argument()?), index }
+            },
+            "text" | "textrm" | "textit" | "textbf" | "mbox" => Node::Text(self.text_argument()?),
+            "operatorname" => Node::Identifier { text: self.text_argument()?, upright: true },
+            "mathit" => self.argument()?,
+            "left" => {
+                let open = self.delimiter()?;
+
+                let (nodes, stop) = self.row(false)?;
+
+                if !matches!(stop, Stop::Right) {
+                    return Err(match stop {
+                        Stop::End => "has a \\left without a \\right".into(),
+                        stop => stop.unexpected(),
+                    });
+                }
+
+                let close = self.delimiter()?;
+
+                let mut row = Vec::with_capacity(nodes.len() + 2);
+                row.extend(open.as_deref().map(Node::fence));
+                row.extend(nodes);
+                row.extend(close.as_deref().map(Node::fence));
+
+                Node::Row(row)
+            },
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" | "biggl" | "biggr" | "Biggl" | "Biggr" =>
+                self.delimiter()?.as_deref().map_or(Node::Row(Vec::new()), Node::operator),
+            "begin" => self.environment()?,
+            _ => return Err(format!("uses the unknown command \\{}", name)),
+        };
+
+        Ok(node)
+    }
+}
+
+/// MathML for LaTeX math. `display` math is set on its own line, other
+/// math runs in with the text around it. The source is kept as an
+/// annotation so it can be copied back out.
+pub fn to_mathml(latex: &str, display: bool) -> Result<String, String> {
+    let mut parser = Parser { source: latex, position: 0, depth: 0 };
+
+    let nodes = match parser.row(false)? {
+        (nodes, Stop::End) => nodes,
+        (_, stop) => return Err(stop.unexpected()),
+    };
+
+    let mut mathml = String::from(if display { "<math display=\"block\">" } else { "<math>" });
+    mathml.push_str("<semantics>");
+    Node::Row(nodes).render(&mut mathml);
+    mathml.push_str(&format!("<annotation encoding=\"application/x-tex\">{}</annotation>", escape(latex)));
+    mathml.push_str("</semantics></math>");
+
+    Ok(mathml)
+}
+
+pub fn validate(field: &str, latex: &str, errors: &mut FieldErrors) {
+    if latex.len() > MAX_MATH_LENGTH {
+        errors.push(FieldError::new(field, &format!("can't have more than {} characters", MAX_MATH_LENGTH)));
+        return;
+    }
+
+    if let Err(message) = to_mathml(latex, false) {
+        errors.push(FieldError::new(field, &format!("isn't valid LaTeX, it {}", message)));
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The MathML of `latex` without the wrapping and the annotation.
+    fn body(latex: &str) -> String {
+        let mathml = to_mathml(latex, false).unwrap();
+        let start = "<math><semantics>".len();
+        let end = mathml.find("<annotation").unwrap();
+
+        mathml[start..end].into()
+    }
+
+    #[test]
+    fn fractions() {
+        assert_eq!(body("\\frac{a}{b}"), "<mfrac><mi>a</mi><mi>b</mi></mfrac>");
+        assert_eq!(body("\\frac{1}{x+1}"), "<mfrac><mn>1</mn><mrow><mi>x</mi><mo>+</mo><mn>1</mn></mrow></mfrac>");
+    }
+
+    #[test]
+    fn scripts() {
+        assert_eq!(body("x_i"), "<msub><mi>x</mi><mi>i</mi></msub>");
+        assert_eq!(body("x^2"), "<msup><mi>x</mi><mn>2</mn></msup>");
+        assert_eq!(body("x_i^2"), "<msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup>");
+        assert_eq!(body("x^2_i"), "<msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup>");
+    }
+
+    #[test]
+    fn roots() {
+        assert_eq!(body("\\sqrt{x}"), "<msqrt><mi>x</mi></msqrt>");
+        assert_eq!(body("\\sqrt[3]{x}"), "<mroot><mi>x</mi><mn>3</mn></mroot>");
+    }
+
+    #[test]
+    fn greek_letters() {
+        assert_eq!(body("\\alpha"), "<mi>α</mi>");
+        assert_eq!(body("\\Omega"), "<mi mathvariant=\"normal\">Ω</mi>");
+    }
+
+    #[test]
+    fn matrices() {
+        assert_eq!(
+            body("\\begin{pmatrix} a & b \\\\ c & d \\end{pmatrix}"),
+            "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo>\
+             <mtable columnalign=\"center\"><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr>\
+             <mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr></mtable>\
+             <mo fence=\"true\" stretchy=\"true\">)</mo></mrow>",
+        );
+    }
+
+    #[test]
+    fn display_math_is_a_block() {
+        assert!(to_mathml("x", true).unwrap().starts_with("<math display=\"block\">"));
+    }
+
+    #[test]
+    fn unbalanced_braces_are_errors() {
+        assert_eq!(to_mathml("{a", false), Err(String::from("is missing a closing }")));
+        assert_eq!(to_mathml("a}", false), Err(String::from("has a } without an opening {")));
+        assert_eq!(to_mathml("\\frac{a}", false), Err(String::from("is missing an argument")));
+        assert_eq!(to_mathml("\\text{a", false), Err(String::from("is missing a closing }")));
+        assert_eq!(to_mathml("\\left( x", false), Err(String::from("has a \\left without a \\right")));
+        assert!(to_mathml(&"{".repeat(1000), false).is_err());
+    }
+
+    #[test]
+    fn unknown_commands_are_errors() {
+        assert_eq!(to_mathml("\\foo", false), Err(String::from("uses the unknown command \\foo")));
+        assert_eq!(to_mathml("\\begin{foo}x\\end{foo}", false), Err(String::from("uses the unknown environment foo")));
+        assert_eq!(to_mathml("\\", false), Err(String::from("ends with a lone \\")));
+    }
+
+    #[test]
+    fn validate_reports_errors_on_the_field() {
+        let mut errors = FieldErrors::new();
+        validate("content", "\\foo", &mut errors);
+        assert!(!errors.empty());
+
+        let mut errors = FieldErrors::new();
+        validate("content", "\\frac{a}{b}", &mut errors);
+        assert!(errors.empty());
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        assert_eq!(body("\\operatorname{x<y&\"}"), "<mi>x&lt;y&amp;&quot;</mi>");
+        assert_eq!(body("\\text{a < b & \"c\"}"), "<mtext>a &lt; b &amp; &quot;c&quot;</mtext>");
+        assert_eq!(body("a<b"), "<mrow><mi>a</mi><mo>&lt;</mo><mi>b</mi></mrow>");
+
+        let mathml = to_mathml("\\text{<script>}", false).unwrap();
+        assert!(!mathml.contains("<script>"));
+        assert!(mathml.contains("<annotation encoding=\"application/x-tex\">\\text{&lt;script&gt;}</annotation>"));
+    }
+}
@@ -1,3 +1,16 @@
 pub mod errors;
 pub mod validate;
 pub mod auth;
+pub mod diff;
+pub mod permission;
+pub mod slug;
+pub mod sql;
+pub mod markdown;
+pub mod zip_stream;
+pub mod html;
+pub mod highlight;
+pub mod math;
+pub mod site;
+pub mod blob_store;
+pub mod image;
+pub mod mail;
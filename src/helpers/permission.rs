@@ -0,0 +1,81 @@
+use diesel::{prelude::*, dsl::exists, select};
+
+use crate::{db::DBPooledConnection, schema::{blocks, documents, repository_members}, models::member::MemberRole};
+
+pub struct Permission;
+
+impl Permission {
+    /// The role of the user in the repository, None when they aren't a member.
+    pub fn role(conn: &mut DBPooledConnection, user_id: &str, repository_id: &str) -> Option<MemberRole> {
+        repository_members::table
+            .filter(repository_members::repository_id.eq(repository_id))
+            .filter(repository_members::user_id.eq(user_id))
+            .select(repository_members::role)
+            .get_result::<MemberRole>(conn)
+            .ok()
+    }
+
+    /// IDs of every repository whose content the user is allowed to read.
+    pub fn readable_repository_ids(conn: &mut DBPooledConnection, user_id: &str) -> Vec<String> {
+        repository_members::table
+            .filter(repository_members::user_id.eq(user_id))
+            .select(repository_members::repository_id)
+            .get_results::<String>(conn)
+            .unwrap_or_default()
+    }
+
+    pub fn can_read_repository(conn: &mut DBPooledConnection, user_id: &str, repository_id: &str) -> bool {
+        Self::role(conn, user_id, repository_id).is_some()
+    }
+
+    pub fn can_read_document(conn: &mut DBPooledConnection, user_id: &str, document_id: &str) -> bool {
+        let repository_ids = Self::readable_repository_ids(conn, user_id);
+
+        let readable = documents::table
+            .filter(documents::id.eq(document_id))
+            .filter(documents::repository_id.eq_any(repository_ids));
+
+        select(exists(readable))
+            .get_result::<bool>(conn)
+            .unwrap_or(false)
+    }
+
+    /// Whether the user can change the documents of the repository.
+    pub fn can_write_repository(conn: &mut DBPooledConnection, user_id: &str, repository_id: &str) -> bool {
+        Self::role(conn, user_id, repository_id).is_some_and(MemberRole::can_edit)
+    }
+
+    /// Whether the user can publish the repository and manage its collaborators.
+    pub fn can_manage_repository(conn: &mut DBPooledConnection, user_id: &str, repository_id: &str) -> bool {
+        Self::role(conn, user_id, repository_id).is_some_and(MemberRole::can_manage)
+    }
+
+    pub fn can_write_document(conn: &mut DBPooledConnection, user_id: &str, document_id: &str) -> bool {
+        let writable = documents::table
+            .filter(documents::id.eq(document_id))
+            .filter(documents::repository_id.eq_any(Self::writable_repository_ids(user_id)));
+
+        select(exists(writable))
+            .get_result::<bool>(conn)
+            .unwrap_or(false)
+    }
+
+    pub fn can_write_block(conn: &mut DBPooledConnection, user_id: &str, block_id: &str) -> bool {
+        let writable = blocks::table
+            .inner_join(documents::table)
+            .filter(blocks::id.eq(block_id))
+            .filter(documents::repository_id.eq_any(Self::writable_repository_ids(user_id)));
+
+        select(exists(writable))
+            .get_result::<bool>(conn)
+            .unwrap_or(false)
+    }
+
+    fn writable_repository_ids(user_id: &str) -> repository_members::BoxedQuery<'_, diesel::pg::Pg, diesel::sql_types::Bpchar> {
+        repository_members::table
+            .filter(repository_members::user_id.eq(user_id))
+            .filter(repository_members::role.eq_any(MemberRole::EDITING))
+            .select(repository_members::repository_id)
+            .into_boxed()
+    }
+}
@@ -0,0 +1,408 @@
+use std::{collections::HashMap, env};
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::models::{repository::Repository, document::{self, Document}, block::{Block, BlockContent}};
+
+use super::html::{escape, RenderedHtml};
+
+pub const FEED_LENGTH: i64 = 20;
+
+pub const STYLE: &str = concat!(
+    "body{margin:0;font-family:system-ui,sans-serif;line-height:1.6;color:#222}",
+    "header{padding:1rem 2rem;border-bottom:1px solid #ddd}",
+    "header a{color:inherit;font-weight:600;text-decoration:none}",
+    ".site{display:flex;gap:2rem;padding:1rem 2rem}",
+    ".site-nav{flex:0 0 14rem}",
+    ".site-nav ul{list-style:none;padding:0}",
+    ".site-nav ul ul{padding-left:1rem}",
+    ".site-nav a[aria-current]{font-weight:600}",
+    ".breadcrumbs ol{display:flex;flex-wrap:wrap;gap:.5rem;list-style:none;padding:0;color:#555}",
+    ".breadcrumbs li+li::before{content:\"/\";margin-right:.5rem}",
+    "main{flex:1;max-width:48rem}",
+    "img{max-width:100%;height:auto}",
+    "figcaption{color:#555;font-size:.9em}",
+    "blockquote{margin:1rem 0;padding:0 1rem;border-left:.25rem solid #ddd;color:#555}",
+    "pre{padding:1rem;overflow:auto;background:#f6f8fa;border-radius:.25rem}",
+    "math[display=block]{margin:1rem 0;overflow-x:auto}",
+    ".embed-unavailable{color:#555;font-style:italic}",
+    ".callout{margin:1rem 0;padding:0 1rem;border-left:.25rem solid #0969da}",
+    ".callout-tip{border-color:#1a7f37}",
+    ".callout-important{border-color:#8250df}",
+    ".callout-warning{border-color:#9a6700}",
+    ".callout-caution{border-color:#cf222e}",
+    ".callout-title{font-weight:600}",
+    ".todo{list-style:none}",
+    "table{border-collapse:collapse;margin:1rem 0}",
+    "th,td{padding:.25rem .75rem;border:1px solid #ddd;text-align:left}",
+    "td.number{text-align:right}",
+    ".search-results{list-style:none;padding:0}",
+);
+
+/// Searches the static build's index in the browser. Results are built
+/// with text nodes so nothing from the index is parsed as html.
+pub const SEARCH_SCRIPT: &str = r#"(function () {
+  var list = document.getElementById("search-results");
+  if (!list) return;
+
+  var query = new URLSearchParams(location.search).get("q") || "";
+  var terms = query.toLowerCase().split(/\s+/).filter(Boolean);
+  document.querySelector("input[name=q]").value = query;
+  if (!terms.length) return;
+
+  fetch("search-index.json").then(function (response) {
+    return response.json();
+  }).then(function (entries) {
+    entries.map(function (entry) {
+      var title = entry.title.toLowerCase();
+      var text = (entry.description || "").toLowerCase() + "\n" + entry.text.toLowerCase();
+      var score = 0;
+      for (var i = 0; i < terms.length; i++) {
+        if (title.indexOf(terms[i]) >= 0) score += 2;
+        else if (text.indexOf(terms[i]) >= 0) score += 1;
+        else return null;
+      }
+      return { entry: entry, score: score };
+    }).filter(Boolean).sort(function (a, b) {
+      return b.score - a.score;
+    }).forEach(function (hit) {
+      var item = document.createElement("li");
+      var link = document.createElement("a");
+      link.href = hit.entry.url;
+      link.textContent = hit.entry.title;
+      item.appendChild(link);
+      if (hit.entry.description) {
+        item.appendChild(document.createTextNode(" - " + hit.entry.description));
+      }
+      list.appendChild(item);
+    });
+    if (!list.children.length) list.textContent = "No results";
+  });
+})();
+"#;
+
+/// Where pages link to, the live site under /p/ or a static build that
+/// only uses relative links so it can be hosted anywhere. Documents are
+/// at their path, in folders named after the documents they are nested
+/// under.
+#[derive(Clone, Copy)]
+pub enum SiteLinks {
+    Live,
+    /// Links from a page `depth` folders down from the root of the build.
+    Static { depth: usize },
+}
+
+impl SiteLinks {
+    /// Static links from the page of the document at `path`.
+    pub fn static_from(path: &str) -> SiteLinks {
+        SiteLinks::Static { depth: path.matches('/').count() }
+    }
+
+    fn index(self, repository: &Repository) -> String {
+        match self {
+            SiteLinks::Live => repository.site_path(),
+            SiteLinks::Static { depth } => format!("{}index.html", "../".repeat(depth)),
+        }
+    }
+
+    pub fn document(self, repository: &Repository, path: &str) -> String {
+        match self {
+            SiteLinks::Live => format!("{}/{}", repository.site_path(), path),
+            SiteLinks::Static { depth } => format!("{}{}.html", "../".repeat(depth), path),
+        }
+    }
+
+    fn head(self, repository: &Repository) -> String {
+        match self {
+            SiteLinks::Live => format!(
+                "<link rel=\"alternate\" type=\"application/atom+xml\" href=\"{}/feed.atom\">\n<style>{}</style>\n",
+                escape(&repository.site_path()),
+                STYLE,
+            ),
+            SiteLinks::Static { depth } => format!(
+                "<link rel=\"stylesheet\" href=\"{0}style.css\">\n<script src=\"{0}search.js\" defer></script>\n",
+                "../".repeat(depth),
+            ),
+        }
+    }
+
+    fn search_form(self) -> String {
+        match self {
+            SiteLinks::Live => String::new(),
+            SiteLinks::Static { depth } => format!(
+                "<form action=\"{}search.html\"><input type=\"search\" name=\"q\" placeholder=\"Search\" aria-label=\"Search\"></form>",
+                "../".repeat(depth),
+            ),
+        }
+    }
+}
+
+/// Absolute url the public site is served from, used in the sitemap and
+/// feed where relative links aren't allowed.
+pub fn base_url() -> String {
+    env::var("PUBLIC_URL")
+        .unwrap_or_else(|_| "http://localhost:8080".into())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Documents of a site along with where they are, so pages can link to
+/// them and mirror how they are nested.
+pub struct SiteDocuments<'a> {
+    pub documents: &'a [Document],
+    paths: HashMap<String, String>,
+    children: HashMap<Option<&'a str>, Vec<&'a Document>>,
+}
+
+impl<'a> SiteDocuments<'a> {
+    /// `documents` has to hold every document of the repository, as
+    /// returned by `DocumentOperation::find_by_repository`.
+    pub fn new(documents: &'a [Document]) -> SiteDocuments<'a> {
+        let mut children: HashMap<_, Vec<_>> = HashMap::new();
+
+        for document in documents {
+            children.entry(document.parent_id.as_deref()).or_default().push(document);
+        }
+
+        SiteDocuments { documents, paths: document::paths(documents), children }
+    }
+
+    pub fn path<'b>(&'b self, document: &'b Document) -> &'b str {
+        self.paths.get(&document.id).map_or(document.slug.as_str(), String::as_str)
+    }
+
+    /// The documents `document` is nested under, top level first.
+    fn ancestors(&self, document: &Document) -> Vec<&'a Document> {
+        let mut ancestors = Vec::new();
+        let mut parent_id = document.parent_id.as_deref();
+
+        while let Some(parent) = parent_id.and_then(|id| self.documents.iter().find(|document| document.id == id)) {
+            ancestors.push(parent);
+            parent_id = parent.parent_id.as_deref();
+        }
+
+        ancestors.reverse();
+        ancestors
+    }
+}
+
+fn nav_list(links: SiteLinks, repository: &Repository, site: &SiteDocuments, parent_id: Option<&str>, current: Option<&str>, nav: &mut String) {
+    let Some(documents) = site.children.get(&parent_id) else {
+        return;
+    };
+
+    nav.push_str("<ul>");
+
+    for document in documents {
+        let aria_current = match current {
+            Some(id) if id == document.id => " aria-current=\"page\"",
+            _ => "",
+        };
+
+        nav.push_str(&format!(
+            "<li><a href=\"{}\"{}>{}</a>",
+            escape(&links.document(repository, site.path(document))),
+            aria_current,
+            escape(&document.name),
+        ));
+
+        nav_list(links, repository, site, Some(&document.id), current, nav);
+        nav.push_str("</li>");
+    }
+
+    nav.push_str("</ul>");
+}
+
+fn nav(links: SiteLinks, repository: &Repository, site: &SiteDocuments, current: Option<&str>) -> String {
+    let mut nav = String::from("<nav class=\"site-nav\">");
+    nav_list(links, repository, site, None, current, &mut nav);
+    nav.push_str("</nav>\n");
+    nav
+}
+
+fn breadcrumbs(links: SiteLinks, repository: &Repository, site: &SiteDocuments, document: &Document) -> String {
+    let ancestors = site.ancestors(document);
+
+    if ancestors.is_empty() {
+        return String::new();
+    }
+
+    let mut breadcrumbs = String::from("<nav class=\"breadcrumbs\" aria-label=\"Breadcrumbs\"><ol>");
+
+    for ancestor in ancestors {
+        breadcrumbs.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>",
+            escape(&links.document(repository, site.path(ancestor))),
+            escape(&ancestor.name),
+        ));
+    }
+
+    breadcrumbs.push_str(&format!("<li aria-current=\"page\">{}</li></ol></nav>\n", escape(&document.name)));
+    breadcrumbs
+}
+
+fn layout(links: SiteLinks, title: &str, repository: &Repository, nav: &str, main: &str) -> String {
+    format!(
+        concat!(
+            "<!DOCTYPE html>\n",
+            "<html lang=\"en\">\n",
+            "<head>\n",
+            "<meta charset=\"utf-8\">\n",
+            "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n",
+            "<title>{}</title>\n",
+            "{}",
+            "</head>\n",
+            "<body>\n",
+            "<header><a href=\"{}\">{}</a>{}</header>\n",
+            "<div class=\"site\">\n",
+            "{}",
+            "<main>\n{}</main>\n",
+            "</div>\n",
+            "</body>\n",
+            "</html>\n",
+        ),
+        escape(title),
+        links.head(repository),
+        escape(&links.index(repository)),
+        escape(&repository.name),
+        links.search_form(),
+        nav,
+        main,
+    )
+}
+
+/// Landing page of a published repository listing its documents.
+pub fn index_page(links: SiteLinks, repository: &Repository, site: &SiteDocuments) -> String {
+    let mut main = format!("<h1>{}</h1>\n", escape(&repository.name));
+
+    if let Some(description) = &repository.description {
+        main.push_str(&format!("<p>{}</p>\n", escape(description)));
+    }
+
+    main.push_str("<ul>\n");
+
+    for document in site.documents {
+        main.push_str(&format!("<li><a href=\"{}\">{}</a>", escape(&links.document(repository, site.path(document))), escape(&document.name)));
+
+        if let Some(description) = &document.description {
+            main.push_str(&format!(" - {}", escape(description)));
+        }
+
+        main.push_str("</li>\n");
+    }
+
+    main.push_str("</ul>\n");
+
+    layout(links, &repository.name, repository, &nav(links, repository, site, None), &main)
+}
+
+pub fn document_page(links: SiteLinks, repository: &Repository, site: &SiteDocuments, document: &Document, rendered: &RenderedHtml) -> String {
+    let main = format!("{}{}<article>\n{}</article>\n", breadcrumbs(links, repository, site, document), rendered.toc(), rendered.body);
+    let title = format!("{} - {}", document.name, repository.name);
+
+    layout(links, &title, repository, &nav(links, repository, site, Some(&document.id)), &main)
+}
+
+/// Page of the static build that shows the results of `SEARCH_SCRIPT`.
+pub fn search_page(repository: &Repository, site: &SiteDocuments) -> String {
+    let links = SiteLinks::Static { depth: 0 };
+    let main = "<h1>Search</h1>\n<ul class=\"search-results\" id=\"search-results\"></ul>\n";
+    let title = format!("Search - {}", repository.name);
+
+    layout(links, &title, repository, &nav(links, repository, site, None), main)
+}
+
+#[derive(Serialize)]
+pub struct SearchEntry {
+    pub title: String,
+    pub url: String,
+    pub description: Option<String>,
+    pub text: String,
+}
+
+impl SearchEntry {
+    /// `path` is where the document is in the site, see `SiteDocuments`.
+    pub fn new(links: SiteLinks, repository: &Repository, path: &str, document: &Document, blocks: &[(Block, Option<BlockContent>)]) -> SearchEntry {
+        let text = blocks
+            .iter()
+            .filter_map(|(_, content)| content.as_ref()?.text())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        SearchEntry {
+            title: document.name.clone(),
+            url: links.document(repository, path),
+            description: document.description.clone(),
+            text,
+        }
+    }
+}
+
+fn rfc3339(date_time: &NaiveDateTime) -> String {
+    date_time.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+pub fn sitemap(repository: &Repository, site: &SiteDocuments) -> String {
+    let base_url = base_url();
+
+    let mut sitemap = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    ));
+
+    sitemap.push_str(&format!("<url><loc>{}{}</loc></url>\n", escape(&base_url), escape(&repository.site_path())));
+
+    for document in site.documents {
+        sitemap.push_str(&format!(
+            "<url><loc>{}{}</loc><lastmod>{}</lastmod></url>\n",
+            escape(&base_url),
+            escape(&SiteLinks::Live.document(repository, site.path(document))),
+            rfc3339(&document.updated_at),
+        ));
+    }
+
+    sitemap.push_str("</urlset>\n");
+    sitemap
+}
+
+/// Atom feed of the given documents, expected newest first.
+pub fn feed(repository: &Repository, site: &SiteDocuments, documents: &[Document]) -> String {
+    let base_url = base_url();
+    let site_url = format!("{}{}", base_url, repository.site_path());
+
+    let updated = documents
+        .first()
+        .map_or(repository.updated_at, |document| document.updated_at);
+
+    let mut feed = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+        "<feed xmlns=\"http://www.w3.org/2005/Atom\">\n",
+    ));
+
+    feed.push_str(&format!("<title>{}</title>\n", escape(&repository.name)));
+    feed.push_str(&format!("<id>{}</id>\n", escape(&site_url)));
+    feed.push_str(&format!("<link href=\"{}\"/>\n", escape(&site_url)));
+    feed.push_str(&format!("<link rel=\"self\" href=\"{}/feed.atom\"/>\n", escape(&site_url)));
+    feed.push_str(&format!("<updated>{}</updated>\n", rfc3339(&updated)));
+    feed.push_str(&format!("<author><name>{}</name></author>\n", escape(&repository.name)));
+
+    for document in documents {
+        let url = format!("{}{}", base_url, SiteLinks::Live.document(repository, site.path(document)));
+
+        feed.push_str("<entry>\n");
+        feed.push_str(&format!("<title>{}</title>\n", escape(&document.name)));
+        feed.push_str(&format!("<id>{}</id>\n", escape(&url)));
+        feed.push_str(&format!("<link href=\"{}\"/>\n", escape(&url)));
+        feed.push_str(&format!("<updated>{}</updated>\n", rfc3339(&document.updated_at)));
+
+        if let Some(description) = &document.description {
+            feed.push_str(&format!("<summary>{}</summary>\n", escape(description)));
+        }
+
+        feed.push_str("</entry>\n");
+    }
+
+    feed.push_str("</feed>\n");
+    feed
+}
@@ -0,0 +1,20 @@
+/// Turns a name into a lowercase, dash separated slug.
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    let slug = slug.trim_end_matches('-');
+
+    if slug.is_empty() {
+        return "untitled".into();
+    }
+
+    slug.into()
+}
@@ -0,0 +1,6 @@
+/// Escapes the wildcards of a `like` pattern so `text` only matches itself.
+pub fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
@@ -2,6 +2,8 @@ use std::collections::HashSet;
 
 use email_address::EmailAddress;
 
+use crate::models::block::MAX_INDENT;
+
 use super::errors::{FieldErrors, FieldError};
 
 lazy_static::lazy_static! {
@@ -59,5 +61,34 @@ impl Validate {
             errors.push(FieldError::new(field, "must contain a number or special character"));
         }
     }
-}
 
+    pub fn name(field: &str, name: &str, errors: &mut FieldErrors) {
+        if name.trim().is_empty() {
+            errors.push(FieldError::new(field, "can't be empty"));
+        }
+
+        if name.len() > 256 {
+            errors.push(FieldError::new(field, "can't have more than 256 characters"));
+        }
+    }
+
+    pub fn indent(field: &str, indent: i32, errors: &mut FieldErrors) {
+        if !(0..=MAX_INDENT).contains(&indent) {
+            errors.push(FieldError::new(field, &format!("must be between 0 and {}", MAX_INDENT)));
+        }
+    }
+
+    /// Code block languages end up in class names and markdown fences, so
+    /// only a small set of characters is allowed.
+    pub fn language(field: &str, language: &str, errors: &mut FieldErrors) {
+        if !Self::is_language(language) {
+            errors.push(FieldError::new(field, "must be 1 to 32 letters, digits or any of + # . _ -"));
+        }
+    }
+
+    pub fn is_language(language: &str) -> bool {
+        (1..=32).contains(&language.len()) && language
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+#._-".contains(c))
+    }
+}
@@ -0,0 +1,71 @@
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+use actix_web::web::Bytes;
+use tokio::sync::mpsc::Sender;
+
+/// Sink for a `ZipWriter` with `set_flush_on_finish_file` enabled. Bytes are
+/// held back until the writer flushes at the end of each file, since it
+/// seeks back to patch the file header, and then sent down the channel so
+/// the archive can be streamed while it is being built.
+pub struct ZipStream {
+    sender: Sender<io::Result<Bytes>>,
+    flushed: u64,
+    buffer: Cursor<Vec<u8>>,
+}
+
+impl ZipStream {
+    pub fn new(sender: Sender<io::Result<Bytes>>) -> ZipStream {
+        ZipStream {
+            sender,
+            flushed: 0,
+            buffer: Cursor::new(Vec::new()),
+        }
+    }
+}
+
+impl Write for ZipStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let chunk = std::mem::take(self.buffer.get_mut());
+
+        if chunk.is_empty() {
+            return Ok(());
+        }
+
+        self.flushed += chunk.len() as u64;
+        self.buffer.set_position(0);
+
+        self.sender
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "zip stream was closed"))
+    }
+}
+
+// `ZipWriter` only reads back when copying files within the archive,
+// which is limited to what hasn't been streamed yet.
+impl Read for ZipStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.buffer.read(buf)
+    }
+}
+
+impl Seek for ZipStream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => (self.flushed + self.buffer.position()) as i64 + offset,
+            SeekFrom::End(offset) => (self.flushed + self.buffer.get_ref().len() as u64) as i64 + offset,
+        };
+
+        if position < self.flushed as i64 {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "can't seek into data that was already streamed"));
+        }
+
+        self.buffer.set_position(position as u64 - self.flushed);
+
+        Ok(position as u64)
+    }
+}
@@ -0,0 +1,52 @@
+use std::{sync::{Arc, mpsc::{self, Sender}}, thread, time::Duration};
+
+use crate::{db::DBPool, helpers::blob_store::BlobStore, models::upload::UploadOperation};
+
+/// How often the queue is checked when nobody asks for it, picks up work
+/// left behind by a restart or another server.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Wakes the image worker after an upload instead of waiting for the next
+/// poll.
+#[derive(Clone)]
+pub struct ImageJobs {
+    sender: Sender<()>,
+}
+
+impl ImageJobs {
+    pub fn notify(&self) {
+        let _ = self.sender.send(());
+    }
+}
+
+/// Starts the thread that processes uploaded images. The queue is the
+/// unprocessed rows of uploads so nothing is lost when the server stops.
+pub fn start(pool: DBPool, blob_store: Arc<dyn BlobStore>) -> ImageJobs {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || loop {
+        loop {
+            let Ok(mut conn) = pool.get() else {
+                break;
+            };
+
+            match UploadOperation::process_next(&mut conn, blob_store.as_ref()) {
+                Ok(true) => continue,
+                Ok(false) => break,
+                Err(err) => {
+                    log::error!("failed to process upload: {:?}", err);
+                    break;
+                },
+            }
+        }
+
+        if let Err(mpsc::RecvTimeoutError::Disconnected) = receiver.recv_timeout(POLL_INTERVAL) {
+            return;
+        }
+
+        // one pass handles everything that arrived meanwhile
+        while receiver.try_recv().is_ok() {}
+    });
+
+    ImageJobs { sender }
+}
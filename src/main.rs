@@ -4,12 +4,16 @@ pub mod handlers;
 pub mod schemas;
 pub mod models;
 pub mod helpers;
+pub mod commands;
+pub mod jobs;
+
+use std::env;
 
 use actix_cors::Cors;
 use actix_web::{middleware::Logger, web::Data, App, HttpServer};
 use dotenv::dotenv;
 
-use crate::{db::establish_connection, handlers::register};
+use crate::{db::establish_connection, handlers::register, helpers::{blob_store, mail}};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -18,12 +22,25 @@ async fn main() -> std::io::Result<()> {
 
     let pool = establish_connection();
 
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    if commands::run(&pool, &args)? {
+        return Ok(());
+    }
+
+    let blob_store = blob_store::from_env();
+    let image_jobs = jobs::start(pool.clone(), blob_store.clone());
+    let mailer = mail::from_env();
+
     log::info!("starting HTTP server on port 8080");
     log::info!("GraphiQL playground: http://localhost:8080/graphiql");
 
     HttpServer::new(move || {
         App::new()
             .app_data(Data::new(pool.clone()))
+            .app_data(Data::from(blob_store.clone()))
+            .app_data(Data::from(mailer.clone()))
+            .app_data(Data::new(image_jobs.clone()))
             .configure(register)
             .wrap(Cors::permissive())
             .wrap(Logger::default())
@@ -0,0 +1,105 @@
+use diesel::prelude::*;
+use juniper::graphql_object;
+
+use crate::{schemas::root::Context, validation_result, db::DBPooledConnection, schema::{blocks, documents, image_blocks}};
+
+use super::{block::{Block, ImageBlock}, document::Document, repository::{Repository, RepositoryOperation, RepositoryResult}};
+
+pub struct MissingAltText {
+    pub document: Document,
+    pub block: Block,
+    pub image: ImageBlock,
+}
+
+#[graphql_object(
+    name = "MissingAltText",
+    description = "An image block without alt text",
+    context = Context
+)]
+impl MissingAltText {
+    #[graphql(description = "The document the image is in")]
+    fn document(&self) -> &Document {
+        &self.document
+    }
+
+    #[graphql(description = "The block holding the image")]
+    fn block(&self) -> &Block {
+        &self.block
+    }
+
+    #[graphql(description = "The image content of the block")]
+    fn image(&self) -> &ImageBlock {
+        &self.image
+    }
+}
+
+pub struct AccessibilityReport {
+    pub repository: Repository,
+    pub image_count: i64,
+    pub missing_alt_text: Vec<MissingAltText>,
+}
+
+#[graphql_object(
+    name = "AccessibilityReport",
+    description = "Accessibility problems in the documents of a repository",
+    context = Context
+)]
+impl AccessibilityReport {
+    #[graphql(description = "The repository the report is for")]
+    fn repository(&self) -> &Repository {
+        &self.repository
+    }
+
+    #[graphql(description = "How many image blocks the repository has")]
+    fn image_count(&self) -> i32 {
+        self.image_count as i32
+    }
+
+    #[graphql(description = "Images without alt text ordered by document then position")]
+    fn missing_alt_text(&self) -> &[MissingAltText] {
+        &self.missing_alt_text
+    }
+}
+
+validation_result!(AccessibilityReportResult, AccessibilityReport);
+
+pub struct AccessibilityOperation;
+
+impl AccessibilityOperation {
+    pub fn report(conn: &mut DBPooledConnection, repository_id: &str) -> AccessibilityReportResult {
+        let repository = match RepositoryOperation::find(conn, repository_id) {
+            RepositoryResult::Repository(repository) => repository,
+            _ => return AccessibilityReportResult::not_found("repository not found"),
+        };
+
+        let images = || image_blocks::table
+            .inner_join(blocks::table.inner_join(documents::table))
+            .filter(documents::repository_id.eq(repository_id));
+
+        let image_count = images()
+            .count()
+            .get_result::<i64>(conn);
+
+        // blank alt text is stored as null
+        let rows = images()
+            .filter(image_blocks::alt.is_null())
+            .order((documents::name.asc(), documents::id.asc(), blocks::line_number.asc()))
+            .select((image_blocks::all_columns, blocks::all_columns, documents::all_columns))
+            .get_results::<(ImageBlock, Block, Document)>(conn);
+
+        let (Ok(image_count), Ok(rows)) = (image_count, rows) else {
+            return AccessibilityReportResult::server();
+        };
+
+        let missing_alt_text = rows
+            .into_iter()
+            .map(|(image, block, document)| MissingAltText { document, block, image })
+            .collect();
+
+        AccessibilityReportResult::AccessibilityReport(AccessibilityReport {
+            repository,
+            image_count,
+            missing_alt_text,
+        })
+    }
+}
@@ -0,0 +1,1706 @@
+use std::{collections::HashMap, io::Write};
+
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Insertable, prelude::*, AsExpression, FromSqlRow, pg::{Pg, PgValue}, serialize::{self, ToSql, Output, IsNull}, deserialize::{self, FromSql}, result::{Error::{NotFound, DatabaseError}, DatabaseErrorKind::ForeignKeyViolation}};
+use juniper::{graphql_object, GraphQLEnum, GraphQLUnion};
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+
+use crate::{schemas::root::Context, validation_result, db::DBPooledConnection, schema::{blocks, documents, users, text_blocks, image_blocks, list_item_blocks, todo_blocks, quote_blocks, code_blocks, callout_blocks, divider_blocks, toggle_blocks, table_blocks, math_blocks, embed_blocks, sql_types}, helpers::{errors::{FieldErrors, FieldError}, validate::Validate, highlight::{highlight, HighlightTheme}, math}};
+
+use super::{user::{User, UserOperation, UserResult}, upload::{ImageMetadata, UploadOperation}, rich_text::{self, TextRun}, table::{self, TableColumn, TableRow}, link::LinkOperation, embed::{EmbedOperation, EmbedStatus, Reader}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, GraphQLEnum, AsExpression, FromSqlRow, Serialize, Deserialize)]
+#[diesel(sql_type = sql_types::Tag)]
+pub enum Tag {
+    H1,
+    H2,
+    H3,
+    P,
+}
+
+impl ToSql<sql_types::Tag, Pg> for Tag {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        match *self {
+            Tag::H1 => out.write_all(b"H1")?,
+            Tag::H2 => out.write_all(b"H2")?,
+            Tag::H3 => out.write_all(b"H3")?,
+            Tag::P => out.write_all(b"P")?,
+        }
+
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<sql_types::Tag, Pg> for Tag {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"H1" => Ok(Tag::H1),
+            b"H2" => Ok(Tag::H2),
+            b"H3" => Ok(Tag::H3),
+            b"P" => Ok(Tag::P),
+            _ => Err("unrecognized tag variant".into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, GraphQLEnum, AsExpression, FromSqlRow, Serialize, Deserialize)]
+#[diesel(sql_type = sql_types::ListStyle)]
+pub enum ListStyle {
+    Bulleted,
+    Numbered,
+}
+
+impl ToSql<sql_types::ListStyle, Pg> for ListStyle {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        match *self {
+            ListStyle::Bulleted => out.write_all(b"BULLETED")?,
+            ListStyle::Numbered => out.write_all(b"NUMBERED")?,
+        }
+
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<sql_types::ListStyle, Pg> for ListStyle {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"BULLETED" => Ok(ListStyle::Bulleted),
+            b"NUMBERED" => Ok(ListStyle::Numbered),
+            _ => Err("unrecognized list style variant".into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, GraphQLEnum, AsExpression, FromSqlRow, Serialize, Deserialize)]
+#[diesel(sql_type = sql_types::CalloutVariant)]
+pub enum CalloutVariant {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl CalloutVariant {
+    pub const ALL: [CalloutVariant; 5] = [
+        CalloutVariant::Note,
+        CalloutVariant::Tip,
+        CalloutVariant::Important,
+        CalloutVariant::Warning,
+        CalloutVariant::Caution,
+    ];
+
+    /// Upper case name, as used by the database and GitHub's alert syntax.
+    pub fn name(self) -> &'static str {
+        match self {
+            CalloutVariant::Note => "NOTE",
+            CalloutVariant::Tip => "TIP",
+            CalloutVariant::Important => "IMPORTANT",
+            CalloutVariant::Warning => "WARNING",
+            CalloutVariant::Caution => "CAUTION",
+        }
+    }
+}
+
+impl ToSql<sql_types::CalloutVariant, Pg> for CalloutVariant {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.name().as_bytes())?;
+
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<sql_types::CalloutVariant, Pg> for CalloutVariant {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        CalloutVariant::ALL
+            .into_iter()
+            .find(|variant| variant.name().as_bytes() == bytes.as_bytes())
+            .ok_or_else(|| "unrecognized callout variant".into())
+    }
+}
+
+/// How deep list items and to-dos can be nested.
+pub const MAX_INDENT: i32 = 8;
+
+#[derive(Queryable)]
+pub struct Block {
+    pub id: String,
+    pub document_id: String,
+    pub line_number: i32,
+    pub created_at: NaiveDateTime,
+    pub last_edited_by: Option<String>,
+    pub last_edited_at: NaiveDateTime,
+    pub parent_id: Option<String>,
+}
+
+#[graphql_object(
+    name = "Block",
+    description = "Block model",
+    context = Context
+)]
+impl Block {
+    #[graphql(description = "The blocks ID in base64 format")]
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[graphql(description = "Position of the block among the blocks with the same parent")]
+    fn line_number(&self) -> i32 {
+        self.line_number
+    }
+
+    #[graphql(description = "The block this one is nested under, null for top level blocks")]
+    fn parent(&self, context: &Context) -> Option<Block> {
+        let mut conn = context.db_pool.get().unwrap();
+        BlockOperation::find(&mut conn, self.parent_id.as_deref()?).ok()
+    }
+
+    #[graphql(description = "Blocks nested under this one in order")]
+    fn children(&self, context: &Context) -> Vec<Block> {
+        let mut conn = context.db_pool.get().unwrap();
+        BlockOperation::find_children(&mut conn, &self.id)
+    }
+
+    #[graphql(description = "The typed content of the block")]
+    fn content(&self, context: &Context) -> Option<BlockContent> {
+        let mut conn = context.db_pool.get().unwrap();
+        BlockOperation::content(&mut conn, &self.id)
+    }
+
+    #[graphql(description = "The user who last edited the block")]
+    fn last_edited_by(&self, context: &Context) -> Option<User> {
+        let mut conn = context.db_pool.get().unwrap();
+
+        match UserOperation::find(&mut conn, self.last_edited_by.as_deref()?) {
+            UserResult::User(user) => Some(user),
+            _ => None,
+        }
+    }
+
+    #[graphql(description = "DateTime for when the block was last edited")]
+    fn last_edited_at(&self) -> &NaiveDateTime {
+        &self.last_edited_at
+    }
+
+    #[graphql(description = "DateTime for when the block was created")]
+    fn created_at(&self) -> &NaiveDateTime {
+        &self.created_at
+    }
+}
+
+validation_result!(BlockResult, Block);
+
+#[derive(Queryable)]
+pub struct TextBlock {
+    pub block_id: String,
+    pub tag: Tag,
+    pub content: Option<String>,
+    pub updated_at: NaiveDateTime,
+    pub rich_text: Option<serde_json::Value>,
+}
+
+#[graphql_object(
+    name = "TextBlock",
+    description = "Heading or paragraph content of a block",
+    context = Context
+)]
+impl TextBlock {
+    #[graphql(description = "")]
+    fn tag(&self) -> Tag {
+        self.tag
+    }
+
+    #[graphql(description = "The text without formatting")]
+    fn content(&self) -> Option<&str> {
+        self.content.as_deref()
+    }
+
+    #[graphql(description = "The text split into runs with the same formatting")]
+    fn rich_text(&self) -> Vec<TextRun> {
+        self.runs()
+    }
+
+    #[graphql(description = "DateTime for when the text was last updated")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+impl TextBlock {
+    /// The formatted text, text without marks is a single plain run.
+    pub fn runs(&self) -> Vec<TextRun> {
+        if let Some(runs) = self.rich_text.clone().and_then(|value| serde_json::from_value(value).ok()) {
+            return runs;
+        }
+
+        match self.content.as_deref() {
+            Some(content) if !content.is_empty() => vec![TextRun::plain(content)],
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Queryable)]
+pub struct ImageBlock {
+    pub block_id: String,
+    pub url: Option<String>,
+    pub updated_at: NaiveDateTime,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub format: Option<String>,
+    pub srcset: Option<String>,
+    pub alt: Option<String>,
+    pub caption: Option<String>,
+}
+
+#[graphql_object(
+    name = "ImageBlock",
+    description = "Image content of a block",
+    context = Context
+)]
+impl ImageBlock {
+    #[graphql(description = "")]
+    fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    #[graphql(description = "Width in pixels, only known for processed uploads")]
+    fn width(&self) -> Option<i32> {
+        self.width
+    }
+
+    #[graphql(description = "Height in pixels, only known for processed uploads")]
+    fn height(&self) -> Option<i32> {
+        self.height
+    }
+
+    #[graphql(description = "Format of the image at url, only known for processed uploads")]
+    fn format(&self) -> Option<&str> {
+        self.format.as_deref()
+    }
+
+    #[graphql(description = "Responsive WebP variants in html srcset syntax")]
+    fn srcset(&self) -> Option<&str> {
+        self.srcset.as_deref()
+    }
+
+    #[graphql(description = "Text describing the image for screen readers, null when missing")]
+    fn alt(&self) -> Option<&str> {
+        self.alt.as_deref()
+    }
+
+    #[graphql(description = "Caption shown below the image")]
+    fn caption(&self) -> Option<&str> {
+        self.caption.as_deref()
+    }
+
+    #[graphql(description = "DateTime for when the image was last updated")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+#[derive(Queryable)]
+pub struct ListItemBlock {
+    pub block_id: String,
+    pub style: ListStyle,
+    pub indent: i32,
+    pub content: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "ListItemBlock",
+    description = "Item of a bulleted or numbered list",
+    context = Context
+)]
+impl ListItemBlock {
+    #[graphql(description = "")]
+    fn style(&self) -> ListStyle {
+        self.style
+    }
+
+    #[graphql(description = "How many levels the item is nested, from 0")]
+    fn indent(&self) -> i32 {
+        self.indent
+    }
+
+    #[graphql(description = "")]
+    fn content(&self) -> Option<&str> {
+        self.content.as_deref()
+    }
+
+    #[graphql(description = "DateTime for when the item was last updated")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+#[derive(Queryable)]
+pub struct TodoBlock {
+    pub block_id: String,
+    pub checked: bool,
+    pub indent: i32,
+    pub content: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "TodoBlock",
+    description = "To-do item that can be checked off",
+    context = Context
+)]
+impl TodoBlock {
+    #[graphql(description = "Whether the to-do is done")]
+    fn checked(&self) -> bool {
+        self.checked
+    }
+
+    #[graphql(description = "How many levels the to-do is nested, from 0")]
+    fn indent(&self) -> i32 {
+        self.indent
+    }
+
+    #[graphql(description = "")]
+    fn content(&self) -> Option<&str> {
+        self.content.as_deref()
+    }
+
+    #[graphql(description = "DateTime for when the to-do was last updated")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+#[derive(Queryable)]
+pub struct QuoteBlock {
+    pub block_id: String,
+    pub content: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "QuoteBlock",
+    description = "Quoted text, paragraphs are separated by blank lines",
+    context = Context
+)]
+impl QuoteBlock {
+    #[graphql(description = "")]
+    fn content(&self) -> Option<&str> {
+        self.content.as_deref()
+    }
+
+    #[graphql(description = "DateTime for when the quote was last updated")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+#[derive(Queryable)]
+pub struct CodeBlock {
+    pub block_id: String,
+    pub language: Option<String>,
+    pub content: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "CodeBlock",
+    description = "Preformatted source code",
+    context = Context
+)]
+impl CodeBlock {
+    #[graphql(description = "Name of the language the code is written in, such as rust")]
+    fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    #[graphql(description = "The code with its whitespace kept as is")]
+    fn content(&self) -> Option<&str> {
+        self.content.as_deref()
+    }
+
+    #[graphql(description = "The code as a pre element colored with inline styles, light unless another theme is given")]
+    fn highlighted_html(&self, theme: Option<HighlightTheme>) -> String {
+        let code = self.content.as_deref().unwrap_or_default();
+        highlight(self.language.as_deref(), code, theme.unwrap_or(HighlightTheme::Light)).to_string()
+    }
+
+    #[graphql(description = "DateTime for when the code was last updated")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+#[derive(Queryable)]
+pub struct MathBlock {
+    pub block_id: String,
+    pub content: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "MathBlock",
+    description = "Equation set on its own line",
+    context = Context
+)]
+impl MathBlock {
+    #[graphql(description = "LaTeX source of the equation")]
+    fn content(&self) -> Option<&str> {
+        self.content.as_deref()
+    }
+
+    #[graphql(description = "The equation as a MathML math element")]
+    fn mathml(&self) -> Option<String> {
+        self.content.as_deref().and_then(|content| math::to_mathml(content, true).ok())
+    }
+
+    #[graphql(description = "DateTime for when the equation was last updated")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+#[derive(Queryable)]
+pub struct EmbedBlock {
+    pub block_id: String,
+    pub source_block_id: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "EmbedBlock",
+    description = "Another block shown live, so edits to it show up here too",
+    context = Context
+)]
+impl EmbedBlock {
+    #[graphql(description = "ID of the embedded block, null once it was deleted")]
+    fn source_block_id(&self) -> Option<&str> {
+        self.source_block_id.as_deref()
+    }
+
+    #[graphql(description = "Whether the embedded block can be shown")]
+    fn status(&self, context: &Context) -> EmbedStatus {
+        self.resolve(context).map_or_else(|status| status, |_| EmbedStatus::Resolved)
+    }
+
+    #[graphql(description = "The embedded block, null unless the status is resolved")]
+    fn block(&self, context: &Context) -> Option<Block> {
+        self.resolve(context).ok().map(|(block, _)| block)
+    }
+
+    #[graphql(description = "DateTime for when the embed was last pointed at a block")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+impl EmbedBlock {
+    fn resolve(&self, context: &Context) -> Result<(Block, BlockContent), EmbedStatus> {
+        let user = context.authed_user().ok_or(EmbedStatus::Forbidden)?;
+        let mut conn = context.db_pool.get().unwrap();
+        EmbedOperation::resolve(&mut conn, Reader::User(&user.id), Some(&self.block_id), self.source_block_id.as_deref())
+    }
+}
+
+#[derive(Queryable)]
+pub struct CalloutBlock {
+    pub block_id: String,
+    pub variant: CalloutVariant,
+    pub content: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "CalloutBlock",
+    description = "Highlighted note or warning set apart from the text around it",
+    context = Context
+)]
+impl CalloutBlock {
+    #[graphql(description = "")]
+    fn variant(&self) -> CalloutVariant {
+        self.variant
+    }
+
+    #[graphql(description = "")]
+    fn content(&self) -> Option<&str> {
+        self.content.as_deref()
+    }
+
+    #[graphql(description = "DateTime for when the callout was last updated")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+#[derive(Queryable)]
+pub struct ToggleBlock {
+    pub block_id: String,
+    pub content: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "ToggleBlock",
+    description = "Collapsible section, the blocks nested under it are hidden until it is opened",
+    context = Context
+)]
+impl ToggleBlock {
+    #[graphql(description = "The always visible summary")]
+    fn content(&self) -> Option<&str> {
+        self.content.as_deref()
+    }
+
+    #[graphql(description = "DateTime for when the toggle was last updated")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+#[derive(Queryable)]
+pub struct TableBlock {
+    pub block_id: String,
+    pub table_columns: serde_json::Value,
+    pub table_rows: serde_json::Value,
+    pub updated_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "TableBlock",
+    description = "Table with a header row and typed columns",
+    context = Context
+)]
+impl TableBlock {
+    #[graphql(description = "Columns in order, their names make up the header row")]
+    fn columns(&self) -> Vec<TableColumn> {
+        self.column_list()
+    }
+
+    #[graphql(description = "Rows in order, each with a cell per column in column order")]
+    fn rows(&self) -> Vec<TableRow> {
+        let columns = self.column_list();
+
+        self.row_list()
+            .into_iter()
+            .map(|row| TableRow { cells: row.ordered_cells(&columns), ..row })
+            .collect()
+    }
+
+    #[graphql(description = "DateTime for when the table was last updated")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+impl TableBlock {
+    pub fn column_list(&self) -> Vec<TableColumn> {
+        serde_json::from_value(self.table_columns.clone()).unwrap_or_default()
+    }
+
+    /// Rows as stored, their cells are keyed by column and only there for
+    /// cells with a value.
+    pub fn row_list(&self) -> Vec<TableRow> {
+        serde_json::from_value(self.table_rows.clone()).unwrap_or_default()
+    }
+}
+
+#[derive(Queryable)]
+pub struct DividerBlock {
+    pub block_id: String,
+    pub updated_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "DividerBlock",
+    description = "Horizontal rule between sections",
+    context = Context
+)]
+impl DividerBlock {
+    #[graphql(description = "DateTime for when the divider was created")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+#[derive(GraphQLUnion)]
+#[graphql(context = Context)]
+pub enum BlockContent {
+    TextBlock(TextBlock),
+    ImageBlock(ImageBlock),
+    ListItemBlock(ListItemBlock),
+    TodoBlock(TodoBlock),
+    QuoteBlock(QuoteBlock),
+    CodeBlock(CodeBlock),
+    MathBlock(MathBlock),
+    EmbedBlock(EmbedBlock),
+    CalloutBlock(CalloutBlock),
+    ToggleBlock(ToggleBlock),
+    TableBlock(TableBlock),
+    DividerBlock(DividerBlock),
+}
+
+impl BlockContent {
+    /// The text of the block, `None` for blocks that have no text.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            BlockContent::TextBlock(text_block) => text_block.content.as_deref(),
+            BlockContent::ListItemBlock(list_item_block) => list_item_block.content.as_deref(),
+            BlockContent::TodoBlock(todo_block) => todo_block.content.as_deref(),
+            BlockContent::QuoteBlock(quote_block) => quote_block.content.as_deref(),
+            BlockContent::CodeBlock(code_block) => code_block.content.as_deref(),
+            BlockContent::MathBlock(math_block) => math_block.content.as_deref(),
+            BlockContent::CalloutBlock(callout_block) => callout_block.content.as_deref(),
+            BlockContent::ToggleBlock(toggle_block) => toggle_block.content.as_deref(),
+            BlockContent::ImageBlock(_) | BlockContent::EmbedBlock(_) | BlockContent::TableBlock(_) | BlockContent::DividerBlock(_) => None,
+        }
+    }
+
+    /// Only list items, to-dos and toggles can have blocks nested under
+    /// them.
+    pub fn can_have_children(&self) -> bool {
+        matches!(self, BlockContent::ListItemBlock(_) | BlockContent::TodoBlock(_) | BlockContent::ToggleBlock(_))
+    }
+}
+
+/// Blocks of a document grouped by parent so they can be walked nested.
+pub struct BlockTree<'a> {
+    children: HashMap<Option<&'a str>, Vec<&'a (Block, Option<BlockContent>)>>,
+}
+
+impl<'a> BlockTree<'a> {
+    /// `blocks` are expected in document order, as returned by
+    /// `BlockOperation::find_by_document_with_content`.
+    pub fn new(blocks: &'a [(Block, Option<BlockContent>)]) -> BlockTree<'a> {
+        let mut children: HashMap<_, Vec<_>> = HashMap::new();
+
+        for block in blocks {
+            children.entry(block.0.parent_id.as_deref()).or_default().push(block);
+        }
+
+        BlockTree { children }
+    }
+
+    /// Blocks nested directly under `parent_id` in order, the top level
+    /// blocks for `None`.
+    pub fn children(&self, parent_id: Option<&'a str>) -> &[&'a (Block, Option<BlockContent>)] {
+        self.children.get(&parent_id).map_or(&[], Vec::as_slice)
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = blocks)]
+pub struct NewBlock {
+    pub id: String,
+    pub document_id: String,
+    pub parent_id: Option<String>,
+    pub line_number: i32,
+    pub last_edited_by: Option<String>,
+}
+
+impl NewBlock {
+    pub fn new(document_id: &str, parent_id: Option<&str>, line_number: i32, user_id: &str) -> NewBlock {
+        NewBlock {
+            id: nanoid!(),
+            document_id: document_id.into(),
+            parent_id: parent_id.map(String::from),
+            line_number,
+            last_edited_by: Some(user_id.into()),
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = text_blocks)]
+pub struct NewTextBlock {
+    pub block_id: String,
+    pub tag: Tag,
+    pub content: Option<String>,
+    pub rich_text: Option<serde_json::Value>,
+}
+
+impl NewTextBlock {
+    pub fn new(block_id: &str, tag: Tag, content: Option<&str>, rich_text: Option<&[TextRun]>) -> NewTextBlock {
+        NewTextBlock {
+            block_id: block_id.into(),
+            tag,
+            content: content.map(|val| val.to_string()),
+            rich_text: rich_text.map(|runs| serde_json::to_value(runs).unwrap()),
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = image_blocks)]
+pub struct NewImageBlock {
+    pub block_id: String,
+    pub url: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub format: Option<String>,
+    pub srcset: Option<String>,
+    pub alt: Option<String>,
+    pub caption: Option<String>,
+}
+
+/// Trims the text, blank text is stored as null.
+fn non_blank(text: Option<&str>) -> Option<String> {
+    text.map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(String::from)
+}
+
+impl NewImageBlock {
+    pub fn new(block_id: &str, url: Option<&str>, alt: Option<&str>, caption: Option<&str>, metadata: Option<ImageMetadata>) -> NewImageBlock {
+        NewImageBlock {
+            block_id: block_id.into(),
+            url: url.map(|val| val.to_string()),
+            alt: non_blank(alt),
+            caption: non_blank(caption),
+            width: metadata.as_ref().map(|metadata| metadata.width),
+            height: metadata.as_ref().map(|metadata| metadata.height),
+            format: metadata.as_ref().map(|metadata| metadata.format.clone()),
+            srcset: metadata.and_then(|metadata| metadata.srcset),
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = list_item_blocks)]
+pub struct NewListItemBlock {
+    pub block_id: String,
+    pub style: ListStyle,
+    pub indent: i32,
+    pub content: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = todo_blocks)]
+pub struct NewTodoBlock {
+    pub block_id: String,
+    pub checked: bool,
+    pub indent: i32,
+    pub content: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = quote_blocks)]
+pub struct NewQuoteBlock {
+    pub block_id: String,
+    pub content: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = code_blocks)]
+pub struct NewCodeBlock {
+    pub block_id: String,
+    pub language: Option<String>,
+    pub content: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = math_blocks)]
+pub struct NewMathBlock {
+    pub block_id: String,
+    pub content: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = embed_blocks)]
+pub struct NewEmbedBlock {
+    pub block_id: String,
+    pub source_block_id: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = callout_blocks)]
+pub struct NewCalloutBlock {
+    pub block_id: String,
+    pub variant: CalloutVariant,
+    pub content: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = toggle_blocks)]
+pub struct NewToggleBlock {
+    pub block_id: String,
+    pub content: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = table_blocks)]
+pub struct NewTableBlock {
+    pub block_id: String,
+    pub table_columns: serde_json::Value,
+    pub table_rows: serde_json::Value,
+}
+
+impl NewTableBlock {
+    pub fn new(block_id: &str, columns: &[TableColumn], rows: &[TableRow]) -> NewTableBlock {
+        NewTableBlock {
+            block_id: block_id.into(),
+            table_columns: serde_json::to_value(columns).unwrap_or_default(),
+            table_rows: serde_json::to_value(rows).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = divider_blocks)]
+pub struct NewDividerBlock {
+    pub block_id: String,
+}
+
+/// Content for a block that is about to be created or replace a blocks
+/// content.
+#[derive(Debug, PartialEq)]
+pub enum NewBlockContent {
+    Text { tag: Tag, content: Option<String>, rich_text: Option<Vec<TextRun>> },
+    Image { url: Option<String>, alt: Option<String>, caption: Option<String> },
+    ListItem { style: ListStyle, indent: i32, content: Option<String> },
+    Todo { checked: bool, indent: i32, content: Option<String> },
+    Quote { content: Option<String> },
+    Code { language: Option<String>, content: Option<String> },
+    Math { content: Option<String> },
+    Embed { source_block_id: Option<String> },
+    Callout { variant: CalloutVariant, content: Option<String> },
+    Toggle { content: Option<String> },
+    Table { columns: Vec<TableColumn>, rows: Vec<TableRow> },
+    Divider,
+}
+
+impl NewBlockContent {
+    /// Text with formatting. The plain content is derived from the runs
+    /// and the runs are only kept when some of them have marks.
+    pub fn text(tag: Tag, runs: Vec<TextRun>) -> NewBlockContent {
+        let runs = rich_text::normalize(runs);
+        let content = Some(rich_text::plain_text(&runs)).filter(|content| !content.is_empty());
+        let rich_text = (!runs.iter().all(TextRun::is_plain)).then_some(runs);
+
+        NewBlockContent::Text { tag, content, rich_text }
+    }
+
+    pub fn validate(&self) -> FieldErrors {
+        let mut errors = FieldErrors::new();
+
+        match self {
+            NewBlockContent::ListItem { indent, .. } | NewBlockContent::Todo { indent, .. } =>
+                Validate::indent("indent", *indent, &mut errors),
+            NewBlockContent::Code { language: Some(language), .. } =>
+                Validate::language("language", language, &mut errors),
+            NewBlockContent::Math { content: Some(content) } => math::validate("content", content, &mut errors),
+            NewBlockContent::Table { columns, rows } => table::validate(columns, rows, &mut errors),
+            _ => {},
+        }
+
+        errors
+    }
+}
+
+/// The content a copy of the block is created with.
+impl From<&BlockContent> for NewBlockContent {
+    fn from(content: &BlockContent) -> NewBlockContent {
+        match content {
+            BlockContent::TextBlock(text_block) => NewBlockContent::Text {
+                tag: text_block.tag,
+                content: text_block.content.clone(),
+                rich_text: text_block.rich_text.is_some().then(|| text_block.runs()),
+            },
+            BlockContent::ImageBlock(image_block) => NewBlockContent::Image {
+                url: image_block.url.clone(),
+                alt: image_block.alt.clone(),
+                caption: image_block.caption.clone(),
+            },
+            BlockContent::ListItemBlock(list_item_block) => NewBlockContent::ListItem {
+                style: list_item_block.style,
+                indent: list_item_block.indent,
+                content: list_item_block.content.clone(),
+            },
+            BlockContent::TodoBlock(todo_block) => NewBlockContent::Todo {
+                checked: todo_block.checked,
+                indent: todo_block.indent,
+                content: todo_block.content.clone(),
+            },
+            BlockContent::QuoteBlock(quote_block) => NewBlockContent::Quote { content: quote_block.content.clone() },
+            BlockContent::CodeBlock(code_block) => NewBlockContent::Code {
+                language: code_block.language.clone(),
+                content: code_block.content.clone(),
+            },
+            BlockContent::MathBlock(math_block) => NewBlockContent::Math { content: math_block.content.clone() },
+            BlockContent::EmbedBlock(embed_block) => NewBlockContent::Embed { source_block_id: embed_block.source_block_id.clone() },
+            BlockContent::CalloutBlock(callout_block) => NewBlockContent::Callout {
+                variant: callout_block.variant,
+                content: callout_block.content.clone(),
+            },
+            BlockContent::ToggleBlock(toggle_block) => NewBlockContent::Toggle { content: toggle_block.content.clone() },
+            BlockContent::TableBlock(table_block) => NewBlockContent::Table {
+                columns: table_block.column_list(),
+                rows: table_block.row_list(),
+            },
+            BlockContent::DividerBlock(_) => NewBlockContent::Divider,
+        }
+    }
+}
+
+fn block_result(result: QueryResult<Block>) -> BlockResult {
+    match result {
+        Ok(block) => BlockResult::Block(block),
+        Err(NotFound) => BlockResult::not_found("block not found"),
+        Err(DatabaseError(ForeignKeyViolation, _)) =>
+            BlockResult::not_found("document not found"),
+        Err(_) => BlockResult::server(),
+    }
+}
+
+/// Why a block can't be put where it was asked to go.
+#[derive(Debug)]
+pub enum PlacementError {
+    ParentNotFound,
+    CantHaveChildren,
+    IntoItself,
+    NothingToNestUnder,
+    NotNested,
+    Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for PlacementError {
+    fn from(err: diesel::result::Error) -> PlacementError {
+        PlacementError::Database(err)
+    }
+}
+
+fn placement_result(result: Result<Block, PlacementError>) -> BlockResult {
+    let (field, message) = match result {
+        Ok(block) => return BlockResult::Block(block),
+        Err(PlacementError::Database(err)) => return block_result(Err(err)),
+        Err(PlacementError::ParentNotFound) => ("parentId", "isn't a block of this document"),
+        Err(PlacementError::CantHaveChildren) => ("parentId", "only list items, to-dos and toggles can have nested blocks"),
+        Err(PlacementError::IntoItself) => ("parentId", "can't be the block itself or a block nested under it"),
+        Err(PlacementError::NothingToNestUnder) => ("id", "needs a list item, to-do or toggle right before it to nest under"),
+        Err(PlacementError::NotNested) => ("id", "isn't nested under another block"),
+    };
+
+    let mut errors = FieldErrors::new();
+    errors.push(FieldError::new(field, message));
+
+    BlockResult::FieldErrors(errors)
+}
+
+/// Why blocks can't be moved to another document.
+#[derive(Debug)]
+pub enum MoveBlocksError {
+    AfterNotFound,
+    AfterMoved,
+    Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for MoveBlocksError {
+    fn from(err: diesel::result::Error) -> MoveBlocksError {
+        MoveBlocksError::Database(err)
+    }
+}
+
+/// Orders blocks the way they are read, every block followed by the blocks
+/// nested under it. `block_list` has to be sorted by line number.
+fn document_order(block_list: Vec<Block>) -> Vec<Block> {
+    let mut children: HashMap<Option<String>, Vec<Block>> = HashMap::new();
+
+    for block in block_list {
+        children.entry(block.parent_id.clone()).or_default().push(block);
+    }
+
+    let mut ordered = Vec::new();
+    let mut stack = children.remove(&None).unwrap_or_default();
+    stack.reverse();
+
+    while let Some(block) = stack.pop() {
+        if let Some(nested) = children.remove(&Some(block.id.clone())) {
+            stack.extend(nested.into_iter().rev());
+        }
+
+        ordered.push(block);
+    }
+
+    ordered
+}
+
+pub struct BlockOperation;
+
+impl BlockOperation {
+    /// Moves every sibling at or after `line_number` down by one so a new
+    /// block can take its place. Blocks are first flipped to negative line
+    /// numbers so the unique (document_id, parent_id, line_number)
+    /// constraint holds per row.
+    fn make_room(conn: &mut DBPooledConnection, document_id: &str, parent_id: Option<&str>, line_number: i32) -> QueryResult<()> {
+        diesel::update(blocks::table)
+            .filter(blocks::document_id.eq(document_id))
+            .filter(blocks::parent_id.is_not_distinct_from(parent_id))
+            .filter(blocks::line_number.ge(line_number))
+            .set(blocks::line_number.eq(blocks::line_number * -1 - 1))
+            .execute(conn)?;
+
+        diesel::update(blocks::table)
+            .filter(blocks::document_id.eq(document_id))
+            .filter(blocks::parent_id.is_not_distinct_from(parent_id))
+            .filter(blocks::line_number.lt(0))
+            .set(blocks::line_number.eq(blocks::line_number * -1))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    fn insert(conn: &mut DBPooledConnection, new_block: &NewBlock) -> QueryResult<Block> {
+        Self::make_room(conn, &new_block.document_id, new_block.parent_id.as_deref(), new_block.line_number)?;
+
+        diesel::insert_into(blocks::table)
+            .values(new_block)
+            .get_result::<Block>(conn)
+    }
+
+    /// Records `user_id` as the last editor of the block.
+    pub fn touch(conn: &mut DBPooledConnection, id: &str, user_id: &str) -> QueryResult<Block> {
+        let block = diesel::update(blocks::table)
+            .filter(blocks::id.eq(id))
+            .set((
+                blocks::last_edited_by.eq(user_id),
+                blocks::last_edited_at.eq(diesel::dsl::now),
+            ))
+            .get_result::<Block>(conn)?;
+
+        Self::touch_document(conn, &block.document_id)?;
+
+        Ok(block)
+    }
+
+    /// Bumps the documents updated_at so edits to its blocks count as
+    /// updates to the document.
+    fn touch_document(conn: &mut DBPooledConnection, document_id: &str) -> QueryResult<()> {
+        diesel::update(documents::table)
+            .filter(documents::id.eq(document_id))
+            .set(documents::updated_at.eq(diesel::dsl::now))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    fn image_metadata(conn: &mut DBPooledConnection, url: Option<&str>) -> QueryResult<Option<ImageMetadata>> {
+        match url {
+            Some(url) => UploadOperation::image_metadata(conn, url),
+            None => Ok(None),
+        }
+    }
+
+    fn insert_content(conn: &mut DBPooledConnection, block_id: &str, content: &NewBlockContent) -> QueryResult<()> {
+        match content {
+            NewBlockContent::Text { tag, content, rich_text } => diesel::insert_into(text_blocks::table)
+                .values(&NewTextBlock::new(block_id, *tag, content.as_deref(), rich_text.as_deref()))
+                .execute(conn)?,
+            NewBlockContent::Image { url, alt, caption } => diesel::insert_into(image_blocks::table)
+                .values(&NewImageBlock::new(block_id, url.as_deref(), alt.as_deref(), caption.as_deref(), Self::image_metadata(conn, url.as_deref())?))
+                .execute(conn)?,
+            NewBlockContent::ListItem { style, indent, content } => diesel::insert_into(list_item_blocks::table)
+                .values(&NewListItemBlock { block_id: block_id.into(), style: *style, indent: *indent, content: content.clone() })
+                .execute(conn)?,
+            NewBlockContent::Todo { checked, indent, content } => diesel::insert_into(todo_blocks::table)
+                .values(&NewTodoBlock { block_id: block_id.into(), checked: *checked, indent: *indent, content: content.clone() })
+                .execute(conn)?,
+            NewBlockContent::Quote { content } => diesel::insert_into(quote_blocks::table)
+                .values(&NewQuoteBlock { block_id: block_id.into(), content: content.clone() })
+                .execute(conn)?,
+            NewBlockContent::Code { language, content } => diesel::insert_into(code_blocks::table)
+                .values(&NewCodeBlock { block_id: block_id.into(), language: language.clone(), content: content.clone() })
+                .execute(conn)?,
+            NewBlockContent::Math { content } => diesel::insert_into(math_blocks::table)
+                .values(&NewMathBlock { block_id: block_id.into(), content: content.clone() })
+                .execute(conn)?,
+            NewBlockContent::Embed { source_block_id } => diesel::insert_into(embed_blocks::table)
+                .values(&NewEmbedBlock { block_id: block_id.into(), source_block_id: source_block_id.clone() })
+                .execute(conn)?,
+            NewBlockContent::Callout { variant, content } => diesel::insert_into(callout_blocks::table)
+                .values(&NewCalloutBlock { block_id: block_id.into(), variant: *variant, content: content.clone() })
+                .execute(conn)?,
+            NewBlockContent::Toggle { content } => diesel::insert_into(toggle_blocks::table)
+                .values(&NewToggleBlock { block_id: block_id.into(), content: content.clone() })
+                .execute(conn)?,
+            NewBlockContent::Table { columns, rows } => diesel::insert_into(table_blocks::table)
+                .values(&NewTableBlock::new(block_id, columns, rows))
+                .execute(conn)?,
+            NewBlockContent::Divider => diesel::insert_into(divider_blocks::table)
+                .values(&NewDividerBlock { block_id: block_id.into() })
+                .execute(conn)?,
+        };
+
+        LinkOperation::sync_block(conn, block_id, content)
+    }
+
+    /// Locks the document so concurrent moves can't nest two blocks under
+    /// each other, then checks `parent_id` can hold `block_id`.
+    fn check_parent(conn: &mut DBPooledConnection, document_id: &str, parent_id: &str, block_id: Option<&str>) -> Result<(), PlacementError> {
+        documents::table
+            .filter(documents::id.eq(document_id))
+            .select(documents::id)
+            .for_update()
+            .get_result::<String>(conn)?;
+
+        let parent = blocks::table
+            .filter(blocks::id.eq(parent_id))
+            .filter(blocks::document_id.eq(document_id))
+            .get_result::<Block>(conn)
+            .optional()?
+            .ok_or(PlacementError::ParentNotFound)?;
+
+        let can_have_children = Self::contents(conn, std::slice::from_ref(&parent.id))?
+            .remove(&parent.id)
+            .is_some_and(|content| content.can_have_children());
+
+        if !can_have_children {
+            return Err(PlacementError::CantHaveChildren);
+        }
+
+        let mut ancestor = Some(parent);
+
+        while let Some(block) = ancestor {
+            if Some(block.id.as_str()) == block_id {
+                return Err(PlacementError::IntoItself);
+            }
+
+            ancestor = match block.parent_id {
+                Some(parent_id) => Some(Self::find(conn, &parent_id)?),
+                None => None,
+            };
+        }
+
+        Ok(())
+    }
+
+    pub fn create(conn: &mut DBPooledConnection, user_id: &str, document_id: &str, parent_id: Option<&str>, line_number: i32, content: &NewBlockContent) -> BlockResult {
+        let result = conn.transaction(|conn| {
+            if let Some(parent_id) = parent_id {
+                Self::check_parent(conn, document_id, parent_id, None)?;
+            }
+
+            let block = Self::insert(conn, &NewBlock::new(document_id, parent_id, line_number, user_id))?;
+            Self::insert_content(conn, &block.id, content)?;
+            Self::touch_document(conn, document_id)?;
+
+            Ok(block)
+        });
+
+        placement_result(result)
+    }
+
+    /// Puts the block under `parent_id` at `line_number`, the blocks nested
+    /// under it come along. Should be run inside a transaction.
+    fn place(conn: &mut DBPooledConnection, user_id: &str, block: &Block, parent_id: Option<&str>, line_number: i32) -> Result<Block, PlacementError> {
+        if let Some(parent_id) = parent_id {
+            Self::check_parent(conn, &block.document_id, parent_id, Some(&block.id))?;
+        }
+
+        // parked under itself so it isn't renumbered along with the
+        // siblings it is moved between
+        diesel::update(blocks::table)
+            .filter(blocks::id.eq(&block.id))
+            .set(blocks::parent_id.eq(&block.id))
+            .execute(conn)?;
+
+        Self::make_room(conn, &block.document_id, parent_id, line_number)?;
+
+        diesel::update(blocks::table)
+            .filter(blocks::id.eq(&block.id))
+            .set((blocks::parent_id.eq(parent_id), blocks::line_number.eq(line_number)))
+            .execute(conn)?;
+
+        Ok(Self::touch(conn, &block.id, user_id)?)
+    }
+
+    /// Moves a block and everything nested under it in one go.
+    pub fn move_to(conn: &mut DBPooledConnection, user_id: &str, id: &str, parent_id: Option<&str>, line_number: i32) -> BlockResult {
+        let result = conn.transaction(|conn| {
+            let block = Self::find(conn, id)?;
+            Self::place(conn, user_id, &block, parent_id, line_number)
+        });
+
+        placement_result(result)
+    }
+
+    /// Nests a block as the last child of the block before it.
+    pub fn indent(conn: &mut DBPooledConnection, user_id: &str, id: &str) -> BlockResult {
+        let result = conn.transaction(|conn| {
+            let block = Self::find(conn, id)?;
+
+            let previous = blocks::table
+                .filter(blocks::document_id.eq(&block.document_id))
+                .filter(blocks::parent_id.is_not_distinct_from(&block.parent_id))
+                .filter(blocks::line_number.lt(block.line_number))
+                .order(blocks::line_number.desc())
+                .first::<Block>(conn)
+                .optional()?
+                .ok_or(PlacementError::NothingToNestUnder)?;
+
+            let last_line_number = blocks::table
+                .filter(blocks::parent_id.eq(&previous.id))
+                .select(diesel::dsl::max(blocks::line_number))
+                .get_result::<Option<i32>>(conn)?;
+
+            let line_number = last_line_number.map_or(0, |line_number| line_number + 1);
+
+            match Self::place(conn, user_id, &block, Some(&previous.id), line_number) {
+                Err(PlacementError::CantHaveChildren) => Err(PlacementError::NothingToNestUnder),
+                result => result,
+            }
+        });
+
+        placement_result(result)
+    }
+
+    /// Moves a nested block out to just after its parent. The blocks after
+    /// it stay where they are.
+    pub fn outdent(conn: &mut DBPooledConnection, user_id: &str, id: &str) -> BlockResult {
+        let result = conn.transaction(|conn| {
+            let block = Self::find(conn, id)?;
+            let parent = Self::find(conn, block.parent_id.as_deref().ok_or(PlacementError::NotNested)?)?;
+
+            Self::place(conn, user_id, &block, parent.parent_id.as_deref(), parent.line_number + 1)
+        });
+
+        placement_result(result)
+    }
+
+    /// Moves blocks along with the blocks nested under them to the level of
+    /// `after_id` in another document, right after it, or to the top of the
+    /// document when there is none. The blocks are copied with new IDs and
+    /// the originals deleted, embeds of them embed the copies.
+    pub fn move_to_document(conn: &mut DBPooledConnection, user_id: &str, ids: &[String], document_id: &str, after_id: Option<&str>) -> Result<(), MoveBlocksError> {
+        conn.transaction(|conn| {
+            let mut roots: Vec<Block> = Vec::new();
+            let mut moved_ids: Vec<String> = Vec::new();
+
+            for id in ids {
+                // already moving along with a block it is nested under
+                if moved_ids.contains(id) {
+                    continue;
+                }
+
+                let block = Self::find(conn, id)?;
+                let subtree_ids = Self::subtree_ids(conn, id)?;
+
+                roots.retain(|root| !subtree_ids.contains(&root.id));
+                moved_ids.extend(subtree_ids);
+                roots.push(block);
+            }
+
+            moved_ids.sort();
+            moved_ids.dedup();
+
+            let mut document_ids = roots.iter().map(|root| root.document_id.clone()).collect::<Vec<_>>();
+            document_ids.push(document_id.into());
+            document_ids.sort();
+            document_ids.dedup();
+
+            // locked in a fixed order so two moves can't wait on each other
+            documents::table
+                .filter(documents::id.eq_any(&document_ids))
+                .order(documents::id.asc())
+                .select(documents::id)
+                .for_update()
+                .get_results::<String>(conn)?;
+
+            let (parent_id, first_line_number) = match after_id {
+                Some(after_id) if moved_ids.iter().any(|id| id == after_id) => return Err(MoveBlocksError::AfterMoved),
+                Some(after_id) => {
+                    let after = blocks::table
+                        .filter(blocks::id.eq(after_id))
+                        .filter(blocks::document_id.eq(document_id))
+                        .get_result::<Block>(conn)
+                        .optional()?
+                        .ok_or(MoveBlocksError::AfterNotFound)?;
+
+                    (after.parent_id, after.line_number + 1)
+                },
+                None => (None, 0),
+            };
+
+            // every root followed by the blocks nested under it, in order
+            let mut block_list = Vec::new();
+
+            for root in &roots {
+                let subtree_ids = Self::subtree_ids(conn, &root.id)?;
+
+                block_list.extend(
+                    Self::find_by_document_with_content(conn, &root.document_id)?
+                        .into_iter()
+                        .filter(|(block, _)| subtree_ids.contains(&block.id)),
+                );
+            }
+
+            let root_ids = roots.iter().map(|root| root.id.as_str()).collect::<Vec<_>>();
+
+            let new_ids = Self::copy_blocks(conn, user_id, &block_list, document_id, |block| {
+                let index = root_ids.iter().position(|id| *id == block.id).unwrap_or_default();
+                (parent_id.clone(), first_line_number + index as i32)
+            })?;
+
+            for (id, copy_id) in &new_ids {
+                diesel::update(embed_blocks::table)
+                    .filter(embed_blocks::source_block_id.eq(id))
+                    .set(embed_blocks::source_block_id.eq(copy_id))
+                    .execute(conn)?;
+            }
+
+            Self::delete_rows(conn, &moved_ids)?;
+
+            for root in &roots {
+                Self::touch(conn, &new_ids[&root.id], user_id)?;
+                Self::touch_document(conn, &root.document_id)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Copies every block of a document into another document with new
+    /// IDs. Embeds of blocks of the same document embed the copies. Should
+    /// be run inside a transaction.
+    pub fn copy_document(conn: &mut DBPooledConnection, user_id: &str, from_document_id: &str, to_document_id: &str) -> QueryResult<()> {
+        let block_list = Self::find_by_document_with_content(conn, from_document_id)?;
+        Self::copy_blocks(conn, user_id, &block_list, to_document_id, |block| (None, block.line_number))?;
+
+        Ok(())
+    }
+
+    /// Copies blocks into a document with new IDs, returning the ID of the
+    /// copy of each block. Parents have to come before the blocks nested
+    /// under them, which go under the copy of their parent. The others go
+    /// where `place` puts them. Embeds of copied blocks embed the copies.
+    fn copy_blocks(conn: &mut DBPooledConnection, user_id: &str, block_list: &[(Block, Option<BlockContent>)], document_id: &str, place: impl Fn(&Block) -> (Option<String>, i32)) -> QueryResult<HashMap<String, String>> {
+        let mut new_ids: HashMap<String, String> = HashMap::new();
+
+        for (block, _) in block_list {
+            let copied_parent_id = block.parent_id.as_ref().and_then(|parent_id| new_ids.get(parent_id)).cloned();
+
+            let (parent_id, line_number) = match copied_parent_id {
+                Some(parent_id) => (Some(parent_id), block.line_number),
+                None => place(block),
+            };
+
+            let copy = Self::insert(conn, &NewBlock::new(document_id, parent_id.as_deref(), line_number, user_id))?;
+
+            new_ids.insert(block.id.clone(), copy.id);
+        }
+
+        for (block, content) in block_list {
+            let Some(content) = content else {
+                continue;
+            };
+
+            let mut new_content = NewBlockContent::from(content);
+
+            if let NewBlockContent::Embed { source_block_id: Some(source_block_id) } = &mut new_content {
+                if let Some(copy_id) = new_ids.get(source_block_id) {
+                    *source_block_id = copy_id.clone();
+                }
+            }
+
+            Self::insert_content(conn, &new_ids[&block.id], &new_content)?;
+        }
+
+        Ok(new_ids)
+    }
+
+    /// Adds blocks to the end of a document in the order given. Should be
+    /// run inside a transaction.
+    pub fn append(conn: &mut DBPooledConnection, user_id: &str, document_id: &str, contents: &[NewBlockContent]) -> QueryResult<Vec<Block>> {
+        let last_line_number = blocks::table
+            .filter(blocks::document_id.eq(document_id))
+            .filter(blocks::parent_id.is_null())
+            .select(diesel::dsl::max(blocks::line_number))
+            .get_result::<Option<i32>>(conn)?;
+
+        let first_line_number = last_line_number.map_or(0, |line_number| line_number + 1);
+
+        let new_blocks = (0..contents.len())
+            .map(|index| NewBlock::new(document_id, None, first_line_number + index as i32, user_id))
+            .collect::<Vec<_>>();
+
+        let block_list = diesel::insert_into(blocks::table)
+            .values(&new_blocks)
+            .get_results::<Block>(conn)?;
+
+        for (block, content) in block_list.iter().zip(contents) {
+            Self::insert_content(conn, &block.id, content)?;
+        }
+
+        Self::touch_document(conn, document_id)?;
+
+        Ok(block_list)
+    }
+
+    /// Replaces the content of a block. The new content has to be of the
+    /// same type as the old one, otherwise the block isn't found.
+    pub fn update(conn: &mut DBPooledConnection, user_id: &str, id: &str, content: &NewBlockContent) -> BlockResult {
+        let result = conn.transaction(|conn| {
+            let updated = match content {
+                NewBlockContent::Text { tag, content, rich_text } => {
+                    let new_text_block = NewTextBlock::new(id, *tag, content.as_deref(), rich_text.as_deref());
+
+                    diesel::update(text_blocks::table)
+                        .filter(text_blocks::block_id.eq(id))
+                        .set((
+                            text_blocks::tag.eq(new_text_block.tag),
+                            text_blocks::content.eq(new_text_block.content),
+                            text_blocks::rich_text.eq(new_text_block.rich_text),
+                        ))
+                        .execute(conn)?
+                },
+                NewBlockContent::Image { url, alt, caption } => {
+                    let new_image_block = NewImageBlock::new(id, url.as_deref(), alt.as_deref(), caption.as_deref(), Self::image_metadata(conn, url.as_deref())?);
+
+                    diesel::update(image_blocks::table)
+                        .filter(image_blocks::block_id.eq(id))
+                        .set((
+                            image_blocks::url.eq(new_image_block.url),
+                            image_blocks::width.eq(new_image_block.width),
+                            image_blocks::height.eq(new_image_block.height),
+                            image_blocks::format.eq(new_image_block.format),
+                            image_blocks::srcset.eq(new_image_block.srcset),
+                            image_blocks::alt.eq(new_image_block.alt),
+                            image_blocks::caption.eq(new_image_block.caption),
+                        ))
+                        .execute(conn)?
+                },
+                NewBlockContent::ListItem { style, indent, content } => diesel::update(list_item_blocks::table)
+                    .filter(list_item_blocks::block_id.eq(id))
+                    .set((list_item_blocks::style.eq(style), list_item_blocks::indent.eq(indent), list_item_blocks::content.eq(content)))
+                    .execute(conn)?,
+                NewBlockContent::Todo { checked, indent, content } => diesel::update(todo_blocks::table)
+                    .filter(todo_blocks::block_id.eq(id))
+                    .set((todo_blocks::checked.eq(checked), todo_blocks::indent.eq(indent), todo_blocks::content.eq(content)))
+                    .execute(conn)?,
+                NewBlockContent::Quote { content } => diesel::update(quote_blocks::table)
+                    .filter(quote_blocks::block_id.eq(id))
+                    .set(quote_blocks::content.eq(content))
+                    .execute(conn)?,
+                NewBlockContent::Code { language, content } => diesel::update(code_blocks::table)
+                    .filter(code_blocks::block_id.eq(id))
+                    .set((code_blocks::language.eq(language), code_blocks::content.eq(content)))
+                    .execute(conn)?,
+                NewBlockContent::Math { content } => diesel::update(math_blocks::table)
+                    .filter(math_blocks::block_id.eq(id))
+                    .set(math_blocks::content.eq(content))
+                    .execute(conn)?,
+                NewBlockContent::Embed { source_block_id } => diesel::update(embed_blocks::table)
+                    .filter(embed_blocks::block_id.eq(id))
+                    .set(embed_blocks::source_block_id.eq(source_block_id))
+                    .execute(conn)?,
+                NewBlockContent::Callout { variant, content } => diesel::update(callout_blocks::table)
+                    .filter(callout_blocks::block_id.eq(id))
+                    .set((callout_blocks::variant.eq(variant), callout_blocks::content.eq(content)))
+                    .execute(conn)?,
+                NewBlockContent::Toggle { content } => diesel::update(toggle_blocks::table)
+                    .filter(toggle_blocks::block_id.eq(id))
+                    .set(toggle_blocks::content.eq(content))
+                    .execute(conn)?,
+                NewBlockContent::Table { columns, rows } => {
+                    let new_table_block = NewTableBlock::new(id, columns, rows);
+
+                    diesel::update(table_blocks::table)
+                        .filter(table_blocks::block_id.eq(id))
+                        .set((table_blocks::table_columns.eq(new_table_block.table_columns), table_blocks::table_rows.eq(new_table_block.table_rows)))
+                        .execute(conn)?
+                },
+                NewBlockContent::Divider => diesel::update(divider_blocks::table)
+                    .filter(divider_blocks::block_id.eq(id))
+                    .set(divider_blocks::updated_at.eq(diesel::dsl::now))
+                    .execute(conn)?,
+            };
+
+            if updated == 0 {
+                return Err(NotFound);
+            }
+
+            LinkOperation::sync_block(conn, id, content)?;
+            Self::touch(conn, id, user_id)
+        });
+
+        block_result(result)
+    }
+
+    /// IDs of the block and every block nested under it, parents first.
+    fn subtree_ids(conn: &mut DBPooledConnection, id: &str) -> QueryResult<Vec<String>> {
+        let mut ids = vec![id.to_string()];
+        let mut level = ids.clone();
+
+        while !level.is_empty() {
+            level = blocks::table
+                .filter(blocks::parent_id.eq_any(&level))
+                .select(blocks::id)
+                .get_results::<String>(conn)?;
+
+            ids.extend(level.iter().cloned());
+        }
+
+        Ok(ids)
+    }
+
+    /// Deletes the block along with the blocks nested under it.
+    pub fn delete(conn: &mut DBPooledConnection, id: &str) -> BlockResult {
+        let result = conn.transaction(|conn| {
+            let block = Self::find(conn, id)?;
+            let ids = Self::subtree_ids(conn, id)?;
+
+            Self::delete_rows(conn, &ids)?;
+            Self::touch_document(conn, &block.document_id)?;
+
+            Ok(block)
+        });
+
+        block_result(result)
+    }
+
+    /// Deletes the blocks with their content and links. Blocks nested under
+    /// them have to be among them. Should be run inside a transaction.
+    fn delete_rows(conn: &mut DBPooledConnection, ids: &[String]) -> QueryResult<()> {
+        LinkOperation::delete_by_blocks(conn, ids)?;
+        diesel::delete(text_blocks::table.filter(text_blocks::block_id.eq_any(ids))).execute(conn)?;
+        diesel::delete(image_blocks::table.filter(image_blocks::block_id.eq_any(ids))).execute(conn)?;
+        diesel::delete(list_item_blocks::table.filter(list_item_blocks::block_id.eq_any(ids))).execute(conn)?;
+        diesel::delete(todo_blocks::table.filter(todo_blocks::block_id.eq_any(ids))).execute(conn)?;
+        diesel::delete(quote_blocks::table.filter(quote_blocks::block_id.eq_any(ids))).execute(conn)?;
+        diesel::delete(code_blocks::table.filter(code_blocks::block_id.eq_any(ids))).execute(conn)?;
+        diesel::delete(math_blocks::table.filter(math_blocks::block_id.eq_any(ids))).execute(conn)?;
+        diesel::delete(embed_blocks::table.filter(embed_blocks::block_id.eq_any(ids))).execute(conn)?;
+        diesel::delete(callout_blocks::table.filter(callout_blocks::block_id.eq_any(ids))).execute(conn)?;
+        diesel::delete(toggle_blocks::table.filter(toggle_blocks::block_id.eq_any(ids))).execute(conn)?;
+        diesel::delete(table_blocks::table.filter(table_blocks::block_id.eq_any(ids))).execute(conn)?;
+        diesel::delete(divider_blocks::table.filter(divider_blocks::block_id.eq_any(ids))).execute(conn)?;
+        diesel::delete(blocks::table.filter(blocks::id.eq_any(ids))).execute(conn)?;
+
+        Ok(())
+    }
+
+    pub fn find(conn: &mut DBPooledConnection, id: &str) -> QueryResult<Block> {
+        blocks::table
+            .filter(blocks::id.eq(id))
+            .get_result::<Block>(conn)
+    }
+
+    /// Top level blocks of the document, nested blocks are found through
+    /// their parents.
+    pub fn find_by_document(conn: &mut DBPooledConnection, document_id: &str) -> Vec<Block> {
+        use crate::schema::blocks::dsl::{blocks, document_id as block_document_id, parent_id, line_number};
+
+        let block_list = blocks
+            .filter(block_document_id.eq(document_id))
+            .filter(parent_id.is_null())
+            .order(line_number.asc())
+            .get_results::<Block>(conn);
+
+        block_list.unwrap_or_default()
+    }
+
+    pub fn find_children(conn: &mut DBPooledConnection, id: &str) -> Vec<Block> {
+        blocks::table
+            .filter(blocks::parent_id.eq(id))
+            .order(blocks::line_number.asc())
+            .get_results::<Block>(conn)
+            .unwrap_or_default()
+    }
+
+    /// Content of each of the blocks that has any, keyed by block ID. Every
+    /// content type lives in its own table so each is queried once.
+    pub fn contents(conn: &mut DBPooledConnection, ids: &[String]) -> QueryResult<HashMap<String, BlockContent>> {
+        let mut contents = HashMap::new();
+
+        for text_block in text_blocks::table.filter(text_blocks::block_id.eq_any(ids)).get_results::<TextBlock>(conn)? {
+            contents.insert(text_block.block_id.clone(), BlockContent::TextBlock(text_block));
+        }
+
+        for image_block in image_blocks::table.filter(image_blocks::block_id.eq_any(ids)).get_results::<ImageBlock>(conn)? {
+            contents.insert(image_block.block_id.clone(), BlockContent::ImageBlock(image_block));
+        }
+
+        for list_item_block in list_item_blocks::table.filter(list_item_blocks::block_id.eq_any(ids)).get_results::<ListItemBlock>(conn)? {
+            contents.insert(list_item_block.block_id.clone(), BlockContent::ListItemBlock(list_item_block));
+        }
+
+        for todo_block in todo_blocks::table.filter(todo_blocks::block_id.eq_any(ids)).get_results::<TodoBlock>(conn)? {
+            contents.insert(todo_block.block_id.clone(), BlockContent::TodoBlock(todo_block));
+        }
+
+        for quote_block in quote_blocks::table.filter(quote_blocks::block_id.eq_any(ids)).get_results::<QuoteBlock>(conn)? {
+            contents.insert(quote_block.block_id.clone(), BlockContent::QuoteBlock(quote_block));
+        }
+
+        for code_block in code_blocks::table.filter(code_blocks::block_id.eq_any(ids)).get_results::<CodeBlock>(conn)? {
+            contents.insert(code_block.block_id.clone(), BlockContent::CodeBlock(code_block));
+        }
+
+        for math_block in math_blocks::table.filter(math_blocks::block_id.eq_any(ids)).get_results::<MathBlock>(conn)? {
+            contents.insert(math_block.block_id.clone(), BlockContent::MathBlock(math_block));
+        }
+
+        for embed_block in embed_blocks::table.filter(embed_blocks::block_id.eq_any(ids)).get_results::<EmbedBlock>(conn)? {
+            contents.insert(embed_block.block_id.clone(), BlockContent::EmbedBlock(embed_block));
+        }
+
+        for callout_block in callout_blocks::table.filter(callout_blocks::block_id.eq_any(ids)).get_results::<CalloutBlock>(conn)? {
+            contents.insert(callout_block.block_id.clone(), BlockContent::CalloutBlock(callout_block));
+        }
+
+        for toggle_block in toggle_blocks::table.filter(toggle_blocks::block_id.eq_any(ids)).get_results::<ToggleBlock>(conn)? {
+            contents.insert(toggle_block.block_id.clone(), BlockContent::ToggleBlock(toggle_block));
+        }
+
+        for table_block in table_blocks::table.filter(table_blocks::block_id.eq_any(ids)).get_results::<TableBlock>(conn)? {
+            contents.insert(table_block.block_id.clone(), BlockContent::TableBlock(table_block));
+        }
+
+        for divider_block in divider_blocks::table.filter(divider_blocks::block_id.eq_any(ids)).get_results::<DividerBlock>(conn)? {
+            contents.insert(divider_block.block_id.clone(), BlockContent::DividerBlock(divider_block));
+        }
+
+        Ok(contents)
+    }
+
+    /// Every block of the document in document order, see `BlockTree` to
+    /// walk them nested.
+    pub fn find_by_document_with_content(conn: &mut DBPooledConnection, document_id: &str) -> QueryResult<Vec<(Block, Option<BlockContent>)>> {
+        let block_list = blocks::table
+            .filter(blocks::document_id.eq(document_id))
+            .order(blocks::line_number.asc())
+            .get_results::<Block>(conn)?;
+
+        let block_list = document_order(block_list);
+
+        let ids = block_list.iter().map(|block| block.id.clone()).collect::<Vec<_>>();
+        let mut contents = Self::contents(conn, &ids)?;
+
+        Ok(block_list
+            .into_iter()
+            .map(|block| {
+                let content = contents.remove(&block.id);
+                (block, content)
+            })
+            .collect())
+    }
+
+    /// Users who last edited at least one block of the document, with how
+    /// many blocks they own, most prolific first.
+    pub fn authors(conn: &mut DBPooledConnection, document_id: &str) -> QueryResult<Vec<(User, i64, NaiveDateTime)>> {
+        use diesel::dsl::count_star;
+
+        blocks::table
+            .inner_join(users::table)
+            .filter(blocks::document_id.eq(document_id))
+            .group_by(users::id)
+            .select((users::all_columns, count_star(), diesel::dsl::max(blocks::last_edited_at).assume_not_null()))
+            .order(count_star().desc())
+            .get_results::<(User, i64, NaiveDateTime)>(conn)
+    }
+
+    pub fn content(conn: &mut DBPooledConnection, id: &str) -> Option<BlockContent> {
+        Self::contents(conn, &[id.to_string()])
+            .ok()?
+            .remove(id)
+    }
+}
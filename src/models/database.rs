@@ -0,0 +1,1118 @@
+use std::{collections::{HashMap, HashSet}, io::Write};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::{Queryable, Insertable, AsChangeset, prelude::*, AsExpression, FromSqlRow, dsl::sql, pg::{Pg, PgValue}, serialize::{self, ToSql, Output, IsNull}, deserialize::{self, FromSql}, sql_types::{Bpchar, Text}, result::{Error::{NotFound, DatabaseError}, DatabaseErrorKind::UniqueViolation}};
+use juniper::{graphql_object, GraphQLEnum, GraphQLInputObject, GraphQLObject};
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+
+use crate::{schemas::root::Context, validation_result, db::DBPooledConnection, schema::{documents, database_properties, property_values, database_views, sql_types}, helpers::{errors::{FieldErrors, FieldError}, html::safe_url, slug::slugify, sql::escape_like}};
+
+use super::{document::{Document, DocumentOperation, DocumentResult, NewDocument}, user::{User, UserOperation, UserResult}, link::LinkOperation};
+
+pub const MAX_PROPERTIES: i64 = 100;
+pub const MAX_OPTIONS: usize = 100;
+pub const MAX_FILTERS: usize = 20;
+pub const MAX_SORTS: usize = 5;
+pub const MAX_URL_LENGTH: usize = 2048;
+pub const DEFAULT_PAGE_SIZE: i32 = 50;
+pub const MAX_PAGE_SIZE: i32 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, GraphQLEnum, AsExpression, FromSqlRow)]
+#[diesel(sql_type = sql_types::PropertyType)]
+pub enum PropertyType {
+    Select,
+    MultiSelect,
+    Date,
+    Person,
+    Number,
+    Url,
+}
+
+impl ToSql<sql_types::PropertyType, Pg> for PropertyType {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        match *self {
+            PropertyType::Select => out.write_all(b"SELECT")?,
+            PropertyType::MultiSelect => out.write_all(b"MULTI_SELECT")?,
+            PropertyType::Date => out.write_all(b"DATE")?,
+            PropertyType::Person => out.write_all(b"PERSON")?,
+            PropertyType::Number => out.write_all(b"NUMBER")?,
+            PropertyType::Url => out.write_all(b"URL")?,
+        }
+
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<sql_types::PropertyType, Pg> for PropertyType {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"SELECT" => Ok(PropertyType::Select),
+            b"MULTI_SELECT" => Ok(PropertyType::MultiSelect),
+            b"DATE" => Ok(PropertyType::Date),
+            b"PERSON" => Ok(PropertyType::Person),
+            b"NUMBER" => Ok(PropertyType::Number),
+            b"URL" => Ok(PropertyType::Url),
+            _ => Err("unrecognized property type variant".into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, GraphQLEnum, AsExpression, FromSqlRow)]
+#[diesel(sql_type = sql_types::ViewLayout)]
+pub enum ViewLayout {
+    Table,
+    #[graphql(description = "Columns for the options of a select property")]
+    Board,
+    #[graphql(description = "Rows placed on the days of a date property")]
+    Calendar,
+}
+
+impl ToSql<sql_types::ViewLayout, Pg> for ViewLayout {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        match *self {
+            ViewLayout::Table => out.write_all(b"TABLE")?,
+            ViewLayout::Board => out.write_all(b"BOARD")?,
+            ViewLayout::Calendar => out.write_all(b"CALENDAR")?,
+        }
+
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<sql_types::ViewLayout, Pg> for ViewLayout {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"TABLE" => Ok(ViewLayout::Table),
+            b"BOARD" => Ok(ViewLayout::Board),
+            b"CALENDAR" => Ok(ViewLayout::Calendar),
+            _ => Err("unrecognized view layout variant".into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, GraphQLEnum, Serialize, Deserialize)]
+pub enum FilterOperator {
+    Equals,
+    NotEquals,
+    #[graphql(description = "Urls containing the text, multi-selects with the option picked")]
+    Contains,
+    LessThan,
+    GreaterThan,
+    IsEmpty,
+    IsNotEmpty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, GraphQLEnum, Serialize, Deserialize)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+#[derive(Clone, GraphQLObject, Serialize, Deserialize)]
+#[graphql(Context = Context)]
+pub struct PropertyFilter {
+    pub property_id: String,
+    pub operator: FilterOperator,
+    pub value: Option<String>,
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(Context = Context)]
+pub struct PropertyFilterInput {
+    pub property_id: String,
+    pub operator: FilterOperator,
+    #[graphql(description = "Written like the values of the property, left out for isEmpty and isNotEmpty")]
+    pub value: Option<String>,
+}
+
+impl From<PropertyFilterInput> for PropertyFilter {
+    fn from(input: PropertyFilterInput) -> PropertyFilter {
+        PropertyFilter { property_id: input.property_id, operator: input.operator, value: input.value }
+    }
+}
+
+#[derive(Clone, GraphQLObject, Serialize, Deserialize)]
+#[graphql(Context = Context)]
+pub struct PropertySort {
+    pub property_id: String,
+    pub direction: SortDirection,
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(Context = Context)]
+pub struct PropertySortInput {
+    pub property_id: String,
+    pub direction: Option<SortDirection>,
+}
+
+impl From<PropertySortInput> for PropertySort {
+    fn from(input: PropertySortInput) -> PropertySort {
+        PropertySort { property_id: input.property_id, direction: input.direction.unwrap_or(SortDirection::Ascending) }
+    }
+}
+
+#[derive(Queryable)]
+pub struct DatabaseProperty {
+    pub id: String,
+    pub database_id: String,
+    pub name: String,
+    pub property_type: PropertyType,
+    pub options: serde_json::Value,
+    pub position: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "DatabaseProperty",
+    description = "A typed property every row of a database has",
+    context = Context
+)]
+impl DatabaseProperty {
+    #[graphql(description = "The properties ID in base64 format")]
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[graphql(description = "")]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[graphql(name = "type")]
+    fn property_type(&self) -> PropertyType {
+        self.property_type
+    }
+
+    #[graphql(description = "Values a select or multi-select property can have, in order")]
+    fn options(&self) -> Vec<String> {
+        self.option_list()
+    }
+
+    #[graphql(description = "DateTime for when the property was created")]
+    fn created_at(&self) -> &NaiveDateTime {
+        &self.created_at
+    }
+
+    #[graphql(description = "DateTime for when the property was last updated")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+validation_result!(DatabasePropertyResult, DatabaseProperty);
+
+/// A parsed value of a property.
+enum Value {
+    Text(String),
+    Number(f64),
+    Date(NaiveDate),
+    User(String),
+}
+
+impl DatabaseProperty {
+    pub fn option_list(&self) -> Vec<String> {
+        serde_json::from_value(self.options.clone()).unwrap_or_default()
+    }
+
+    /// Reads a value written the way the api takes it: numbers as decimals,
+    /// dates as YYYY-MM-DD, people as user IDs and options by name. A
+    /// multi-select value is a single option.
+    fn parse(&self, value: &str) -> Result<Value, String> {
+        let value = value.trim();
+
+        match self.property_type {
+            PropertyType::Select | PropertyType::MultiSelect if self.option_list().iter().any(|option| option == value) =>
+                Ok(Value::Text(value.into())),
+            PropertyType::Select | PropertyType::MultiSelect => Err(format!("{} isn't one of the options", value)),
+            PropertyType::Date => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(Value::Date)
+                .map_err(|_| format!("{} isn't a date in the YYYY-MM-DD format", value)),
+            PropertyType::Number => match value.parse::<f64>() {
+                Ok(number) if number.is_finite() => Ok(Value::Number(number)),
+                _ => Err(format!("{} isn't a number", value)),
+            },
+            PropertyType::Person => Ok(Value::User(value.into())),
+            PropertyType::Url if value.len() > MAX_URL_LENGTH => Err(format!("can't have more than {} characters", MAX_URL_LENGTH)),
+            PropertyType::Url => match safe_url(value) {
+                Some(url) => Ok(Value::Text(url.into())),
+                None => Err(String::from("must be an http(s) or root relative url")),
+            },
+        }
+    }
+
+    fn allows(&self, operator: FilterOperator) -> bool {
+        match operator {
+            FilterOperator::IsEmpty | FilterOperator::IsNotEmpty => true,
+            FilterOperator::Contains => matches!(self.property_type, PropertyType::MultiSelect | PropertyType::Url),
+            FilterOperator::Equals | FilterOperator::NotEquals => self.property_type != PropertyType::MultiSelect,
+            FilterOperator::LessThan | FilterOperator::GreaterThan => matches!(self.property_type, PropertyType::Number | PropertyType::Date),
+        }
+    }
+
+    /// The value a filter compares with. Urls are matched on any text.
+    fn filter_value(&self, filter: &PropertyFilter) -> Result<Option<Value>, String> {
+        match (filter.operator, filter.value.as_deref().map(str::trim)) {
+            (FilterOperator::IsEmpty | FilterOperator::IsNotEmpty, _) => Ok(None),
+            (_, None | Some("")) => Err(String::from("need a value to compare with")),
+            (FilterOperator::Contains, Some(text)) if self.property_type == PropertyType::Url => Ok(Some(Value::Text(text.into()))),
+            (_, Some(value)) => self.parse(value).map(Some),
+        }
+    }
+
+    /// Expression the rows are ordered by, looked up in a subquery so rows
+    /// without a value can come last.
+    fn sort_expression(&self) -> &'static str {
+        match self.property_type {
+            PropertyType::Select | PropertyType::Url => "text_value",
+            PropertyType::MultiSelect => "array_to_string(option_values, ',')",
+            PropertyType::Date => "date_value",
+            PropertyType::Number => "number_value",
+            PropertyType::Person => "(select email from users where users.id = property_values.user_id)",
+        }
+    }
+}
+
+#[derive(Queryable)]
+pub struct PropertyValue {
+    pub document_id: String,
+    pub property_id: String,
+    pub text_value: Option<String>,
+    pub option_values: Option<Vec<Option<String>>>,
+    pub number_value: Option<f64>,
+    pub date_value: Option<NaiveDate>,
+    pub user_id: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "PropertyValue",
+    description = "The value a database row has for one of the properties",
+    context = Context
+)]
+impl PropertyValue {
+    #[graphql(description = "The property the value is for")]
+    fn property(&self, context: &Context) -> DatabasePropertyResult {
+        let mut conn = context.db_pool.get().unwrap();
+        DatabaseOperation::find_property(&mut conn, &self.property_id)
+    }
+
+    fn property_id(&self) -> &str {
+        &self.property_id
+    }
+
+    #[graphql(description = "The option of a select property or the url of a url property")]
+    fn text(&self) -> Option<&str> {
+        self.text_value.as_deref()
+    }
+
+    #[graphql(description = "The options picked for a multi-select property")]
+    fn options(&self) -> Vec<String> {
+        self.option_values.iter().flatten().flatten().cloned().collect()
+    }
+
+    fn number(&self) -> Option<f64> {
+        self.number_value
+    }
+
+    fn date(&self) -> Option<NaiveDate> {
+        self.date_value
+    }
+
+    #[graphql(description = "The person picked for a person property")]
+    fn user(&self, context: &Context) -> Option<User> {
+        let mut conn = context.db_pool.get().unwrap();
+
+        match UserOperation::find(&mut conn, self.user_id.as_deref()?) {
+            UserResult::User(user) => Some(user),
+            _ => None,
+        }
+    }
+
+    #[graphql(description = "DateTime for when the value was last updated")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+#[derive(Queryable)]
+pub struct DatabaseView {
+    pub id: String,
+    pub database_id: String,
+    pub name: String,
+    pub layout: ViewLayout,
+    pub property_id: Option<String>,
+    pub filters: serde_json::Value,
+    pub sorts: serde_json::Value,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "DatabaseView",
+    description = "A saved way of looking at the rows of a database",
+    context = Context
+)]
+impl DatabaseView {
+    #[graphql(description = "The views ID in base64 format")]
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[graphql(description = "")]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn layout(&self) -> ViewLayout {
+        self.layout
+    }
+
+    #[graphql(description = "The select property a board is grouped by or the date property of a calendar")]
+    fn property(&self, context: &Context) -> Option<DatabasePropertyResult> {
+        let mut conn = context.db_pool.get().unwrap();
+        Some(DatabaseOperation::find_property(&mut conn, self.property_id.as_deref()?))
+    }
+
+    #[graphql(description = "Filters rows have to match all of")]
+    fn filters(&self) -> Vec<PropertyFilter> {
+        self.filter_list()
+    }
+
+    #[graphql(description = "Sorts applied in order")]
+    fn sorts(&self) -> Vec<PropertySort> {
+        self.sort_list()
+    }
+
+    #[graphql(description = "DateTime for when the view was created")]
+    fn created_at(&self) -> &NaiveDateTime {
+        &self.created_at
+    }
+
+    #[graphql(description = "DateTime for when the view was last updated")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+validation_result!(DatabaseViewResult, DatabaseView);
+
+impl DatabaseView {
+    pub fn filter_list(&self) -> Vec<PropertyFilter> {
+        serde_json::from_value(self.filters.clone()).unwrap_or_default()
+    }
+
+    pub fn sort_list(&self) -> Vec<PropertySort> {
+        serde_json::from_value(self.sorts.clone()).unwrap_or_default()
+    }
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct DatabaseRow {
+    #[graphql(description = "Pass as after to get the rows that come after this one")]
+    pub cursor: String,
+    pub document: Document,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct DatabaseRows {
+    #[graphql(description = "How many rows match the filters")]
+    pub total_count: i32,
+    pub rows: Vec<DatabaseRow>,
+    pub end_cursor: Option<String>,
+    pub has_next_page: bool,
+}
+
+validation_result!(DatabaseRowsResult, DatabaseRows);
+
+#[derive(Insertable)]
+#[diesel(table_name = database_properties)]
+pub struct NewDatabaseProperty {
+    pub id: String,
+    pub database_id: String,
+    pub name: String,
+    pub property_type: PropertyType,
+    pub options: serde_json::Value,
+    pub position: i32,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = property_values, treat_none_as_null = true)]
+pub struct NewPropertyValue {
+    pub document_id: String,
+    pub property_id: String,
+    pub text_value: Option<String>,
+    pub option_values: Option<Vec<Option<String>>>,
+    pub number_value: Option<f64>,
+    pub date_value: Option<NaiveDate>,
+    pub user_id: Option<String>,
+}
+
+impl NewPropertyValue {
+    fn new(document_id: &str, property_id: &str) -> NewPropertyValue {
+        NewPropertyValue {
+            document_id: document_id.into(),
+            property_id: property_id.into(),
+            text_value: None,
+            option_values: None,
+            number_value: None,
+            date_value: None,
+            user_id: None,
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = database_views)]
+pub struct NewDatabaseView {
+    pub id: String,
+    pub database_id: String,
+    pub name: String,
+    pub layout: ViewLayout,
+    pub property_id: Option<String>,
+    pub filters: serde_json::Value,
+    pub sorts: serde_json::Value,
+}
+
+/// Checks the filters and sorts only use properties of the database in a
+/// way that fits their type.
+pub fn validate(properties: &[DatabaseProperty], filters: &[PropertyFilter], sorts: &[PropertySort], errors: &mut FieldErrors) {
+    let property = |id: &str| properties.iter().find(|property| property.id == id);
+
+    if filters.len() > MAX_FILTERS {
+        errors.push(FieldError::new("filter", &format!("can't have more than {} filters", MAX_FILTERS)));
+    }
+
+    for filter in filters {
+        let Some(property) = property(&filter.property_id) else {
+            errors.push(FieldError::new("filter", &format!("property {} doesn't exist", filter.property_id)));
+            continue;
+        };
+
+        if !property.allows(filter.operator) {
+            errors.push(FieldError::new("filter", &format!("{} can't be filtered with this operator", property.name)));
+        } else if let Err(message) = property.filter_value(filter) {
+            errors.push(FieldError::new("filter", &format!("{} {}", property.name, message)));
+        }
+    }
+
+    if sorts.len() > MAX_SORTS {
+        errors.push(FieldError::new("sort", &format!("can't have more than {} sorts", MAX_SORTS)));
+    }
+
+    for sort in sorts {
+        if property(&sort.property_id).is_none() {
+            errors.push(FieldError::new("sort", &format!("property {} doesn't exist", sort.property_id)));
+        }
+    }
+}
+
+fn validate_options(property_type: PropertyType, options: &[String], errors: &mut FieldErrors) {
+    match property_type {
+        PropertyType::Select | PropertyType::MultiSelect => {
+            let unique = options.iter().collect::<HashSet<_>>();
+
+            if options.is_empty() || options.len() > MAX_OPTIONS {
+                errors.push(FieldError::new("options", &format!("must have between 1 and {} options", MAX_OPTIONS)));
+            }
+
+            if unique.len() != options.len() || options.iter().any(|option| option.is_empty() || option.len() > 256) {
+                errors.push(FieldError::new("options", "have to be unique and between 1 and 256 characters"));
+            }
+        },
+        _ if !options.is_empty() => {
+            errors.push(FieldError::new("options", "only select and multi-select properties have options"));
+        },
+        _ => {},
+    }
+}
+
+/// The rows of a database matching every filter. Each filter is a
+/// subquery on the values of its property so it runs in the database.
+fn filtered<'a>(database_id: &'a str, properties: &'a [DatabaseProperty], filters: &'a [PropertyFilter]) -> documents::BoxedQuery<'a, Pg> {
+    let mut query = documents::table
+        .filter(documents::database_id.eq(database_id))
+        .into_boxed();
+
+    for filter in filters {
+        let Some(property) = properties.iter().find(|property| property.id == filter.property_id) else {
+            continue;
+        };
+
+        let values = || property_values::table
+            .filter(property_values::property_id.eq(&property.id))
+            .select(property_values::document_id)
+            .into_boxed();
+
+        let Ok(value) = property.filter_value(filter) else {
+            continue;
+        };
+
+        let matching = match (filter.operator, value) {
+            (FilterOperator::IsEmpty | FilterOperator::IsNotEmpty, _) => values(),
+            (FilterOperator::Contains, Some(Value::Text(text))) if property.property_type == PropertyType::Url =>
+                values().filter(property_values::text_value.ilike(format!("%{}%", escape_like(&text)))),
+            (FilterOperator::Contains, Some(Value::Text(option))) => values().filter(property_values::option_values.contains(vec![Some(option)])),
+            (FilterOperator::Equals | FilterOperator::NotEquals, Some(Value::Text(text))) => values().filter(property_values::text_value.eq(text)),
+            (FilterOperator::Equals | FilterOperator::NotEquals, Some(Value::User(user_id))) => values().filter(property_values::user_id.eq(user_id)),
+            (FilterOperator::Equals | FilterOperator::NotEquals, Some(Value::Number(number))) => values().filter(property_values::number_value.eq(number)),
+            (FilterOperator::LessThan, Some(Value::Number(number))) => values().filter(property_values::number_value.lt(number)),
+            (FilterOperator::GreaterThan, Some(Value::Number(number))) => values().filter(property_values::number_value.gt(number)),
+            (FilterOperator::Equals | FilterOperator::NotEquals, Some(Value::Date(date))) => values().filter(property_values::date_value.eq(date)),
+            (FilterOperator::LessThan, Some(Value::Date(date))) => values().filter(property_values::date_value.lt(date)),
+            (FilterOperator::GreaterThan, Some(Value::Date(date))) => values().filter(property_values::date_value.gt(date)),
+            _ => continue,
+        };
+
+        // rows without a value are empty and not equal to anything
+        query = match filter.operator {
+            FilterOperator::IsEmpty | FilterOperator::NotEquals => query.filter(documents::id.ne_all(matching)),
+            _ => query.filter(documents::id.eq_any(matching)),
+        };
+    }
+
+    query
+}
+
+pub struct DatabaseOperation;
+
+impl DatabaseOperation {
+    pub fn make_database(conn: &mut DBPooledConnection, id: &str) -> DocumentResult {
+        let document = diesel::update(documents::table)
+            .filter(documents::id.eq(id))
+            .set(documents::is_database.eq(true))
+            .get_result::<Document>(conn);
+
+        match document {
+            Ok(document) => DocumentResult::Document(document),
+            Err(NotFound) => DocumentResult::not_found("document not found"),
+            Err(_) => DocumentResult::server(),
+        }
+    }
+
+    /// Gives a copy of a database the same properties and views, without
+    /// any rows. Returns the ID of the copy of each property.
+    pub fn copy_schema(conn: &mut DBPooledConnection, from_id: &str, to_id: &str) -> QueryResult<HashMap<String, String>> {
+        diesel::update(documents::table)
+            .filter(documents::id.eq(to_id))
+            .set(documents::is_database.eq(true))
+            .execute(conn)?;
+
+        let mut new_ids = HashMap::new();
+
+        for property in Self::properties(conn, from_id) {
+            let new_property = NewDatabaseProperty {
+                id: nanoid!(),
+                database_id: to_id.into(),
+                name: property.name,
+                property_type: property.property_type,
+                options: property.options,
+                position: property.position,
+            };
+
+            diesel::insert_into(database_properties::table)
+                .values(&new_property)
+                .execute(conn)?;
+
+            new_ids.insert(property.id, new_property.id);
+        }
+
+        let copy_id = |property_id: &str| new_ids.get(property_id).cloned().unwrap_or_default();
+
+        for view in Self::views(conn, from_id) {
+            let filters = view.filter_list()
+                .into_iter()
+                .map(|filter| PropertyFilter { property_id: copy_id(&filter.property_id), ..filter })
+                .collect::<Vec<_>>();
+
+            let sorts = view.sort_list()
+                .into_iter()
+                .map(|sort| PropertySort { property_id: copy_id(&sort.property_id), ..sort })
+                .collect::<Vec<_>>();
+
+            let new_view = NewDatabaseView {
+                id: nanoid!(),
+                database_id: to_id.into(),
+                name: view.name,
+                layout: view.layout,
+                property_id: view.property_id.as_deref().map(copy_id),
+                filters: serde_json::to_value(&filters).unwrap_or_default(),
+                sorts: serde_json::to_value(&sorts).unwrap_or_default(),
+            };
+
+            diesel::insert_into(database_views::table)
+                .values(&new_view)
+                .execute(conn)?;
+        }
+
+        Ok(new_ids)
+    }
+
+    /// Gives a copy of a database row the values of the row. Values of the
+    /// properties in `property_ids` go to the copies of the properties, for
+    /// rows copied along with their database.
+    pub fn copy_values(conn: &mut DBPooledConnection, from_id: &str, to_id: &str, property_ids: &HashMap<String, String>) -> QueryResult<()> {
+        let new_values = Self::values(conn, from_id)
+            .into_iter()
+            .map(|value| NewPropertyValue {
+                document_id: to_id.into(),
+                property_id: property_ids.get(&value.property_id).cloned().unwrap_or(value.property_id),
+                text_value: value.text_value,
+                option_values: value.option_values,
+                number_value: value.number_value,
+                date_value: value.date_value,
+                user_id: value.user_id,
+            })
+            .collect::<Vec<_>>();
+
+        diesel::insert_into(property_values::table)
+            .values(&new_values)
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    fn find_database(conn: &mut DBPooledConnection, id: &str) -> QueryResult<Option<Document>> {
+        let document = documents::table
+            .filter(documents::id.eq(id))
+            .get_result::<Document>(conn)?;
+
+        Ok(document.is_database.then_some(document))
+    }
+
+    pub fn add_property(conn: &mut DBPooledConnection, database_id: &str, name: &str, property_type: PropertyType, options: Vec<String>) -> DatabasePropertyResult {
+        let options = options
+            .iter()
+            .map(|option| option.trim().to_string())
+            .collect::<Vec<_>>();
+
+        let mut errors = FieldErrors::new();
+        validate_options(property_type, &options, &mut errors);
+
+        if !errors.empty() {
+            return DatabasePropertyResult::FieldErrors(errors);
+        }
+
+        let result = conn.transaction(|conn| {
+            if Self::find_database(conn, database_id)?.is_none() {
+                return Ok(None);
+            }
+
+            let count = database_properties::table
+                .filter(database_properties::database_id.eq(database_id))
+                .count()
+                .get_result::<i64>(conn)?;
+
+            if count >= MAX_PROPERTIES {
+                return Ok(None);
+            }
+
+            let new_property = NewDatabaseProperty {
+                id: nanoid!(),
+                database_id: database_id.into(),
+                name: name.into(),
+                property_type,
+                options: serde_json::to_value(&options).unwrap_or_default(),
+                position: count as i32,
+            };
+
+            diesel::insert_into(database_properties::table)
+                .values(&new_property)
+                .get_result::<DatabaseProperty>(conn)
+                .map(Some)
+        });
+
+        match result {
+            Ok(Some(property)) => DatabasePropertyResult::DatabaseProperty(property),
+            Ok(None) => {
+                let mut errors = FieldErrors::new();
+                errors.push(FieldError::new("documentId", &format!("has to be a database with less than {} properties", MAX_PROPERTIES)));
+                DatabasePropertyResult::FieldErrors(errors)
+            },
+            Err(NotFound) => DatabasePropertyResult::not_found("document not found"),
+            Err(DatabaseError(UniqueViolation, _)) => {
+                let mut errors = FieldErrors::new();
+                errors.push(FieldError::new("name", "is already taken"));
+                DatabasePropertyResult::FieldErrors(errors)
+            },
+            Err(_) => DatabasePropertyResult::server(),
+        }
+    }
+
+    /// Deletes the property along with its values and the views built on
+    /// it. Filters and sorts of other views that use it are left out when
+    /// they are read.
+    pub fn delete_property(conn: &mut DBPooledConnection, id: &str) -> DatabasePropertyResult {
+        let result = conn.transaction(|conn| {
+            diesel::delete(property_values::table.filter(property_values::property_id.eq(id))).execute(conn)?;
+            diesel::delete(database_views::table.filter(database_views::property_id.eq(id))).execute(conn)?;
+
+            let property = diesel::delete(database_properties::table.filter(database_properties::id.eq(id)))
+                .get_result::<DatabaseProperty>(conn)?;
+
+            diesel::update(database_properties::table)
+                .filter(database_properties::database_id.eq(&property.database_id))
+                .filter(database_properties::position.gt(property.position))
+                .set(database_properties::position.eq(database_properties::position - 1))
+                .execute(conn)?;
+
+            Ok(property)
+        });
+
+        match result {
+            Ok(property) => DatabasePropertyResult::DatabaseProperty(property),
+            Err(NotFound) => DatabasePropertyResult::not_found("property not found"),
+            Err(_) => DatabasePropertyResult::server(),
+        }
+    }
+
+    /// Adds a document to the repository of the database as a new row.
+    pub fn create_row(conn: &mut DBPooledConnection, database_id: &str, name: &str) -> DocumentResult {
+        let result = conn.transaction(|conn| {
+            let Some(database) = Self::find_database(conn, database_id)? else {
+                return Ok(None);
+            };
+
+            let new_document = NewDocument {
+                database_id: Some(database.id),
+                position: DocumentOperation::next_position(conn, &database.repository_id, None)?,
+                ..NewDocument::new(&database.repository_id, &slugify(name), name, None)
+            };
+
+            let document = DocumentOperation::insert_with_available_slug(conn, new_document)?;
+
+            LinkOperation::resolve_dangling(conn, &document)?;
+
+            Ok(Some(document))
+        });
+
+        match result {
+            Ok(Some(document)) => DocumentResult::Document(document),
+            Ok(None) => {
+                let mut errors = FieldErrors::new();
+                errors.push(FieldError::new("databaseId", "isn't a database"));
+                DocumentResult::FieldErrors(errors)
+            },
+            Err(NotFound) => DocumentResult::not_found("database not found"),
+            Err(_) => DocumentResult::server(),
+        }
+    }
+
+    /// Sets or, when `value` and `values` are both empty, clears the value
+    /// a row has for a property. Multi-select properties take `values`,
+    /// the others `value`.
+    pub fn set_value(conn: &mut DBPooledConnection, document_id: &str, property_id: &str, value: Option<&str>, values: Option<Vec<String>>) -> DocumentResult {
+        let property = match Self::find_property(conn, property_id) {
+            DatabasePropertyResult::DatabaseProperty(property) => property,
+            _ => return DocumentResult::not_found("property not found"),
+        };
+
+        let document = match DocumentOperation::find(conn, document_id) {
+            DocumentResult::Document(document) => document,
+            result => return result,
+        };
+
+        let mut errors = FieldErrors::new();
+
+        if document.database_id.as_deref() != Some(&property.database_id) {
+            errors.push(FieldError::new("propertyId", "isn't a property of the database the document is a row of"));
+            return DocumentResult::FieldErrors(errors);
+        }
+
+        let mut new_value = NewPropertyValue::new(document_id, property_id);
+
+        match (property.property_type, value.map(str::trim).filter(|value| !value.is_empty()), values) {
+            (PropertyType::MultiSelect, Some(_), _) => {
+                errors.push(FieldError::new("value", "multi-select properties take values"));
+            },
+            (PropertyType::MultiSelect, None, values) => {
+                let mut options = Vec::new();
+
+                for option in values.unwrap_or_default() {
+                    match property.parse(&option) {
+                        Ok(Value::Text(option)) if !options.contains(&Some(option.clone())) => options.push(Some(option)),
+                        Ok(_) => {},
+                        Err(message) => {
+                            errors.push(FieldError::new("values", &message));
+                        },
+                    }
+                }
+
+                new_value.option_values = Some(options).filter(|options| !options.is_empty());
+            },
+            (_, _, Some(_)) => {
+                errors.push(FieldError::new("values", "only multi-select properties take values"));
+            },
+            (_, None, None) => {},
+            (_, Some(value), None) => match property.parse(value) {
+                Ok(Value::Text(text)) => new_value.text_value = Some(text),
+                Ok(Value::Number(number)) => new_value.number_value = Some(number),
+                Ok(Value::Date(date)) => new_value.date_value = Some(date),
+                Ok(Value::User(user_id)) => match UserOperation::find(conn, &user_id) {
+                    UserResult::User(user) => new_value.user_id = Some(user.id),
+                    _ => {
+                        errors.push(FieldError::new("value", "user not found"));
+                    },
+                },
+                Err(message) => {
+                    errors.push(FieldError::new("value", &message));
+                },
+            },
+        }
+
+        if !errors.empty() {
+            return DocumentResult::FieldErrors(errors);
+        }
+
+        let is_empty = new_value.text_value.is_none()
+            && new_value.option_values.is_none()
+            && new_value.number_value.is_none()
+            && new_value.date_value.is_none()
+            && new_value.user_id.is_none();
+
+        let result = conn.transaction(|conn| {
+            if is_empty {
+                diesel::delete(property_values::table)
+                    .filter(property_values::document_id.eq(document_id))
+                    .filter(property_values::property_id.eq(property_id))
+                    .execute(conn)?;
+            } else {
+                diesel::insert_into(property_values::table)
+                    .values(&new_value)
+                    .on_conflict((property_values::document_id, property_values::property_id))
+                    .do_update()
+                    .set(&new_value)
+                    .execute(conn)?;
+            }
+
+            diesel::update(documents::table)
+                .filter(documents::id.eq(document_id))
+                .set(documents::updated_at.eq(diesel::dsl::now))
+                .get_result::<Document>(conn)
+        });
+
+        match result {
+            Ok(document) => DocumentResult::Document(document),
+            Err(_) => DocumentResult::server(),
+        }
+    }
+
+    pub fn create_view(conn: &mut DBPooledConnection, database_id: &str, name: &str, layout: ViewLayout, property_id: Option<&str>, filters: Vec<PropertyFilter>, sorts: Vec<PropertySort>) -> DatabaseViewResult {
+        let properties = Self::properties(conn, database_id);
+        let property = property_id.and_then(|id| properties.iter().find(|property| property.id == id));
+
+        let mut errors = FieldErrors::new();
+
+        match (layout, property_id, property.map(|property| property.property_type)) {
+            (ViewLayout::Table, None, _) => {},
+            (ViewLayout::Table, Some(_), _) => {
+                errors.push(FieldError::new("propertyId", "only boards and calendars have a property"));
+            },
+            (_, Some(_), None) => {
+                errors.push(FieldError::new("propertyId", "isn't a property of the database"));
+            },
+            (ViewLayout::Board, _, Some(PropertyType::Select)) | (ViewLayout::Calendar, _, Some(PropertyType::Date)) => {},
+            (ViewLayout::Board, _, _) => {
+                errors.push(FieldError::new("propertyId", "boards are grouped by a select property"));
+            },
+            (ViewLayout::Calendar, _, _) => {
+                errors.push(FieldError::new("propertyId", "calendars need a date property"));
+            },
+        }
+
+        validate(&properties, &filters, &sorts, &mut errors);
+
+        if !errors.empty() {
+            return DatabaseViewResult::FieldErrors(errors);
+        }
+
+        let new_view = NewDatabaseView {
+            id: nanoid!(),
+            database_id: database_id.into(),
+            name: name.into(),
+            layout,
+            property_id: property_id.map(String::from),
+            filters: serde_json::to_value(&filters).unwrap_or_default(),
+            sorts: serde_json::to_value(&sorts).unwrap_or_default(),
+        };
+
+        let result = conn.transaction(|conn| {
+            if Self::find_database(conn, database_id)?.is_none() {
+                return Ok(None);
+            }
+
+            diesel::insert_into(database_views::table)
+                .values(&new_view)
+                .get_result::<DatabaseView>(conn)
+                .map(Some)
+        });
+
+        match result {
+            Ok(Some(view)) => DatabaseViewResult::DatabaseView(view),
+            Ok(None) => {
+                let mut errors = FieldErrors::new();
+                errors.push(FieldError::new("documentId", "isn't a database"));
+                DatabaseViewResult::FieldErrors(errors)
+            },
+            Err(NotFound) => DatabaseViewResult::not_found("document not found"),
+            Err(_) => DatabaseViewResult::server(),
+        }
+    }
+
+    pub fn delete_view(conn: &mut DBPooledConnection, id: &str) -> DatabaseViewResult {
+        let view = diesel::delete(database_views::table.filter(database_views::id.eq(id)))
+            .get_result::<DatabaseView>(conn);
+
+        match view {
+            Ok(view) => DatabaseViewResult::DatabaseView(view),
+            Err(NotFound) => DatabaseViewResult::not_found("view not found"),
+            Err(_) => DatabaseViewResult::server(),
+        }
+    }
+
+    /// A page of the rows matching the filters in the order of the sorts,
+    /// then by name. Cursors are positions in that order.
+    pub fn query(conn: &mut DBPooledConnection, database_id: &str, filters: &[PropertyFilter], sorts: &[PropertySort], first: i32, after: Option<&str>) -> DatabaseRowsResult {
+        let properties = Self::properties(conn, database_id);
+        let mut errors = FieldErrors::new();
+
+        validate(&properties, filters, sorts, &mut errors);
+
+        let offset = match after.map(str::parse::<i64>) {
+            None => 0,
+            Some(Ok(offset)) if offset >= 0 => offset,
+            Some(_) => {
+                errors.push(FieldError::new("after", "isn't a cursor of this database"));
+                0
+            },
+        };
+
+        if !errors.empty() {
+            return DatabaseRowsResult::FieldErrors(errors);
+        }
+
+        let total_count = filtered(database_id, &properties, filters)
+            .count()
+            .get_result::<i64>(conn);
+
+        let mut query = filtered(database_id, &properties, filters);
+
+        for sort in sorts {
+            let Some(property) = properties.iter().find(|property| property.id == sort.property_id) else {
+                continue;
+            };
+
+            let direction = match sort.direction {
+                SortDirection::Ascending => "asc",
+                SortDirection::Descending => "desc",
+            };
+
+            let subquery = format!(
+                "(select {} from property_values where property_values.document_id = documents.id and property_values.property_id = ",
+                property.sort_expression(),
+            );
+
+            query = query.then_order_by(
+                sql::<Text>(&subquery)
+                    .bind::<Bpchar, _>(property.id.clone())
+                    .sql(&format!(") {} nulls last", direction)),
+            );
+        }
+
+        let document_list = query
+            .then_order_by((documents::name.asc(), documents::id.asc()))
+            .offset(offset)
+            .limit(first as i64 + 1)
+            .get_results::<Document>(conn);
+
+        let (Ok(total_count), Ok(mut document_list)) = (total_count, document_list) else {
+            return DatabaseRowsResult::server();
+        };
+
+        let has_next_page = document_list.len() > first as usize;
+        document_list.truncate(first as usize);
+
+        let rows = document_list
+            .into_iter()
+            .enumerate()
+            .map(|(index, document)| DatabaseRow { cursor: (offset + index as i64 + 1).to_string(), document })
+            .collect::<Vec<_>>();
+
+        DatabaseRowsResult::DatabaseRows(DatabaseRows {
+            total_count: total_count as i32,
+            end_cursor: rows.last().map(|row| row.cursor.clone()),
+            rows,
+            has_next_page,
+        })
+    }
+
+    /// Rows as the view shows them. Filters and sorts on properties that
+    /// were deleted since the view was saved are left out.
+    pub fn query_view(conn: &mut DBPooledConnection, view: &DatabaseView, first: i32, after: Option<&str>) -> DatabaseRowsResult {
+        let property_ids = Self::properties(conn, &view.database_id)
+            .into_iter()
+            .map(|property| property.id)
+            .collect::<HashSet<_>>();
+
+        let mut filters = view.filter_list();
+        let mut sorts = view.sort_list();
+
+        filters.retain(|filter| property_ids.contains(&filter.property_id));
+        sorts.retain(|sort| property_ids.contains(&sort.property_id));
+
+        Self::query(conn, &view.database_id, &filters, &sorts, first, after)
+    }
+
+    pub fn find_property(conn: &mut DBPooledConnection, id: &str) -> DatabasePropertyResult {
+        let property = database_properties::table
+            .filter(database_properties::id.eq(id))
+            .get_result::<DatabaseProperty>(conn);
+
+        match property {
+            Ok(property) => DatabasePropertyResult::DatabaseProperty(property),
+            Err(NotFound) => DatabasePropertyResult::not_found("property not found"),
+            Err(_) => DatabasePropertyResult::server(),
+        }
+    }
+
+    pub fn find_view(conn: &mut DBPooledConnection, id: &str) -> DatabaseViewResult {
+        let view = database_views::table
+            .filter(database_views::id.eq(id))
+            .get_result::<DatabaseView>(conn);
+
+        match view {
+            Ok(view) => DatabaseViewResult::DatabaseView(view),
+            Err(NotFound) => DatabaseViewResult::not_found("view not found"),
+            Err(_) => DatabaseViewResult::server(),
+        }
+    }
+
+    pub fn properties(conn: &mut DBPooledConnection, database_id: &str) -> Vec<DatabaseProperty> {
+        database_properties::table
+            .filter(database_properties::database_id.eq(database_id))
+            .order(database_properties::position.asc())
+            .get_results::<DatabaseProperty>(conn)
+            .unwrap_or_default()
+    }
+
+    pub fn views(conn: &mut DBPooledConnection, database_id: &str) -> Vec<DatabaseView> {
+        database_views::table
+            .filter(database_views::database_id.eq(database_id))
+            .order((database_views::created_at.asc(), database_views::id.asc()))
+            .get_results::<DatabaseView>(conn)
+            .unwrap_or_default()
+    }
+
+    pub fn values(conn: &mut DBPooledConnection, document_id: &str) -> Vec<PropertyValue> {
+        property_values::table
+            .inner_join(database_properties::table)
+            .filter(property_values::document_id.eq(document_id))
+            .order(database_properties::position.asc())
+            .select(property_values::all_columns)
+            .get_results::<PropertyValue>(conn)
+            .unwrap_or_default()
+    }
+}
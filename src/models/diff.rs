@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+
+use juniper::{graphql_object, GraphQLObject, GraphQLUnion};
+
+use crate::{schemas::root::Context, validation_result, db::DBPooledConnection, helpers::{diff::{lcs, diff_words, TextSegment}, errors::{FieldErrors, FieldError}, permission::Permission}};
+
+use super::{block::Tag, document::{DocumentOperation, DocumentResult}, revision::{DocumentRevision, RevisionBlock, RevisionOperation, DocumentRevisionResult}};
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct BlockAdded {
+    pub block: RevisionBlock,
+    pub position: i32,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct BlockRemoved {
+    pub block: RevisionBlock,
+    pub position: i32,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct BlockMoved {
+    pub block_id: String,
+    pub from_position: i32,
+    pub to_position: i32,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct BlockTagChanged {
+    pub block_id: String,
+    pub from: Tag,
+    pub to: Tag,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct BlockImageChanged {
+    pub block_id: String,
+    pub from_url: Option<String>,
+    pub to_url: Option<String>,
+    pub from_alt: Option<String>,
+    pub to_alt: Option<String>,
+    pub from_caption: Option<String>,
+    pub to_caption: Option<String>,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct BlockPropertiesChanged {
+    pub block_id: String,
+    #[graphql(description = "Names of the changed properties, such as checked or indent")]
+    pub properties: Vec<String>,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct BlockTextChanged {
+    pub block_id: String,
+    pub segments: Vec<TextSegment>,
+}
+
+#[derive(GraphQLUnion)]
+#[graphql(context = Context)]
+pub enum BlockChange {
+    BlockAdded(BlockAdded),
+    BlockRemoved(BlockRemoved),
+    BlockMoved(BlockMoved),
+    BlockTagChanged(BlockTagChanged),
+    BlockImageChanged(BlockImageChanged),
+    BlockPropertiesChanged(BlockPropertiesChanged),
+    BlockTextChanged(BlockTextChanged),
+}
+
+pub struct DocumentDiff {
+    pub from: DocumentRevision,
+    pub to: DocumentRevision,
+    pub changes: Vec<BlockChange>,
+}
+
+#[graphql_object(
+    name = "DocumentDiff",
+    description = "Block level changes between two revisions of a document",
+    context = Context
+)]
+impl DocumentDiff {
+    #[graphql(description = "The document both revisions belong to")]
+    fn document(&self, context: &Context) -> DocumentResult {
+        let mut conn = context.db_pool.get().unwrap();
+        DocumentOperation::find(&mut conn, &self.to.document_id)
+    }
+
+    #[graphql(description = "The older revision")]
+    fn from_revision(&self) -> &DocumentRevision {
+        &self.from
+    }
+
+    #[graphql(description = "The newer revision")]
+    fn to_revision(&self) -> &DocumentRevision {
+        &self.to
+    }
+
+    #[graphql(description = "The changes needed to get from the older revision to the newer one")]
+    fn changes(&self) -> &[BlockChange] {
+        &self.changes
+    }
+}
+
+validation_result!(DocumentDiffResult, DocumentDiff);
+
+fn diff_blocks(from_blocks: &[RevisionBlock], to_blocks: &[RevisionBlock]) -> Vec<BlockChange> {
+    let from_positions = from_blocks
+        .iter()
+        .enumerate()
+        .map(|(position, block)| (block.id.as_str(), position))
+        .collect::<HashMap<_, _>>();
+
+    let to_positions = to_blocks
+        .iter()
+        .enumerate()
+        .map(|(position, block)| (block.id.as_str(), position))
+        .collect::<HashMap<_, _>>();
+
+    let mut changes = Vec::new();
+
+    for (position, block) in from_blocks.iter().enumerate() {
+        if !to_positions.contains_key(block.id.as_str()) {
+            changes.push(BlockChange::BlockRemoved(BlockRemoved {
+                block: block.clone(),
+                position: position as i32,
+            }));
+        }
+    }
+
+    let from_common = from_blocks
+        .iter()
+        .map(|block| block.id.as_str())
+        .filter(|id| to_positions.contains_key(id))
+        .collect::<Vec<_>>();
+
+    let to_common = to_blocks
+        .iter()
+        .map(|block| block.id.as_str())
+        .filter(|id| from_positions.contains_key(id))
+        .collect::<Vec<_>>();
+
+    let mut unmoved = vec![false; to_common.len()];
+
+    for (_, j) in lcs(&from_common, &to_common) {
+        unmoved[j] = true;
+    }
+
+    let mut common_index = 0;
+
+    for (position, block) in to_blocks.iter().enumerate() {
+        let Some(&from_position) = from_positions.get(block.id.as_str()) else {
+            changes.push(BlockChange::BlockAdded(BlockAdded {
+                block: block.clone(),
+                position: position as i32,
+            }));
+
+            continue;
+        };
+
+        if !unmoved[common_index] {
+            changes.push(BlockChange::BlockMoved(BlockMoved {
+                block_id: block.id.clone(),
+                from_position: from_position as i32,
+                to_position: position as i32,
+            }));
+        }
+
+        common_index += 1;
+
+        let from_block = &from_blocks[from_position];
+
+        if from_block.kind != block.kind {
+            changes.push(BlockChange::BlockRemoved(BlockRemoved {
+                block: from_block.clone(),
+                position: from_position as i32,
+            }));
+
+            changes.push(BlockChange::BlockAdded(BlockAdded {
+                block: block.clone(),
+                position: position as i32,
+            }));
+
+            continue;
+        }
+
+        if let (Some(from_tag), Some(to_tag)) = (from_block.tag, block.tag) {
+            if from_tag != to_tag {
+                changes.push(BlockChange::BlockTagChanged(BlockTagChanged {
+                    block_id: block.id.clone(),
+                    from: from_tag,
+                    to: to_tag,
+                }));
+            }
+        }
+
+        if (&from_block.url, &from_block.alt, &from_block.caption) != (&block.url, &block.alt, &block.caption) {
+            changes.push(BlockChange::BlockImageChanged(BlockImageChanged {
+                block_id: block.id.clone(),
+                from_url: from_block.url.clone(),
+                to_url: block.url.clone(),
+                from_alt: from_block.alt.clone(),
+                to_alt: block.alt.clone(),
+                from_caption: from_block.caption.clone(),
+                to_caption: block.caption.clone(),
+            }));
+        }
+
+        let properties = [
+            ("parent", from_block.parent_id != block.parent_id),
+            ("listStyle", from_block.list_style != block.list_style),
+            ("indent", from_block.indent != block.indent),
+            ("checked", from_block.checked != block.checked),
+            ("language", from_block.language != block.language),
+            ("variant", from_block.variant != block.variant),
+            ("sourceBlockId", from_block.source_block_id != block.source_block_id),
+            ("richText", from_block.rich_text != block.rich_text),
+            ("columns", from_block.columns != block.columns),
+            ("rows", from_block.rows != block.rows),
+        ]
+            .into_iter()
+            .filter(|(_, changed)| *changed)
+            .map(|(property, _)| property.to_string())
+            .collect::<Vec<_>>();
+
+        if !properties.is_empty() {
+            changes.push(BlockChange::BlockPropertiesChanged(BlockPropertiesChanged {
+                block_id: block.id.clone(),
+                properties,
+            }));
+        }
+
+        if from_block.content != block.content {
+            changes.push(BlockChange::BlockTextChanged(BlockTextChanged {
+                block_id: block.id.clone(),
+                segments: diff_words(
+                    from_block.content.as_deref().unwrap_or_default(),
+                    block.content.as_deref().unwrap_or_default(),
+                ),
+            }));
+        }
+    }
+
+    changes
+}
+
+pub struct DiffOperation;
+
+impl DiffOperation {
+    /// Revisions of documents the user can't read are reported as not
+    /// found, before anything else is looked at.
+    pub fn diff(conn: &mut DBPooledConnection, user_id: &str, from_revision_id: &str, to_revision_id: &str) -> DocumentDiffResult {
+        let from = match RevisionOperation::find(conn, from_revision_id) {
+            DocumentRevisionResult::DocumentRevision(revision) if Permission::can_read_document(conn, user_id, &revision.document_id) => revision,
+            _ => return DocumentDiffResult::not_found("from revision not found"),
+        };
+
+        let to = match RevisionOperation::find(conn, to_revision_id) {
+            DocumentRevisionResult::DocumentRevision(revision) => revision,
+            _ => return DocumentDiffResult::not_found("to revision not found"),
+        };
+
+        if from.document_id != to.document_id {
+            let mut errors = FieldErrors::new();
+            errors.push(FieldError::new("toRevision", "must belong to the same document"));
+            return DocumentDiffResult::FieldErrors(errors);
+        }
+
+        let changes = diff_blocks(&from.block_list(), &to.block_list());
+
+        DocumentDiffResult::DocumentDiff(DocumentDiff { from, to, changes })
+    }
+}
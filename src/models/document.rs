@@ -0,0 +1,788 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Insertable, prelude::*, result::{Error::{NotFound, DatabaseError}, DatabaseErrorKind::UniqueViolation}};
+use juniper::{graphql_object, GraphQLObject};
+use nanoid::nanoid;
+
+use crate::{schemas::root::Context, validation_result, schema::{documents, repositories}, db::DBPooledConnection, helpers::{slug::slugify, markdown, permission::Permission, errors::{FieldErrors, FieldError}}};
+
+use super::{repository::{RepositoryOperation, RepositoryResult}, block::{Block, BlockOperation}, revision::{DocumentRevision, RevisionOperation}, user::User, database::{DatabaseOperation, DatabaseProperty, DatabaseView, PropertyValue}, link::{DocumentLink, LinkOperation}, embed::{EmbedOperation, Reader}};
+
+/// Times a document is inserted with the next free slug when concurrent
+/// inserts keep taking it.
+const MAX_SLUG_ATTEMPTS: usize = 5;
+
+#[derive(Queryable)]
+pub struct Document {
+    pub id: String,
+    pub repository_id: String,
+    pub slug: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub is_database: bool,
+    pub database_id: Option<String>,
+    pub parent_id: Option<String>,
+    pub position: i32,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct Contributor {
+    pub user: User,
+    #[graphql(description = "How many blocks of the document the user edited last")]
+    pub block_count: i32,
+    pub last_edited_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "Document",
+    description = "Document model",
+    context = Context
+)]
+impl Document {
+    #[graphql(description = "The documents ID in base64 format")]
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[graphql(description = "The repository the document belongs to")]
+    fn repository(&self, context: &Context) -> RepositoryResult {
+        let mut conn = context.db_pool.get().unwrap();
+        RepositoryOperation::find(&mut conn, &self.repository_id)
+    }
+
+    #[graphql(description = "")]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[graphql(description = "")]
+    fn slug(&self) -> &str {
+        &self.slug
+    }
+
+    #[graphql(description = "")]
+    fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    #[graphql(description = "The document it is nested under, null for top level documents")]
+    fn parent(&self, context: &Context) -> Option<DocumentResult> {
+        let mut conn = context.db_pool.get().unwrap();
+        Some(DocumentOperation::find(&mut conn, self.parent_id.as_deref()?))
+    }
+
+    #[graphql(description = "Place among the documents with the same parent, starting at 0")]
+    fn position(&self) -> i32 {
+        self.position
+    }
+
+    #[graphql(description = "Documents nested directly under the document, in order")]
+    fn children(&self, context: &Context) -> Vec<Document> {
+        let mut conn = context.db_pool.get().unwrap();
+        DocumentOperation::find_children(&mut conn, &self.repository_id, Some(&self.id))
+    }
+
+    #[graphql(description = "Documents the document is nested under, top level first, for breadcrumbs")]
+    fn ancestors(&self, context: &Context) -> Vec<Document> {
+        let mut conn = context.db_pool.get().unwrap();
+        DocumentOperation::ancestors(&mut conn, self).unwrap_or_default()
+    }
+
+    #[graphql(description = "The top level blocks of the document ordered by line number, nested blocks are under their parents children")]
+    fn blocks(&self, context: &Context) -> Vec<Block> {
+        let mut conn = context.db_pool.get().unwrap();
+        BlockOperation::find_by_document(&mut conn, &self.id)
+    }
+
+    #[graphql(description = "The blocks of the document rendered as CommonMark")]
+    fn markdown(&self, context: &Context) -> String {
+        let Some(user) = context.authed_user() else {
+            return String::new();
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+        DocumentOperation::markdown(&mut conn, &self.id, Reader::User(&user.id)).unwrap_or_default()
+    }
+
+    #[graphql(description = "Users who last edited blocks of the document")]
+    fn contributors(&self, context: &Context) -> Vec<Contributor> {
+        let mut conn = context.db_pool.get().unwrap();
+        DocumentOperation::contributors(&mut conn, &self.id)
+    }
+
+    #[graphql(description = "Saved revisions of the document, newest first")]
+    fn revisions(&self, context: &Context) -> Vec<DocumentRevision> {
+        let mut conn = context.db_pool.get().unwrap();
+        RevisionOperation::find_by_document(&mut conn, &self.id)
+    }
+
+    #[graphql(description = "Whether the document is a database whose rows are documents")]
+    fn is_database(&self) -> bool {
+        self.is_database
+    }
+
+    #[graphql(description = "The database the document is a row of")]
+    fn database(&self, context: &Context) -> Option<DocumentResult> {
+        let mut conn = context.db_pool.get().unwrap();
+        Some(DocumentOperation::find(&mut conn, self.database_id.as_deref()?))
+    }
+
+    #[graphql(description = "Properties the rows of a database have, in order")]
+    fn properties(&self, context: &Context) -> Vec<DatabaseProperty> {
+        let mut conn = context.db_pool.get().unwrap();
+        DatabaseOperation::properties(&mut conn, &self.id)
+    }
+
+    #[graphql(description = "Saved views of a database")]
+    fn views(&self, context: &Context) -> Vec<DatabaseView> {
+        let mut conn = context.db_pool.get().unwrap();
+        DatabaseOperation::views(&mut conn, &self.id)
+    }
+
+    #[graphql(description = "The values a database row has set, in property order")]
+    fn property_values(&self, context: &Context) -> Vec<PropertyValue> {
+        let mut conn = context.db_pool.get().unwrap();
+        DatabaseOperation::values(&mut conn, &self.id)
+    }
+
+    #[graphql(description = "[[Document]] links written in the blocks of the document")]
+    fn outgoing_links(&self, context: &Context) -> Vec<DocumentLink> {
+        let mut conn = context.db_pool.get().unwrap();
+        LinkOperation::outgoing(&mut conn, &self.id)
+    }
+
+    #[graphql(description = "Links to the document from documents the signed in user can read")]
+    fn backlinks(&self, context: &Context) -> Vec<DocumentLink> {
+        let Some(user) = context.authed_user() else {
+            return Vec::new();
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+        let repository_ids = Permission::readable_repository_ids(&mut conn, &user.id);
+
+        LinkOperation::backlinks(&mut conn, &self.id, &repository_ids)
+    }
+
+    #[graphql(description = "DateTime for when the document was created")]
+    fn created_at(&self) -> &NaiveDateTime {
+        &self.created_at
+    }
+
+    #[graphql(description = "DateTime for when the document was last updated")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+validation_result!(DocumentResult, Document);
+
+#[derive(Insertable)]
+#[diesel(table_name = documents)]
+pub struct NewDocument {
+    pub id: String,
+    pub repository_id: String,
+    pub slug: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub database_id: Option<String>,
+    pub parent_id: Option<String>,
+    pub position: i32,
+}
+
+impl NewDocument {
+    pub fn new(repository_id: &str, slug: &str, name: &str, description: Option<&str>) -> NewDocument {
+        NewDocument {
+            id: nanoid!(),
+            repository_id: repository_id.into(),
+            slug: slug.into(),
+            name: name.into(),
+            description: description.map(|val| val.to_string()),
+            database_id: None,
+            parent_id: None,
+            position: 0,
+        }
+    }
+}
+
+/// Why a document can't be put where it was asked to go.
+#[derive(Debug)]
+pub enum DocumentPlacementError {
+    ParentNotFound,
+    IntoItself,
+    SameRepository,
+    DatabaseRow,
+    Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for DocumentPlacementError {
+    fn from(err: diesel::result::Error) -> DocumentPlacementError {
+        DocumentPlacementError::Database(err)
+    }
+}
+
+fn placement_result(result: Result<Document, DocumentPlacementError>) -> DocumentResult {
+    let (field, message) = match result {
+        Ok(document) => return DocumentResult::Document(document),
+        Err(DocumentPlacementError::Database(NotFound)) => return DocumentResult::not_found("document not found"),
+        Err(DocumentPlacementError::Database(_)) => return DocumentResult::server(),
+        Err(DocumentPlacementError::ParentNotFound) => ("newParentId", "isn't a document of this repository"),
+        Err(DocumentPlacementError::IntoItself) => ("newParentId", "can't be the document itself or a document nested under it"),
+        Err(DocumentPlacementError::SameRepository) => ("targetRepositoryId", "is already the repository of the document"),
+        Err(DocumentPlacementError::DatabaseRow) => ("id", "is or holds a row of a database that isn't moved along, move the database instead"),
+    };
+
+    let mut errors = FieldErrors::new();
+    errors.push(FieldError::new(field, message));
+
+    DocumentResult::FieldErrors(errors)
+}
+
+/// Orders documents the way they are listed, every document followed by
+/// the documents nested under it. `document_list` has to be sorted by
+/// position.
+fn tree_order(document_list: Vec<Document>) -> Vec<Document> {
+    let mut children: HashMap<Option<String>, Vec<Document>> = HashMap::new();
+
+    for document in document_list {
+        children.entry(document.parent_id.clone()).or_default().push(document);
+    }
+
+    let mut ordered = Vec::new();
+    let mut stack = children.remove(&None).unwrap_or_default();
+    stack.reverse();
+
+    while let Some(document) = stack.pop() {
+        if let Some(nested) = children.remove(&Some(document.id.clone())) {
+            stack.extend(nested.into_iter().rev());
+        }
+
+        ordered.push(document);
+    }
+
+    ordered
+}
+
+/// Path of each document keyed by ID, the slugs of the documents it is
+/// nested under and its own joined like folders. `document_list` has to
+/// hold every document of the repository.
+pub fn paths(document_list: &[Document]) -> HashMap<String, String> {
+    let by_id = document_list
+        .iter()
+        .map(|document| (document.id.as_str(), document))
+        .collect::<HashMap<_, _>>();
+
+    document_list
+        .iter()
+        .map(|document| {
+            let mut slugs = vec![document.slug.as_str()];
+            let mut parent_id = document.parent_id.as_deref();
+
+            while let Some(parent) = parent_id.and_then(|id| by_id.get(id)) {
+                slugs.push(&parent.slug);
+                parent_id = parent.parent_id.as_deref();
+            }
+
+            slugs.reverse();
+
+            (document.id.clone(), slugs.join("/"))
+        })
+        .collect()
+}
+
+pub struct DocumentOperation;
+
+impl DocumentOperation {
+    /// Inserts a document with a slug derived from its name, suffixed with
+    /// a number when the slug is already taken. It goes after the documents
+    /// already under `parent_id`.
+    pub fn create(conn: &mut DBPooledConnection, repository_id: &str, parent_id: Option<&str>, name: &str, description: Option<&str>) -> QueryResult<Document> {
+        let new_document = NewDocument {
+            parent_id: parent_id.map(String::from),
+            position: Self::next_position(conn, repository_id, parent_id)?,
+            ..NewDocument::new(repository_id, &slugify(name), name, description)
+        };
+
+        let document = Self::insert_with_available_slug(conn, new_document)?;
+
+        LinkOperation::resolve_dangling(conn, &document)?;
+
+        Ok(document)
+    }
+
+    /// Position for a document added after the documents under
+    /// `parent_id`.
+    pub fn next_position(conn: &mut DBPooledConnection, repository_id: &str, parent_id: Option<&str>) -> QueryResult<i32> {
+        let last_position = documents::table
+            .filter(documents::repository_id.eq(repository_id))
+            .filter(documents::parent_id.is_not_distinct_from(parent_id))
+            .select(diesel::dsl::max(documents::position))
+            .get_result::<Option<i32>>(conn)?;
+
+        Ok(last_position.map_or(0, |position| position + 1))
+    }
+
+    /// Locks the repository so concurrent moves can't nest two documents
+    /// under each other, then checks `parent_id` can hold `document`.
+    fn check_parent(conn: &mut DBPooledConnection, document: &Document, parent_id: &str) -> Result<(), DocumentPlacementError> {
+        repositories::table
+            .filter(repositories::id.eq(&document.repository_id))
+            .select(repositories::id)
+            .for_update()
+            .get_result::<String>(conn)?;
+
+        let mut ancestor = documents::table
+            .filter(documents::id.eq(parent_id))
+            .filter(documents::repository_id.eq(&document.repository_id))
+            .get_result::<Document>(conn)
+            .optional()?;
+
+        if ancestor.is_none() {
+            return Err(DocumentPlacementError::ParentNotFound);
+        }
+
+        while let Some(parent) = ancestor {
+            if parent.id == document.id {
+                return Err(DocumentPlacementError::IntoItself);
+            }
+
+            ancestor = match parent.parent_id {
+                Some(parent_id) => Some(documents::table.filter(documents::id.eq(parent_id)).get_result::<Document>(conn)?),
+                None => None,
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Numbers the documents from 0 in the order given.
+    fn renumber(conn: &mut DBPooledConnection, ids: &[String]) -> QueryResult<()> {
+        for (position, id) in ids.iter().enumerate() {
+            diesel::update(documents::table)
+                .filter(documents::id.eq(id))
+                .filter(documents::position.ne(position as i32))
+                .set(documents::position.eq(position as i32))
+                .execute(conn)?;
+        }
+
+        Ok(())
+    }
+
+    /// Puts document `id` at `position` among the other documents under
+    /// `parent_id`, after them when there is no position.
+    fn place_among_siblings(conn: &mut DBPooledConnection, repository_id: &str, parent_id: Option<&str>, id: &str, position: Option<i32>) -> QueryResult<()> {
+        let mut siblings = Self::sibling_ids(conn, repository_id, parent_id, id)?;
+        let index = position.map_or(siblings.len(), |position| position.clamp(0, siblings.len() as i32) as usize);
+
+        siblings.insert(index, id.into());
+        Self::renumber(conn, &siblings)
+    }
+
+    /// The document along with the documents nested under it and the rows
+    /// of databases among them, the document first.
+    fn subtree_ids(conn: &mut DBPooledConnection, id: &str) -> QueryResult<Vec<String>> {
+        let mut ids = vec![id.to_string()];
+        let mut level = ids.clone();
+
+        while !level.is_empty() {
+            level = documents::table
+                .filter(documents::parent_id.eq_any(&level).or(documents::database_id.eq_any(&level)))
+                .filter(documents::id.ne_all(&ids))
+                .select(documents::id)
+                .get_results::<String>(conn)?;
+
+            ids.extend(level.iter().cloned());
+        }
+
+        Ok(ids)
+    }
+
+    fn sibling_ids(conn: &mut DBPooledConnection, repository_id: &str, parent_id: Option<&str>, except_id: &str) -> QueryResult<Vec<String>> {
+        documents::table
+            .filter(documents::repository_id.eq(repository_id))
+            .filter(documents::parent_id.is_not_distinct_from(parent_id))
+            .filter(documents::id.ne(except_id))
+            .order((documents::position.asc(), documents::name.asc()))
+            .select(documents::id)
+            .get_results::<String>(conn)
+    }
+
+    /// Nests a document along with the documents under it under
+    /// `parent_id`, or at the top level when there is none, at `position`
+    /// among its new siblings or after them when there is none.
+    pub fn move_to(conn: &mut DBPooledConnection, id: &str, parent_id: Option<&str>, position: Option<i32>) -> DocumentResult {
+        let result = conn.transaction(|conn| {
+            let document = documents::table
+                .filter(documents::id.eq(id))
+                .get_result::<Document>(conn)?;
+
+            match parent_id {
+                Some(parent_id) => Self::check_parent(conn, &document, parent_id)?,
+                None => {
+                    repositories::table
+                        .filter(repositories::id.eq(&document.repository_id))
+                        .select(repositories::id)
+                        .for_update()
+                        .get_result::<String>(conn)?;
+                },
+            }
+
+            diesel::update(documents::table)
+                .filter(documents::id.eq(id))
+                .set(documents::parent_id.eq(parent_id))
+                .execute(conn)?;
+
+            Self::place_among_siblings(conn, &document.repository_id, parent_id, id, position)?;
+
+            if document.parent_id.as_deref() != parent_id {
+                let old_siblings = Self::sibling_ids(conn, &document.repository_id, document.parent_id.as_deref(), id)?;
+                Self::renumber(conn, &old_siblings)?;
+            }
+
+            Ok(documents::table
+                .filter(documents::id.eq(id))
+                .get_result::<Document>(conn)?)
+        });
+
+        placement_result(result)
+    }
+
+    /// Moves a document to another repository, along with the documents
+    /// nested under it and the rows of databases among them, and places it
+    /// like `move_to` does. They keep their IDs and slugs so links and
+    /// embeds keep working.
+    pub fn move_to_repository(conn: &mut DBPooledConnection, id: &str, repository_id: &str, parent_id: Option<&str>, position: Option<i32>) -> DocumentResult {
+        let result = conn.transaction(|conn| {
+            let document = documents::table
+                .filter(documents::id.eq(id))
+                .get_result::<Document>(conn)?;
+
+            if document.repository_id == repository_id {
+                return Err(DocumentPlacementError::SameRepository);
+            }
+
+            // locked in a fixed order so two moves can't wait on each other
+            repositories::table
+                .filter(repositories::id.eq_any([&document.repository_id, repository_id]))
+                .order(repositories::id.asc())
+                .select(repositories::id)
+                .for_update()
+                .get_results::<String>(conn)?;
+
+            let ids = Self::subtree_ids(conn, &document.id)?;
+
+            let rows_left_behind = documents::table
+                .filter(documents::id.eq_any(&ids))
+                .filter(documents::database_id.ne_all(&ids))
+                .count()
+                .get_result::<i64>(conn)?;
+
+            if rows_left_behind > 0 {
+                return Err(DocumentPlacementError::DatabaseRow);
+            }
+
+            // the moved documents are all in the other repository, so the
+            // document can't end up nested under itself
+            if let Some(parent_id) = parent_id {
+                documents::table
+                    .filter(documents::id.eq(parent_id))
+                    .filter(documents::repository_id.eq(repository_id))
+                    .select(documents::id)
+                    .get_result::<String>(conn)
+                    .optional()?
+                    .ok_or(DocumentPlacementError::ParentNotFound)?;
+            }
+
+            diesel::update(documents::table)
+                .filter(documents::id.eq_any(&ids))
+                .set(documents::repository_id.eq(repository_id))
+                .execute(conn)?;
+
+            diesel::update(documents::table)
+                .filter(documents::id.eq(id))
+                .set(documents::parent_id.eq(parent_id))
+                .execute(conn)?;
+
+            Self::place_among_siblings(conn, repository_id, parent_id, id, position)?;
+
+            let moved = documents::table
+                .filter(documents::id.eq(id))
+                .get_result::<Document>(conn)?;
+
+            let old_siblings = Self::sibling_ids(conn, &document.repository_id, document.parent_id.as_deref(), id)?;
+            Self::renumber(conn, &old_siblings)?;
+
+            for moved_document in documents::table.filter(documents::id.eq_any(&ids)).get_results::<Document>(conn)? {
+                LinkOperation::resolve_dangling(conn, &moved_document)?;
+            }
+
+            Ok(moved)
+        });
+
+        placement_result(result)
+    }
+
+    /// Copies a document along with the documents nested under it and the
+    /// rows of databases among them, and their blocks, with new IDs. The
+    /// copy goes right after the document when it stays in the same
+    /// repository and at the end of the top level otherwise, the copies
+    /// nested under it keep their places. Copies of databases get the
+    /// properties and views, copies of rows the values while they stay
+    /// next to their database or are copied along with it.
+    pub fn duplicate(conn: &mut DBPooledConnection, user_id: &str, id: &str, repository_id: &str) -> DocumentResult {
+        let result = conn.transaction(|conn| {
+            let document = documents::table
+                .filter(documents::id.eq(id))
+                .get_result::<Document>(conn)?;
+
+            let ids = Self::subtree_ids(conn, id)?;
+
+            let mut pending = documents::table
+                .filter(documents::id.eq_any(&ids))
+                .filter(documents::id.ne(id))
+                .order(documents::position.asc())
+                .get_results::<Document>(conn)?;
+
+            let same_repository = document.repository_id == repository_id;
+            let parent_id = document.parent_id.as_deref().filter(|_| same_repository);
+            let position = Self::next_position(conn, repository_id, parent_id)?;
+            let copy = Self::insert_copy(conn, &document, repository_id, parent_id, position)?;
+
+            if same_repository {
+                let mut siblings = Self::sibling_ids(conn, repository_id, parent_id, &copy.id)?;
+                let index = siblings.iter().position(|sibling| *sibling == document.id).map_or(siblings.len(), |index| index + 1);
+
+                siblings.insert(index, copy.id.clone());
+                Self::renumber(conn, &siblings)?;
+            }
+
+            let mut new_ids = HashMap::from([(document.id.clone(), copy.id)]);
+            let mut copied = vec![document];
+
+            // parents are copied before the documents nested under them,
+            // rows nested elsewhere go to the top level
+            while !pending.is_empty() {
+                let (ready, rest): (Vec<_>, Vec<_>) = pending
+                    .into_iter()
+                    .partition(|nested| nested.parent_id.as_ref().is_none_or(|parent_id| !ids.contains(parent_id) || new_ids.contains_key(parent_id)));
+
+                pending = rest;
+
+                for nested in ready {
+                    let (parent_id, position) = match nested.parent_id.as_ref().and_then(|parent_id| new_ids.get(parent_id)) {
+                        Some(parent_id) => (Some(parent_id.clone()), nested.position),
+                        None => (None, Self::next_position(conn, repository_id, None)?),
+                    };
+
+                    let nested_copy = Self::insert_copy(conn, &nested, repository_id, parent_id.as_deref(), position)?;
+
+                    new_ids.insert(nested.id.clone(), nested_copy.id);
+                    copied.push(nested);
+                }
+            }
+
+            let mut property_ids = HashMap::new();
+
+            for original in copied.iter().filter(|original| original.is_database) {
+                property_ids.extend(DatabaseOperation::copy_schema(conn, &original.id, &new_ids[&original.id])?);
+            }
+
+            for original in &copied {
+                let copy_id = &new_ids[&original.id];
+
+                let database_id = match &original.database_id {
+                    Some(database_id) if new_ids.contains_key(database_id) => Some(&new_ids[database_id]),
+                    Some(database_id) if same_repository => Some(database_id),
+                    _ => None,
+                };
+
+                if let Some(database_id) = database_id {
+                    diesel::update(documents::table)
+                        .filter(documents::id.eq(copy_id))
+                        .set(documents::database_id.eq(database_id))
+                        .execute(conn)?;
+
+                    DatabaseOperation::copy_values(conn, &original.id, copy_id, &property_ids)?;
+                }
+
+                BlockOperation::copy_document(conn, user_id, &original.id, copy_id)?;
+            }
+
+            let copies = documents::table
+                .filter(documents::id.eq_any(new_ids.values()))
+                .get_results::<Document>(conn)?;
+
+            for copy in &copies {
+                LinkOperation::resolve_dangling(conn, copy)?;
+            }
+
+            documents::table
+                .filter(documents::id.eq(&new_ids[id]))
+                .get_result::<Document>(conn)
+        });
+
+        match result {
+            Ok(document) => DocumentResult::Document(document),
+            Err(NotFound) => DocumentResult::not_found("document not found"),
+            Err(_) => DocumentResult::server(),
+        }
+    }
+
+    /// Inserts a copy of the document without its blocks under `parent_id`
+    /// at `position`, with a free slug for its name.
+    fn insert_copy(conn: &mut DBPooledConnection, document: &Document, repository_id: &str, parent_id: Option<&str>, position: i32) -> QueryResult<Document> {
+        let new_document = NewDocument {
+            parent_id: parent_id.map(String::from),
+            position,
+            ..NewDocument::new(repository_id, &slugify(&document.name), &document.name, document.description.as_deref())
+        };
+
+        Self::insert_with_available_slug(conn, new_document)
+    }
+
+    /// Inserts the document with the first free slug starting from its
+    /// slug. Another document can take the slug between finding it free
+    /// and inserting, the insert is then tried again with the next one.
+    pub fn insert_with_available_slug(conn: &mut DBPooledConnection, mut new_document: NewDocument) -> QueryResult<Document> {
+        let base = std::mem::take(&mut new_document.slug);
+        let mut attempts = 0;
+
+        loop {
+            new_document.slug = Self::available_slug(conn, &base)?;
+            attempts += 1;
+
+            // in a savepoint so a failed insert doesn't abort the transaction
+            // the document is inserted in
+            let document = conn.transaction(|conn| {
+                diesel::insert_into(documents::table)
+                    .values(&new_document)
+                    .get_result::<Document>(conn)
+            });
+
+            match document {
+                Err(DatabaseError(UniqueViolation, info)) if info.constraint_name() == Some("documents_slug_key") && attempts < MAX_SLUG_ATTEMPTS => continue,
+                document => return document,
+            }
+        }
+    }
+
+    pub fn available_slug(conn: &mut DBPooledConnection, base: &str) -> QueryResult<String> {
+        let taken = documents::table
+            .filter(documents::slug.eq(base).or(documents::slug.like(format!("{}-%", base))))
+            .select(documents::slug)
+            .get_results::<String>(conn)?;
+
+        let slug = (1..)
+            .map(|n| if n == 1 { base.to_string() } else { format!("{}-{}", base, n) })
+            .find(|slug| !taken.contains(slug))
+            .unwrap();
+
+        Ok(slug)
+    }
+
+    pub fn find(conn: &mut DBPooledConnection, id: &str) -> DocumentResult {
+        use crate::schema::documents::dsl::{documents, id as document_id};
+
+        let document = documents
+            .filter(document_id.eq(id))
+            .get_result::<Document>(conn);
+
+        match document {
+            Ok(document) => DocumentResult::Document(document),
+            Err(NotFound) => DocumentResult::not_found("document not found"),
+            Err(_) => DocumentResult::server(),
+        }
+    }
+
+    pub fn find_by_slug(conn: &mut DBPooledConnection, repository_id: &str, slug: &str) -> DocumentResult {
+        use crate::schema::documents::dsl::{documents, repository_id as document_repository_id, slug as document_slug};
+
+        let document = documents
+            .filter(document_repository_id.eq(repository_id))
+            .filter(document_slug.eq(slug))
+            .get_result::<Document>(conn);
+
+        match document {
+            Ok(document) => DocumentResult::Document(document),
+            Err(NotFound) => DocumentResult::not_found("document not found"),
+            Err(_) => DocumentResult::server(),
+        }
+    }
+
+    /// Every document of the repository, each followed by the documents
+    /// nested under it.
+    pub fn find_by_repository(conn: &mut DBPooledConnection, repository_id: &str) -> Vec<Document> {
+        use crate::schema::documents::dsl::{documents, repository_id as document_repository_id, position, name};
+
+        let document_list = documents
+            .filter(document_repository_id.eq(repository_id))
+            .order((position.asc(), name.asc()))
+            .get_results::<Document>(conn);
+
+        tree_order(document_list.unwrap_or_default())
+    }
+
+    pub fn find_children(conn: &mut DBPooledConnection, repository_id: &str, parent_id: Option<&str>) -> Vec<Document> {
+        documents::table
+            .filter(documents::repository_id.eq(repository_id))
+            .filter(documents::parent_id.is_not_distinct_from(parent_id))
+            .order((documents::position.asc(), documents::name.asc()))
+            .get_results::<Document>(conn)
+            .unwrap_or_default()
+    }
+
+    /// The documents `document` is nested under, top level first.
+    pub fn ancestors(conn: &mut DBPooledConnection, document: &Document) -> QueryResult<Vec<Document>> {
+        let mut ancestors = Vec::new();
+        let mut parent_id = document.parent_id.clone();
+
+        while let Some(id) = parent_id {
+            let parent = documents::table
+                .filter(documents::id.eq(id))
+                .get_result::<Document>(conn)?;
+
+            parent_id = parent.parent_id.clone();
+            ancestors.push(parent);
+        }
+
+        ancestors.reverse();
+
+        Ok(ancestors)
+    }
+
+    pub fn find_recently_updated(conn: &mut DBPooledConnection, repository_id: &str, limit: i64) -> Vec<Document> {
+        use crate::schema::documents::dsl::{documents, repository_id as document_repository_id, updated_at};
+
+        let document_list = documents
+            .filter(document_repository_id.eq(repository_id))
+            .order(updated_at.desc())
+            .limit(limit)
+            .get_results::<Document>(conn);
+
+        document_list.unwrap_or_default()
+    }
+
+    /// The document as CommonMark, with embeds showing what `reader` can
+    /// read of them.
+    pub fn markdown(conn: &mut DBPooledConnection, id: &str, reader: Reader) -> QueryResult<String> {
+        let mut block_list = BlockOperation::find_by_document_with_content(conn, id)?;
+        EmbedOperation::resolve_all(conn, reader, &mut block_list);
+        Ok(markdown::render(&block_list))
+    }
+
+    pub fn contributors(conn: &mut DBPooledConnection, id: &str) -> Vec<Contributor> {
+        let authors = BlockOperation::authors(conn, id).unwrap_or_default();
+
+        authors
+            .into_iter()
+            .map(|(user, block_count, last_edited_at)| Contributor {
+                user,
+                block_count: block_count as i32,
+                last_edited_at,
+            })
+            .collect()
+    }
+}
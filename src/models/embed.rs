@@ -0,0 +1,102 @@
+use diesel::prelude::*;
+use juniper::GraphQLEnum;
+
+use crate::{db::DBPooledConnection, schema::{documents, repositories}, helpers::permission::Permission};
+
+use super::block::{Block, BlockContent, BlockOperation};
+
+/// How many embeds may be followed to get to the embedded content.
+pub const MAX_EMBED_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, GraphQLEnum)]
+pub enum EmbedStatus {
+    #[graphql(description = "The embedded block was found and can be read")]
+    Resolved,
+    #[graphql(description = "The embedded block was deleted")]
+    Deleted,
+    #[graphql(description = "The reader can't read the document of the embedded block")]
+    Forbidden,
+    #[graphql(description = "The embeds lead back to the embed or nest too deep")]
+    Cycle,
+}
+
+/// Who embedded blocks are resolved for.
+#[derive(Clone, Copy)]
+pub enum Reader<'a> {
+    User(&'a str),
+    /// Visitors of the site built from a repository, who can read it and
+    /// any published repository.
+    Site(&'a str),
+}
+
+impl Reader<'_> {
+    fn can_read_document(&self, conn: &mut DBPooledConnection, document_id: &str) -> bool {
+        match self {
+            Reader::User(user_id) => Permission::can_read_document(conn, user_id, document_id),
+            Reader::Site(repository_id) => documents::table
+                .inner_join(repositories::table)
+                .filter(documents::id.eq(document_id))
+                .filter(repositories::id.eq(repository_id).or(repositories::published_at.is_not_null()))
+                .count()
+                .get_result::<i64>(conn)
+                .is_ok_and(|count| count > 0),
+        }
+    }
+}
+
+pub struct EmbedOperation;
+
+impl EmbedOperation {
+    /// The block embedded by `embed_block_id`, following embeds of embeds
+    /// to the first block that has content of its own. The reader has to
+    /// be able to read every block on the way.
+    pub fn resolve(conn: &mut DBPooledConnection, reader: Reader, embed_block_id: Option<&str>, source_block_id: Option<&str>) -> Result<(Block, BlockContent), EmbedStatus> {
+        let mut visited = embed_block_id.map(String::from).into_iter().collect::<Vec<_>>();
+        let mut next = source_block_id.map(String::from);
+
+        loop {
+            let Some(id) = next.take() else {
+                return Err(EmbedStatus::Deleted);
+            };
+
+            if visited.contains(&id) || visited.len() > MAX_EMBED_DEPTH {
+                return Err(EmbedStatus::Cycle);
+            }
+
+            let Ok(block) = BlockOperation::find(conn, &id) else {
+                return Err(EmbedStatus::Deleted);
+            };
+
+            if !reader.can_read_document(conn, &block.document_id) {
+                return Err(EmbedStatus::Forbidden);
+            }
+
+            let content = BlockOperation::contents(conn, std::slice::from_ref(&id))
+                .ok()
+                .and_then(|mut contents| contents.remove(&id));
+
+            match content {
+                Some(BlockContent::EmbedBlock(embed_block)) => next = embed_block.source_block_id,
+                Some(content) => return Ok((block, content)),
+                None => return Err(EmbedStatus::Deleted),
+            }
+
+            visited.push(id);
+        }
+    }
+
+    /// Replaces the content of embed blocks with the content they embed, so
+    /// blocks can be rendered without looking anything up. Embeds that
+    /// can't be resolved are left as they are.
+    pub fn resolve_all(conn: &mut DBPooledConnection, reader: Reader, block_list: &mut [(Block, Option<BlockContent>)]) {
+        for (block, content) in block_list {
+            let Some(BlockContent::EmbedBlock(embed_block)) = content else {
+                continue;
+            };
+
+            if let Ok((_, resolved)) = Self::resolve(conn, reader, Some(&block.id), embed_block.source_block_id.as_deref()) {
+                *content = Some(resolved);
+            }
+        }
+    }
+}
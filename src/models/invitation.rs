@@ -0,0 +1,331 @@
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Insertable, prelude::*, dsl::{now, IntervalDsl}, sql_types::Text, result::{Error::{NotFound, DatabaseError}, DatabaseErrorKind::UniqueViolation}};
+use juniper::graphql_object;
+use nanoid::nanoid;
+use sha2::{Digest, Sha256};
+
+use crate::{schemas::root::Context, validation_result, db::DBPooledConnection, schema::{users, repositories, repository_members, repository_invitations}, helpers::{errors::{FieldErrors, FieldError}, mail::{Mail, Mailer}}};
+
+use super::{user::{User, UserOperation, UserResult}, repository::{Repository, RepositoryOperation, RepositoryResult}, member::{MemberRole, RepositoryMember, RepositoryMemberResult, NewRepositoryMember}};
+
+pub const INVITATION_DURATION: i32 = 7;     // days
+
+sql_function!(fn lower(x: Text) -> Text);
+
+#[derive(Queryable)]
+pub struct RepositoryInvitation {
+    pub id: String,
+    pub repository_id: String,
+    pub email: String,
+    pub role: MemberRole,
+    pub token_hash: String,
+    pub invited_by: String,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "RepositoryInvitation",
+    description = "An invitation to join a repository sent by email",
+    context = Context
+)]
+impl RepositoryInvitation {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[graphql(description = "The repository the invitee joins")]
+    fn repository(&self, context: &Context) -> RepositoryResult {
+        let mut conn = context.db_pool.get().unwrap();
+        RepositoryOperation::find(&mut conn, &self.repository_id)
+    }
+
+    #[graphql(description = "The lowercase email the invitation was sent to")]
+    fn email(&self) -> &str {
+        &self.email
+    }
+
+    #[graphql(description = "The role the invitee gets when accepting")]
+    fn role(&self) -> MemberRole {
+        self.role
+    }
+
+    #[graphql(description = "The user who sent the invitation")]
+    fn invited_by(&self, context: &Context) -> UserResult {
+        let mut conn = context.db_pool.get().unwrap();
+        UserOperation::find(&mut conn, &self.invited_by)
+    }
+
+    #[graphql(description = "DateTime after which the invitation can't be accepted")]
+    fn expires_at(&self) -> &NaiveDateTime {
+        &self.expires_at
+    }
+
+    #[graphql(description = "DateTime for when the invitation was sent")]
+    fn created_at(&self) -> &NaiveDateTime {
+        &self.created_at
+    }
+}
+
+validation_result!(RepositoryInvitationResult, RepositoryInvitation);
+
+pub struct RepositoryInvitationList {
+    pub invitations: Vec<RepositoryInvitation>,
+}
+
+#[graphql_object(
+    name = "RepositoryInvitationList",
+    description = "The invitations of a repository, expired ones included",
+    context = Context
+)]
+impl RepositoryInvitationList {
+    fn invitations(&self) -> &[RepositoryInvitation] {
+        &self.invitations
+    }
+}
+
+validation_result!(RepositoryInvitationListResult, RepositoryInvitationList);
+
+#[derive(Insertable)]
+#[diesel(table_name = repository_invitations)]
+pub struct NewRepositoryInvitation {
+    pub id: String,
+    pub repository_id: String,
+    pub email: String,
+    pub role: MemberRole,
+    pub token_hash: String,
+    pub invited_by: String,
+}
+
+impl NewRepositoryInvitation {
+    pub fn new(repository_id: &str, email: &str, role: MemberRole, token: &str, invited_by: &str) -> NewRepositoryInvitation {
+        NewRepositoryInvitation {
+            id: nanoid!(),
+            repository_id: repository_id.into(),
+            email: email.into(),
+            role,
+            token_hash: hash_token(token),
+            invited_by: invited_by.into(),
+        }
+    }
+}
+
+/// Invitations are looked up by token, so the hash has to be deterministic.
+/// Tokens are random enough that a salt isn't needed.
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub enum InvitationError {
+    AlreadyMember,
+    AlreadyInvited,
+    Invalid,
+    OtherEmail,
+    Mail(std::io::Error),
+    Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for InvitationError {
+    fn from(err: diesel::result::Error) -> InvitationError {
+        InvitationError::Database(err)
+    }
+}
+
+impl InvitationError {
+    /// Field the error is about, `field` being the one the token was given in.
+    fn field_error(&self, field: &str) -> Option<FieldError> {
+        match self {
+            InvitationError::AlreadyMember => Some(FieldError::new("email", "is already a collaborator")),
+            InvitationError::AlreadyInvited => Some(FieldError::new("email", "is already invited")),
+            InvitationError::Invalid => Some(FieldError::new(field, "is invalid or has expired")),
+            InvitationError::OtherEmail => Some(FieldError::new(field, "was sent to another email")),
+            InvitationError::Mail(_) | InvitationError::Database(_) => None,
+        }
+    }
+}
+
+fn invitation_result(result: Result<RepositoryInvitation, InvitationError>) -> RepositoryInvitationResult {
+    match result {
+        Ok(invitation) => RepositoryInvitationResult::RepositoryInvitation(invitation),
+        Err(InvitationError::Database(NotFound)) => RepositoryInvitationResult::not_found("invitation not found"),
+        Err(err) => match err.field_error("token") {
+            Some(error) => {
+                let mut errors = FieldErrors::new();
+                errors.push(error);
+                RepositoryInvitationResult::FieldErrors(errors)
+            },
+            None => RepositoryInvitationResult::server(),
+        },
+    }
+}
+
+pub struct InvitationOperation;
+
+impl InvitationOperation {
+    /// Invites `email` and mails them the token. An expired invitation for
+    /// the same email is replaced, a pending one is left alone. Nothing is
+    /// kept when the mail can't be sent.
+    pub fn invite(conn: &mut DBPooledConnection, mailer: &dyn Mailer, inviter: &User, repository_id: &str, email: &str, role: MemberRole) -> RepositoryInvitationResult {
+        let email = email.trim().to_lowercase();
+
+        let result = conn.transaction(|conn| {
+            let repository = repositories::table
+                .filter(repositories::id.eq(repository_id))
+                .get_result::<Repository>(conn)?;
+
+            let member = repository_members::table
+                .inner_join(users::table)
+                .filter(repository_members::repository_id.eq(repository_id))
+                .filter(lower(users::email).eq(&email))
+                .count()
+                .get_result::<i64>(conn)?;
+
+            if member > 0 {
+                return Err(InvitationError::AlreadyMember);
+            }
+
+            diesel::delete(repository_invitations::table)
+                .filter(repository_invitations::repository_id.eq(repository_id))
+                .filter(repository_invitations::email.eq(&email))
+                .filter(repository_invitations::expires_at.le(now))
+                .execute(conn)?;
+
+            let token = nanoid!(32);
+            let new_invitation = NewRepositoryInvitation::new(repository_id, &email, role, &token, &inviter.id);
+
+            let invitation = diesel::insert_into(repository_invitations::table)
+                .values((&new_invitation, repository_invitations::expires_at.eq(now + INVITATION_DURATION.days())))
+                .get_result::<RepositoryInvitation>(conn);
+
+            match invitation {
+                Err(DatabaseError(UniqueViolation, _)) => Err(InvitationError::AlreadyInvited),
+                invitation => Ok((repository, invitation?, token)),
+            }
+        });
+
+        // sent once the invitation is committed, so the mailer isn't waited
+        // on with the rows locked
+        let result = result.and_then(|(repository, invitation, token)| {
+            let subject = format!("You're invited to {}", repository.name);
+            let text = format!(
+                "{} invited you to the repository {} with the {} role.\n\n\
+                 Give this invitation token when creating your account, or accept it after signing in with this email:\n\n\
+                 {}\n\n\
+                 The invitation expires on {}.\n",
+                inviter.email,
+                repository.name,
+                format!("{:?}", role).to_lowercase(),
+                token,
+                invitation.expires_at.format("%Y-%m-%d %H:%M"),
+            );
+
+            match mailer.send(&Mail { to: &email, subject: &subject, text: &text }) {
+                Ok(()) => Ok(invitation),
+                Err(err) => {
+                    diesel::delete(repository_invitations::table)
+                        .filter(repository_invitations::id.eq(&invitation.id))
+                        .execute(conn)?;
+
+                    Err(InvitationError::Mail(err))
+                },
+            }
+        });
+
+        if let Err(InvitationError::Mail(err)) = &result {
+            log::error!("failed to send invitation: {}", err);
+        }
+
+        invitation_result(result)
+    }
+
+    pub fn find(conn: &mut DBPooledConnection, id: &str) -> RepositoryInvitationResult {
+        let invitation = repository_invitations::table
+            .filter(repository_invitations::id.eq(id))
+            .get_result::<RepositoryInvitation>(conn);
+
+        match invitation {
+            Ok(invitation) => RepositoryInvitationResult::RepositoryInvitation(invitation),
+            Err(NotFound) => RepositoryInvitationResult::not_found("invitation not found"),
+            Err(_) => RepositoryInvitationResult::server(),
+        }
+    }
+
+    /// Invitations of the repository, newest first.
+    pub fn find_by_repository(conn: &mut DBPooledConnection, repository_id: &str) -> RepositoryInvitationListResult {
+        let invitations = repository_invitations::table
+            .filter(repository_invitations::repository_id.eq(repository_id))
+            .order(repository_invitations::created_at.desc())
+            .get_results::<RepositoryInvitation>(conn);
+
+        match invitations {
+            Ok(invitations) => RepositoryInvitationListResult::RepositoryInvitationList(RepositoryInvitationList { invitations }),
+            Err(_) => RepositoryInvitationListResult::server(),
+        }
+    }
+
+    /// The invitation the token is for, None when there's none or it expired.
+    pub fn find_pending(conn: &mut DBPooledConnection, token: &str) -> Option<RepositoryInvitation> {
+        repository_invitations::table
+            .filter(repository_invitations::token_hash.eq(hash_token(token)))
+            .filter(repository_invitations::expires_at.gt(now))
+            .get_result::<RepositoryInvitation>(conn)
+            .ok()
+    }
+
+    pub fn revoke(conn: &mut DBPooledConnection, id: &str) -> RepositoryInvitationResult {
+        let result = diesel::delete(repository_invitations::table)
+            .filter(repository_invitations::id.eq(id))
+            .get_result::<RepositoryInvitation>(conn)
+            .map_err(InvitationError::from);
+
+        invitation_result(result)
+    }
+
+    /// Adds the user to the repository with the role of the invitation,
+    /// which has to be sent to their email. Members keep the role they
+    /// have. `field` is the argument the token was given in.
+    pub fn accept(conn: &mut DBPooledConnection, user: &User, token: &str, field: &str) -> RepositoryMemberResult {
+        let result = conn.transaction(|conn| {
+            let invitation = repository_invitations::table
+                .filter(repository_invitations::token_hash.eq(hash_token(token)))
+                .filter(repository_invitations::expires_at.gt(now))
+                .for_update()
+                .get_result::<RepositoryInvitation>(conn)
+                .optional()?
+                .ok_or(InvitationError::Invalid)?;
+
+            if invitation.email != user.email.to_lowercase() {
+                return Err(InvitationError::OtherEmail);
+            }
+
+            diesel::insert_into(repository_members::table)
+                .values(&NewRepositoryMember::new(&invitation.repository_id, &user.id, invitation.role))
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+
+            diesel::delete(repository_invitations::table)
+                .filter(repository_invitations::id.eq(&invitation.id))
+                .execute(conn)?;
+
+            let member = repository_members::table
+                .filter(repository_members::repository_id.eq(&invitation.repository_id))
+                .filter(repository_members::user_id.eq(&user.id))
+                .get_result::<RepositoryMember>(conn)?;
+
+            Ok(member)
+        });
+
+        match result {
+            Ok(member) => RepositoryMemberResult::RepositoryMember(member),
+            Err(err) => match err.field_error(field) {
+                Some(error) => {
+                    let mut errors = FieldErrors::new();
+                    errors.push(error);
+                    RepositoryMemberResult::FieldErrors(errors)
+                },
+                None => RepositoryMemberResult::server(),
+            },
+        }
+    }
+}
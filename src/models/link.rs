@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Insertable, prelude::*, dsl::count_star};
+use juniper::{graphql_object, GraphQLObject};
+
+use crate::{schemas::root::Context, validation_result, db::DBPooledConnection, schema::{blocks, documents, document_links}, helpers::{permission::Permission, sql::escape_like}};
+
+use super::{block::{Block, BlockOperation, NewBlockContent}, document::{Document, DocumentOperation, DocumentResult}};
+
+pub const MAX_LABEL_LENGTH: usize = 256;
+
+/// A `[[Document]]` link in the text of a block.
+#[derive(Queryable)]
+pub struct DocumentLink {
+    pub block_id: String,
+    pub source_document_id: String,
+    pub target_document_id: Option<String>,
+    pub label: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "DocumentLink",
+    description = "Wiki style [[Document]] link in the text of a block",
+    context = Context
+)]
+impl DocumentLink {
+    #[graphql(description = "The text between the brackets")]
+    fn label(&self) -> &str {
+        &self.label
+    }
+
+    #[graphql(description = "The block the link is written in")]
+    fn block(&self, context: &Context) -> Option<Block> {
+        let mut conn = context.db_pool.get().unwrap();
+        BlockOperation::find(&mut conn, &self.block_id).ok()
+    }
+
+    #[graphql(description = "The document the link is written in")]
+    fn source(&self, context: &Context) -> DocumentResult {
+        let mut conn = context.db_pool.get().unwrap();
+        DocumentOperation::find(&mut conn, &self.source_document_id)
+    }
+
+    #[graphql(description = "The document linked to, null while no document has the label as its name or it can't be read")]
+    fn target(&self, context: &Context) -> Option<DocumentResult> {
+        let target_document_id = self.target_document_id.as_deref()?;
+        let user = context.authed_user()?;
+        let mut conn = context.db_pool.get().unwrap();
+
+        Permission::can_read_document(&mut conn, &user.id, target_document_id)
+            .then(|| DocumentOperation::find(&mut conn, target_document_id))
+    }
+
+    #[graphql(description = "DateTime for when the link was first saved")]
+    fn created_at(&self) -> &NaiveDateTime {
+        &self.created_at
+    }
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct GraphNode {
+    #[graphql(description = "ID of the document")]
+    pub id: String,
+    pub name: String,
+    pub slug: String,
+    #[graphql(description = "How many links from documents of the repository point to the document")]
+    pub backlink_count: i32,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct GraphEdge {
+    #[graphql(description = "ID of the document the links are written in")]
+    pub source: String,
+    #[graphql(description = "ID of the document linked to")]
+    pub target: String,
+    #[graphql(description = "How many links the source has to the target")]
+    pub weight: i32,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct RepositoryGraph {
+    pub nodes: Vec<GraphNode>,
+    #[graphql(description = "Links between documents of the repository, grouped by source and target")]
+    pub edges: Vec<GraphEdge>,
+}
+
+validation_result!(RepositoryGraphResult, RepositoryGraph);
+
+#[derive(Insertable)]
+#[diesel(table_name = document_links)]
+pub struct NewDocumentLink {
+    pub block_id: String,
+    pub source_document_id: String,
+    pub target_document_id: Option<String>,
+    pub label: String,
+}
+
+/// Labels of the `[[Document]]` links in `text`, each once in the order
+/// they first appear. Labels can't span lines or hold brackets.
+pub fn labels(text: &str) -> Vec<String> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("[[") {
+        let inner = &rest[start + 2..];
+        let end = inner.find(['[', ']', '\n']).unwrap_or(inner.len());
+
+        if !inner[end..].starts_with("]]") {
+            rest = &rest[start + 1..];
+            continue;
+        }
+
+        let label = inner[..end].trim();
+
+        if !label.is_empty() && label.len() <= MAX_LABEL_LENGTH && !labels.iter().any(|known| known == label) {
+            labels.push(label.into());
+        }
+
+        rest = &inner[end + 2..];
+    }
+
+    labels
+}
+
+/// The text links are read from. Code and math are left out as brackets
+/// mean something else in them.
+fn linked_text(content: &NewBlockContent) -> Option<String> {
+    match content {
+        NewBlockContent::Text { rich_text: Some(runs), .. } => Some(
+            runs.iter()
+                .filter(|run| !run.code && !run.math)
+                .map(|run| run.text.as_str())
+                .collect(),
+        ),
+        NewBlockContent::Text { content, .. }
+        | NewBlockContent::ListItem { content, .. }
+        | NewBlockContent::Todo { content, .. }
+        | NewBlockContent::Quote { content }
+        | NewBlockContent::Callout { content, .. }
+        | NewBlockContent::Toggle { content } => content.clone(),
+        _ => None,
+    }
+}
+
+pub struct LinkOperation;
+
+impl LinkOperation {
+    /// The document of the repository a label links to, matched on the
+    /// name ignoring case or on the slug. The oldest wins when several
+    /// documents have the same name.
+    fn resolve(conn: &mut DBPooledConnection, repository_id: &str, label: &str) -> QueryResult<Option<String>> {
+        documents::table
+            .filter(documents::repository_id.eq(repository_id))
+            .filter(documents::name.ilike(escape_like(label)).or(documents::slug.eq(label)))
+            .order(documents::created_at.asc())
+            .select(documents::id)
+            .first::<String>(conn)
+            .optional()
+    }
+
+    /// Stores the links in the new content of a block. Links that were
+    /// already there keep their target, so they still point to the same
+    /// document after it is renamed or moved.
+    pub fn sync_block(conn: &mut DBPooledConnection, block_id: &str, content: &NewBlockContent) -> QueryResult<()> {
+        let labels = linked_text(content).map(|text| labels(&text)).unwrap_or_default();
+
+        diesel::delete(document_links::table)
+            .filter(document_links::block_id.eq(block_id))
+            .filter(document_links::label.ne_all(&labels))
+            .execute(conn)?;
+
+        if labels.is_empty() {
+            return Ok(());
+        }
+
+        let kept = document_links::table
+            .filter(document_links::block_id.eq(block_id))
+            .select(document_links::label)
+            .get_results::<String>(conn)?;
+
+        let (document_id, repository_id) = blocks::table
+            .inner_join(documents::table)
+            .filter(blocks::id.eq(block_id))
+            .select((documents::id, documents::repository_id))
+            .get_result::<(String, String)>(conn)?;
+
+        let new_links = labels
+            .into_iter()
+            .filter(|label| !kept.contains(label))
+            .map(|label| Ok(NewDocumentLink {
+                block_id: block_id.into(),
+                source_document_id: document_id.clone(),
+                target_document_id: Self::resolve(conn, &repository_id, &label)?,
+                label,
+            }))
+            .collect::<QueryResult<Vec<_>>>()?;
+
+        diesel::insert_into(document_links::table)
+            .values(&new_links)
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Points links of the repository that had no target yet at a newly
+    /// created document with their label as its name.
+    pub fn resolve_dangling(conn: &mut DBPooledConnection, document: &Document) -> QueryResult<()> {
+        let sources = documents::table
+            .filter(documents::repository_id.eq(&document.repository_id))
+            .select(documents::id);
+
+        diesel::update(document_links::table)
+            .filter(document_links::target_document_id.is_null())
+            .filter(document_links::source_document_id.eq_any(sources))
+            .filter(document_links::label.ilike(escape_like(&document.name)).or(document_links::label.eq(&document.slug)))
+            .set(document_links::target_document_id.eq(&document.id))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Has to run before the blocks themselves are deleted.
+    pub fn delete_by_blocks(conn: &mut DBPooledConnection, block_ids: &[String]) -> QueryResult<()> {
+        diesel::delete(document_links::table.filter(document_links::block_id.eq_any(block_ids))).execute(conn)?;
+        Ok(())
+    }
+
+    pub fn outgoing(conn: &mut DBPooledConnection, document_id: &str) -> Vec<DocumentLink> {
+        let link_list = document_links::table
+            .filter(document_links::source_document_id.eq(document_id))
+            .order((document_links::created_at.asc(), document_links::label.asc()))
+            .get_results::<DocumentLink>(conn);
+
+        link_list.unwrap_or_default()
+    }
+
+    /// Links to the document written in documents of `repository_ids`.
+    pub fn backlinks(conn: &mut DBPooledConnection, document_id: &str, repository_ids: &[String]) -> Vec<DocumentLink> {
+        let sources = documents::table
+            .filter(documents::repository_id.eq_any(repository_ids))
+            .select(documents::id);
+
+        let link_list = document_links::table
+            .filter(document_links::target_document_id.eq(document_id))
+            .filter(document_links::source_document_id.eq_any(sources))
+            .order((document_links::created_at.asc(), document_links::label.asc()))
+            .get_results::<DocumentLink>(conn);
+
+        link_list.unwrap_or_default()
+    }
+
+    /// Every document of the repository and the links between them, for
+    /// drawing the repository as a graph.
+    pub fn graph(conn: &mut DBPooledConnection, repository_id: &str) -> RepositoryGraphResult {
+        let result = conn.transaction(|conn| {
+            let document_list = documents::table
+                .filter(documents::repository_id.eq(repository_id))
+                .order(documents::name.asc())
+                .select((documents::id, documents::name, documents::slug))
+                .get_results::<(String, String, String)>(conn)?;
+
+            let ids = document_list.iter().map(|(id, ..)| id.as_str()).collect::<Vec<_>>();
+
+            let edge_list = document_links::table
+                .filter(document_links::source_document_id.eq_any(&ids))
+                .filter(document_links::target_document_id.eq_any(&ids))
+                .group_by((document_links::source_document_id, document_links::target_document_id))
+                .select((document_links::source_document_id, document_links::target_document_id.assume_not_null(), count_star()))
+                .order((document_links::source_document_id.asc(), document_links::target_document_id.asc()))
+                .get_results::<(String, String, i64)>(conn)?;
+
+            Ok::<_, diesel::result::Error>((document_list, edge_list))
+        });
+
+        let Ok((document_list, edge_list)) = result else {
+            return RepositoryGraphResult::server();
+        };
+
+        let mut backlink_counts: HashMap<&str, i64> = HashMap::new();
+
+        for (_, target, weight) in &edge_list {
+            *backlink_counts.entry(target.as_str()).or_default() += weight;
+        }
+
+        let nodes = document_list
+            .iter()
+            .map(|(id, name, slug)| GraphNode {
+                id: id.clone(),
+                name: name.clone(),
+                slug: slug.clone(),
+                backlink_count: backlink_counts.get(id.as_str()).copied().unwrap_or_default() as i32,
+            })
+            .collect();
+
+        let edges = edge_list
+            .into_iter()
+            .map(|(source, target, weight)| GraphEdge { source, target, weight: weight as i32 })
+            .collect();
+
+        RepositoryGraphResult::RepositoryGraph(RepositoryGraph { nodes, edges })
+    }
+}
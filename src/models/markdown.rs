@@ -0,0 +1,57 @@
+use diesel::{prelude::*, result::{Error::DatabaseError, DatabaseErrorKind::ForeignKeyViolation}};
+use juniper::graphql_object;
+
+use crate::{schemas::root::Context, validation_result, db::DBPooledConnection, helpers::markdown::{self, ImportWarning}};
+
+use super::{block::BlockOperation, document::{Document, DocumentOperation}};
+
+pub const MAX_MARKDOWN_LENGTH: usize = 1024 * 1024;
+
+pub struct MarkdownImport {
+    pub document: Document,
+    pub warnings: Vec<ImportWarning>,
+}
+
+#[graphql_object(
+    name = "MarkdownImport",
+    description = "A document created from markdown",
+    context = Context
+)]
+impl MarkdownImport {
+    #[graphql(description = "The created document")]
+    fn document(&self) -> &Document {
+        &self.document
+    }
+
+    #[graphql(description = "Markdown constructs that could not be imported exactly")]
+    fn warnings(&self) -> &[ImportWarning] {
+        &self.warnings
+    }
+}
+
+validation_result!(MarkdownImportResult, MarkdownImport);
+
+pub struct MarkdownOperation;
+
+impl MarkdownOperation {
+    pub fn import(conn: &mut DBPooledConnection, user_id: &str, repository_id: &str, parent_id: Option<&str>, name: &str, source: &str) -> MarkdownImportResult {
+        let parsed = markdown::parse(source);
+
+        let result = conn.transaction(|conn| {
+            let document = DocumentOperation::create(conn, repository_id, parent_id, name, None)?;
+            BlockOperation::append(conn, user_id, &document.id, &parsed.blocks)?;
+
+            Ok(document)
+        });
+
+        match result {
+            Ok(document) => MarkdownImportResult::MarkdownImport(MarkdownImport {
+                document,
+                warnings: parsed.warnings,
+            }),
+            Err(DatabaseError(ForeignKeyViolation, _)) =>
+                MarkdownImportResult::not_found("repository not found"),
+            Err(_) => MarkdownImportResult::server(),
+        }
+    }
+}
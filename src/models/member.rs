@@ -0,0 +1,256 @@
+use std::io::Write;
+
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Insertable, prelude::*, AsExpression, FromSqlRow, pg::{Pg, PgValue}, serialize::{self, ToSql, Output, IsNull}, deserialize::{self, FromSql}, result::{Error::{NotFound, DatabaseError}, DatabaseErrorKind::UniqueViolation}};
+use juniper::{graphql_object, GraphQLEnum};
+
+use crate::{schemas::root::Context, validation_result, db::DBPooledConnection, schema::{users, repository_members, sql_types}, helpers::errors::{FieldErrors, FieldError}};
+
+use super::user::{User, UserOperation, UserResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, GraphQLEnum, AsExpression, FromSqlRow)]
+#[diesel(sql_type = sql_types::MemberRole)]
+pub enum MemberRole {
+    #[graphql(description = "Can do anything, including managing other owners")]
+    Owner,
+    #[graphql(description = "Can edit, publish and manage collaborators other than owners")]
+    Admin,
+    #[graphql(description = "Can read and edit the documents")]
+    Editor,
+    #[graphql(description = "Can read the documents and comment on them")]
+    Commenter,
+    #[graphql(description = "Can read the documents")]
+    Viewer,
+}
+
+impl MemberRole {
+    /// Roles that can change the documents of a repository.
+    pub const EDITING: [MemberRole; 3] = [MemberRole::Owner, MemberRole::Admin, MemberRole::Editor];
+
+    pub fn can_edit(self) -> bool {
+        Self::EDITING.contains(&self)
+    }
+
+    /// Whether the role allows publishing and managing collaborators.
+    pub fn can_manage(self) -> bool {
+        matches!(self, MemberRole::Owner | MemberRole::Admin)
+    }
+
+    /// Whether a member with this role can give `role` to someone or take
+    /// it away. Only owners can touch other owners.
+    pub fn can_assign(self, role: MemberRole) -> bool {
+        match self {
+            MemberRole::Owner => true,
+            MemberRole::Admin => role != MemberRole::Owner,
+            _ => false,
+        }
+    }
+}
+
+impl ToSql<sql_types::MemberRole, Pg> for MemberRole {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        match *self {
+            MemberRole::Owner => out.write_all(b"OWNER")?,
+            MemberRole::Admin => out.write_all(b"ADMIN")?,
+            MemberRole::Editor => out.write_all(b"EDITOR")?,
+            MemberRole::Commenter => out.write_all(b"COMMENTER")?,
+            MemberRole::Viewer => out.write_all(b"VIEWER")?,
+        }
+
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<sql_types::MemberRole, Pg> for MemberRole {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"OWNER" => Ok(MemberRole::Owner),
+            b"ADMIN" => Ok(MemberRole::Admin),
+            b"EDITOR" => Ok(MemberRole::Editor),
+            b"COMMENTER" => Ok(MemberRole::Commenter),
+            b"VIEWER" => Ok(MemberRole::Viewer),
+            _ => Err("unrecognized member role variant".into()),
+        }
+    }
+}
+
+#[derive(Queryable)]
+pub struct RepositoryMember {
+    pub repository_id: String,
+    pub user_id: String,
+    pub role: MemberRole,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[graphql_object(
+    name = "RepositoryMember",
+    description = "A user with a role in a repository",
+    context = Context
+)]
+impl RepositoryMember {
+    fn repository_id(&self) -> &str {
+        &self.repository_id
+    }
+
+    fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    #[graphql(description = "The user the role is given to")]
+    fn user(&self, context: &Context) -> UserResult {
+        let mut conn = context.db_pool.get().unwrap();
+        UserOperation::find(&mut conn, &self.user_id)
+    }
+
+    #[graphql(description = "What the user can do in the repository")]
+    fn role(&self) -> MemberRole {
+        self.role
+    }
+
+    #[graphql(description = "DateTime for when the user joined the repository")]
+    fn created_at(&self) -> &NaiveDateTime {
+        &self.created_at
+    }
+
+    #[graphql(description = "DateTime for when the role was last changed")]
+    fn updated_at(&self) -> &NaiveDateTime {
+        &self.updated_at
+    }
+}
+
+validation_result!(RepositoryMemberResult, RepositoryMember);
+
+#[derive(Insertable)]
+#[diesel(table_name = repository_members)]
+pub struct NewRepositoryMember {
+    pub repository_id: String,
+    pub user_id: String,
+    pub role: MemberRole,
+}
+
+impl NewRepositoryMember {
+    pub fn new(repository_id: &str, user_id: &str, role: MemberRole) -> NewRepositoryMember {
+        NewRepositoryMember {
+            repository_id: repository_id.into(),
+            user_id: user_id.into(),
+            role,
+        }
+    }
+}
+
+pub enum MemberError {
+    UserNotFound,
+    AlreadyMember,
+    LastOwner,
+    Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for MemberError {
+    fn from(err: diesel::result::Error) -> MemberError {
+        MemberError::Database(err)
+    }
+}
+
+fn member_result(result: Result<RepositoryMember, MemberError>) -> RepositoryMemberResult {
+    let (field, message) = match result {
+        Ok(member) => return RepositoryMemberResult::RepositoryMember(member),
+        Err(MemberError::Database(NotFound)) => return RepositoryMemberResult::not_found("collaborator not found"),
+        Err(MemberError::Database(_)) => return RepositoryMemberResult::server(),
+        Err(MemberError::UserNotFound) => ("email", "isn't the email of a user"),
+        Err(MemberError::AlreadyMember) => ("email", "is already a collaborator"),
+        Err(MemberError::LastOwner) => ("userId", "is the last owner of the repository"),
+    };
+
+    let mut errors = FieldErrors::new();
+    errors.push(FieldError::new(field, message));
+
+    RepositoryMemberResult::FieldErrors(errors)
+}
+
+pub struct MemberOperation;
+
+impl MemberOperation {
+    /// Members of the repository, owners first.
+    pub fn find_by_repository(conn: &mut DBPooledConnection, repository_id: &str) -> Vec<RepositoryMember> {
+        repository_members::table
+            .filter(repository_members::repository_id.eq(repository_id))
+            .order((repository_members::role, repository_members::created_at))
+            .get_results::<RepositoryMember>(conn)
+            .unwrap_or_default()
+    }
+
+    pub fn add(conn: &mut DBPooledConnection, repository_id: &str, email: &str, role: MemberRole) -> RepositoryMemberResult {
+        let result = conn.transaction(|conn| {
+            let user = users::table
+                .filter(users::email.eq(email))
+                .get_result::<User>(conn)
+                .optional()?
+                .ok_or(MemberError::UserNotFound)?;
+
+            let member = diesel::insert_into(repository_members::table)
+                .values(&NewRepositoryMember::new(repository_id, &user.id, role))
+                .get_result::<RepositoryMember>(conn);
+
+            match member {
+                Err(DatabaseError(UniqueViolation, _)) => Err(MemberError::AlreadyMember),
+                member => Ok(member?),
+            }
+        });
+
+        member_result(result)
+    }
+
+    pub fn update_role(conn: &mut DBPooledConnection, repository_id: &str, user_id: &str, role: MemberRole) -> RepositoryMemberResult {
+        let result = conn.transaction(|conn| {
+            if role != MemberRole::Owner {
+                Self::check_other_owner(conn, repository_id, user_id)?;
+            }
+
+            let member = diesel::update(repository_members::table)
+                .filter(repository_members::repository_id.eq(repository_id))
+                .filter(repository_members::user_id.eq(user_id))
+                .set(repository_members::role.eq(role))
+                .get_result::<RepositoryMember>(conn)?;
+
+            Ok(member)
+        });
+
+        member_result(result)
+    }
+
+    pub fn remove(conn: &mut DBPooledConnection, repository_id: &str, user_id: &str) -> RepositoryMemberResult {
+        let result = conn.transaction(|conn| {
+            Self::check_other_owner(conn, repository_id, user_id)?;
+
+            let member = diesel::delete(repository_members::table)
+                .filter(repository_members::repository_id.eq(repository_id))
+                .filter(repository_members::user_id.eq(user_id))
+                .get_result::<RepositoryMember>(conn)?;
+
+            Ok(member)
+        });
+
+        member_result(result)
+    }
+
+    /// Makes sure the repository keeps an owner when `user_id` stops being
+    /// one. The members are locked so two owners can't step down at once.
+    fn check_other_owner(conn: &mut DBPooledConnection, repository_id: &str, user_id: &str) -> Result<(), MemberError> {
+        let owner_ids = repository_members::table
+            .filter(repository_members::repository_id.eq(repository_id))
+            .select((repository_members::user_id, repository_members::role))
+            .order(repository_members::user_id)
+            .for_update()
+            .get_results::<(String, MemberRole)>(conn)?
+            .into_iter()
+            .filter_map(|(id, role)| (role == MemberRole::Owner).then_some(id))
+            .collect::<Vec<_>>();
+
+        if owner_ids == [user_id] {
+            return Err(MemberError::LastOwner);
+        }
+
+        Ok(())
+    }
+}
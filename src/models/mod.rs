@@ -1,3 +1,18 @@
 pub mod user;
 pub mod repository;
-
+pub mod document;
+pub mod block;
+pub mod revision;
+pub mod diff;
+pub mod search;
+pub mod quick_find;
+pub mod markdown;
+pub mod upload;
+pub mod accessibility;
+pub mod rich_text;
+pub mod table;
+pub mod database;
+pub mod link;
+pub mod embed;
+pub mod member;
+pub mod invitation;
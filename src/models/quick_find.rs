@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use diesel::{prelude::*, sql_query, sql_types::{Array, BigInt, Bpchar, Float4, Text}};
+use juniper::{graphql_object, GraphQLUnion};
+
+use crate::{schemas::root::Context, validation_result, db::DBPooledConnection, helpers::{permission::Permission, sql::escape_like}, schema::{documents, repositories}};
+
+use super::{document::Document, repository::Repository};
+
+pub const DEFAULT_LIMIT: i32 = 10;
+pub const MAX_LIMIT: i32 = 50;
+
+// `<%` and `ilike` can both be answered by the trigram indexes on name.
+// Prefix matches get a bonus so they always come before fuzzy ones.
+const QUICK_FIND_QUERY: &str = "
+    select kind, id, score from (
+        select
+            'repository' as kind,
+            r.id as id,
+            (word_similarity($1, r.name) + case when r.name ilike $2 then 1 else 0 end)::real as score,
+            r.updated_at as updated_at
+        from repositories r
+        where
+            r.id = any($3)
+            and ($1 <% r.name or r.name ilike $2)
+
+        union all
+
+        select
+            'document' as kind,
+            d.id as id,
+            (word_similarity($1, d.name) + case when d.name ilike $2 then 1 else 0 end)::real as score,
+            d.updated_at as updated_at
+        from documents d
+        where
+            d.repository_id = any($3)
+            and ($1 <% d.name or d.name ilike $2)
+    ) hits
+    order by score desc, updated_at desc
+    limit $4
+";
+
+#[derive(QueryableByName)]
+struct QuickFindRow {
+    #[diesel(sql_type = Text)]
+    kind: String,
+    #[diesel(sql_type = Bpchar)]
+    id: String,
+    #[diesel(sql_type = Float4)]
+    score: f32,
+}
+
+#[derive(GraphQLUnion)]
+#[graphql(context = Context)]
+pub enum QuickFindItem {
+    Repository(Repository),
+    Document(Document),
+}
+
+pub struct QuickFindHit {
+    pub item: QuickFindItem,
+    pub score: f32,
+}
+
+#[graphql_object(
+    name = "QuickFindHit",
+    description = "A repository or document whose name matches a quick find term",
+    context = Context
+)]
+impl QuickFindHit {
+    #[graphql(description = "The matching repository or document")]
+    fn item(&self) -> &QuickFindItem {
+        &self.item
+    }
+
+    #[graphql(description = "How closely the name matches, higher is better")]
+    fn score(&self) -> f64 {
+        self.score as f64
+    }
+}
+
+pub struct QuickFind {
+    pub term: String,
+    pub hits: Vec<QuickFindHit>,
+}
+
+#[graphql_object(
+    name = "QuickFind",
+    description = "Typo tolerant name matches for jumping to a repository or document",
+    context = Context
+)]
+impl QuickFind {
+    #[graphql(description = "The term that was matched against")]
+    fn term(&self) -> &str {
+        &self.term
+    }
+
+    #[graphql(description = "Matches ordered by score then by most recently updated")]
+    fn hits(&self) -> &[QuickFindHit] {
+        &self.hits
+    }
+}
+
+validation_result!(QuickFindResult, QuickFind);
+
+pub struct QuickFindOperation;
+
+impl QuickFindOperation {
+    pub fn find(conn: &mut DBPooledConnection, user_id: &str, term: &str, limit: i32) -> QuickFindResult {
+        let repository_ids = Permission::readable_repository_ids(conn, user_id);
+
+        let rows = sql_query(QUICK_FIND_QUERY)
+            .bind::<Text, _>(term)
+            .bind::<Text, _>(format!("{}%", escape_like(term)))
+            .bind::<Array<Bpchar>, _>(repository_ids)
+            .bind::<BigInt, _>(limit as i64)
+            .load::<QuickFindRow>(conn);
+
+        let Ok(rows) = rows else {
+            return QuickFindResult::server();
+        };
+
+        let ids_of = |kind: &str| rows
+            .iter()
+            .filter(|row| row.kind == kind)
+            .map(|row| row.id.clone())
+            .collect::<Vec<_>>();
+
+        let repository_list = repositories::table
+            .filter(repositories::id.eq_any(ids_of("repository")))
+            .get_results::<Repository>(conn);
+
+        let document_list = documents::table
+            .filter(documents::id.eq_any(ids_of("document")))
+            .get_results::<Document>(conn);
+
+        let (Ok(repository_list), Ok(document_list)) = (repository_list, document_list) else {
+            return QuickFindResult::server();
+        };
+
+        let mut repository_map = repository_list
+            .into_iter()
+            .map(|repository| (repository.id.clone(), repository))
+            .collect::<HashMap<_, _>>();
+
+        let mut document_map = document_list
+            .into_iter()
+            .map(|document| (document.id.clone(), document))
+            .collect::<HashMap<_, _>>();
+
+        let hits = rows
+            .iter()
+            .filter_map(|row| {
+                let item = match row.kind.as_str() {
+                    "repository" => QuickFindItem::Repository(repository_map.remove(&row.id)?),
+                    _ => QuickFindItem::Document(document_map.remove(&row.id)?),
+                };
+
+                Some(QuickFindHit { item, score: row.score })
+            })
+            .collect();
+
+        QuickFindResult::QuickFind(QuickFind { term: term.into(), hits })
+    }
+}
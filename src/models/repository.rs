@@ -3,9 +3,9 @@ use diesel::{Queryable, Insertable, prelude::*, result::{Error::{NotFound, Datab
 use juniper::graphql_object;
 use nanoid::nanoid;
 
-use crate::{schemas::root::Context, validation_result, schema::repositories, db::DBPooledConnection};
+use crate::{schemas::root::Context, validation_result, schema::{repositories, repository_members}, db::DBPooledConnection};
 
-use super::user::{UserOperation, UserResult};
+use super::{user::{UserOperation, UserResult}, document::{Document, DocumentOperation}, member::{RepositoryMember, MemberOperation, MemberRole, NewRepositoryMember}};
 
 #[derive(Queryable)]
 pub struct Repository {
@@ -16,6 +16,7 @@ pub struct Repository {
     pub description: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub published_at: Option<NaiveDateTime>,
     // id          char(21) primary key,
     // user_id     char(21) not null references users(id),
     // slug        text unique not null,
@@ -23,6 +24,7 @@ pub struct Repository {
     // description text,
     // created_at  timestamp not null default now(),
     // updated_at  timestamp not null default now()
+    // published_at timestamp
 }
 
 #[graphql_object(
@@ -36,7 +38,7 @@ impl Repository {
         &self.id
     }
 
-    #[graphql(description = "The user who owns the repository")]
+    #[graphql(description = "The user who created the repository and whose site path it is under")]
     fn user(&self, context: &Context) -> UserResult {
         let mut conn = context.db_pool.get().unwrap();
         UserOperation::find(&mut conn, &self.user_id)
@@ -57,6 +59,18 @@ impl Repository {
         self.description.as_deref()
     }
 
+    #[graphql(description = "The documents in the repository")]
+    fn documents(&self, context: &Context) -> Vec<Document> {
+        let mut conn = context.db_pool.get().unwrap();
+        DocumentOperation::find_by_repository(&mut conn, &self.id)
+    }
+
+    #[graphql(description = "The users with a role in the repository, owners first")]
+    fn members(&self, context: &Context) -> Vec<RepositoryMember> {
+        let mut conn = context.db_pool.get().unwrap();
+        MemberOperation::find_by_repository(&mut conn, &self.id)
+    }
+
     #[graphql(description = "DateTime for when the user was created")]
     fn created_at(&self) -> &NaiveDateTime {
         &self.created_at
@@ -66,6 +80,23 @@ impl Repository {
     fn updated_at(&self) -> &NaiveDateTime {
         &self.updated_at
     }
+
+    #[graphql(description = "DateTime for when the repository was published, null when it isn't public")]
+    fn published_at(&self) -> Option<&NaiveDateTime> {
+        self.published_at.as_ref()
+    }
+
+    #[graphql(description = "Path of the public site, null when the repository isn't published")]
+    fn site_path(&self) -> Option<String> {
+        self.published_at.map(|_| self.site_path())
+    }
+}
+
+impl Repository {
+    /// Root of the public site for the repository.
+    pub fn site_path(&self) -> String {
+        format!("/p/{}/{}", self.user_id, self.slug)
+    }
 }
 
 
@@ -99,9 +130,17 @@ impl RepositoryOperation {
     pub fn create(conn: &mut DBPooledConnection, user_id: &str, slug: &str, name: &str, description: Option<&str>) -> RepositoryResult {
         let new_repository = NewRepository::new(user_id, slug, name, description);
  
-        let result = diesel::insert_into(repositories::table)
-            .values(&new_repository)
-            .get_result::<Repository>(conn);
+        let result = conn.transaction(|conn| {
+            let repository = diesel::insert_into(repositories::table)
+                .values(&new_repository)
+                .get_result::<Repository>(conn)?;
+
+            diesel::insert_into(repository_members::table)
+                .values(&NewRepositoryMember::new(&repository.id, user_id, MemberRole::Owner))
+                .execute(conn)?;
+
+            Ok(repository)
+        });
 
 
         match result {
@@ -126,6 +165,59 @@ impl RepositoryOperation {
         }
     }
 
+    pub fn find_by_slug(conn: &mut DBPooledConnection, slug: &str) -> RepositoryResult {
+        use crate::schema::repositories::dsl::{repositories, slug as repository_slug};
+
+        let repository = repositories
+            .filter(repository_slug.eq(slug))
+            .get_result::<Repository>(conn);
+
+        match repository {
+            Ok(repository) => RepositoryResult::Repository(repository),
+            Err(NotFound) => RepositoryResult::not_found("repository not found"),
+            Err(_) => RepositoryResult::server(),
+        }
+    }
+
+    /// Looks up a repository for the public site. Anything unpublished or
+    /// owned by someone else is treated as missing.
+    pub fn find_published(conn: &mut DBPooledConnection, owner_id: &str, slug: &str) -> Option<Repository> {
+        repositories::table
+            .filter(repositories::user_id.eq(owner_id))
+            .filter(repositories::slug.eq(slug))
+            .filter(repositories::published_at.is_not_null())
+            .get_result::<Repository>(conn)
+            .ok()
+    }
+
+    /// Publishes the repository, keeping the original date when it is
+    /// already published.
+    pub fn publish(conn: &mut DBPooledConnection, id: &str) -> RepositoryResult {
+        let result = diesel::update(repositories::table)
+            .filter(repositories::id.eq(id))
+            .filter(repositories::published_at.is_null())
+            .set(repositories::published_at.eq(diesel::dsl::now))
+            .execute(conn);
+
+        match result {
+            Ok(_) => Self::find(conn, id),
+            Err(_) => RepositoryResult::server(),
+        }
+    }
+
+    pub fn unpublish(conn: &mut DBPooledConnection, id: &str) -> RepositoryResult {
+        let repository = diesel::update(repositories::table)
+            .filter(repositories::id.eq(id))
+            .set(repositories::published_at.eq(None::<NaiveDateTime>))
+            .get_result::<Repository>(conn);
+
+        match repository {
+            Ok(repository) => RepositoryResult::Repository(repository),
+            Err(NotFound) => RepositoryResult::not_found("repository not found"),
+            Err(_) => RepositoryResult::server(),
+        }
+    }
+
     pub fn find_by_user(conn: &mut DBPooledConnection, user_id: &str) -> Vec<Repository> {
         use crate::schema::repositories::dsl::{repositories, user_id as repository_user_id};
         
@@ -133,10 +225,18 @@ impl RepositoryOperation {
             .filter(repository_user_id.eq(user_id))
             .get_results::<Repository>(conn);
 
-        match repository_list {
-            Ok(vals) => vals,
-            Err(_) => vec![],
-        }
+        repository_list.unwrap_or_default()
     }
-}
 
+    /// Repositories the user is a member of but didn't create.
+    pub fn find_shared_with_user(conn: &mut DBPooledConnection, user_id: &str) -> Vec<Repository> {
+        repositories::table
+            .inner_join(repository_members::table)
+            .filter(repository_members::user_id.eq(user_id))
+            .filter(repositories::user_id.ne(user_id))
+            .select(repositories::all_columns)
+            .order(repositories::name)
+            .get_results::<Repository>(conn)
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,250 @@
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Insertable, prelude::*, result::{Error::{NotFound, DatabaseError}, DatabaseErrorKind::ForeignKeyViolation}};
+use juniper::{graphql_object, GraphQLEnum, GraphQLObject};
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+
+use crate::{schemas::root::Context, validation_result, schema::document_revisions, db::DBPooledConnection};
+
+use super::{block::{Block, BlockContent, BlockOperation, Tag, ListStyle, CalloutVariant}, rich_text::TextRun, table::{TableColumn, TableRow}, document::{DocumentOperation, DocumentResult}, user::{UserOperation, UserResult}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, GraphQLEnum, Serialize, Deserialize)]
+pub enum BlockKind {
+    Text,
+    Image,
+    ListItem,
+    Todo,
+    Quote,
+    Code,
+    Math,
+    Embed,
+    Callout,
+    Toggle,
+    Table,
+    Divider,
+}
+
+/// A block as it was when a revision was saved. Stored inside the
+/// revisions `blocks` json column.
+#[derive(Clone, GraphQLObject, Serialize, Deserialize)]
+#[graphql(context = Context)]
+pub struct RevisionBlock {
+    pub id: String,
+    #[graphql(description = "The block it was nested under, null for top level blocks")]
+    pub parent_id: Option<String>,
+    pub line_number: i32,
+    pub kind: BlockKind,
+    pub tag: Option<Tag>,
+    pub content: Option<String>,
+    #[graphql(description = "Marked up text of text blocks, null when the text is plain")]
+    pub rich_text: Option<Vec<TextRun>>,
+    pub url: Option<String>,
+    pub alt: Option<String>,
+    pub caption: Option<String>,
+    pub list_style: Option<ListStyle>,
+    pub indent: Option<i32>,
+    pub checked: Option<bool>,
+    pub language: Option<String>,
+    pub variant: Option<CalloutVariant>,
+    #[graphql(description = "The block embed blocks showed, null once it was deleted")]
+    pub source_block_id: Option<String>,
+    pub columns: Option<Vec<TableColumn>>,
+    pub rows: Option<Vec<TableRow>>,
+}
+
+impl RevisionBlock {
+    pub fn new(block: &Block, content: Option<&BlockContent>) -> Option<RevisionBlock> {
+        let content = content?;
+
+        let kind = match content {
+            BlockContent::TextBlock(_) => BlockKind::Text,
+            BlockContent::ImageBlock(_) => BlockKind::Image,
+            BlockContent::ListItemBlock(_) => BlockKind::ListItem,
+            BlockContent::TodoBlock(_) => BlockKind::Todo,
+            BlockContent::QuoteBlock(_) => BlockKind::Quote,
+            BlockContent::CodeBlock(_) => BlockKind::Code,
+            BlockContent::MathBlock(_) => BlockKind::Math,
+            BlockContent::EmbedBlock(_) => BlockKind::Embed,
+            BlockContent::CalloutBlock(_) => BlockKind::Callout,
+            BlockContent::ToggleBlock(_) => BlockKind::Toggle,
+            BlockContent::TableBlock(_) => BlockKind::Table,
+            BlockContent::DividerBlock(_) => BlockKind::Divider,
+        };
+
+        let mut revision_block = RevisionBlock {
+            id: block.id.clone(),
+            parent_id: block.parent_id.clone(),
+            line_number: block.line_number,
+            kind,
+            tag: None,
+            content: content.text().map(String::from),
+            rich_text: None,
+            url: None,
+            alt: None,
+            caption: None,
+            list_style: None,
+            indent: None,
+            checked: None,
+            language: None,
+            variant: None,
+            source_block_id: None,
+            columns: None,
+            rows: None,
+        };
+
+        match content {
+            BlockContent::TextBlock(text_block) => {
+                revision_block.tag = Some(text_block.tag);
+                revision_block.rich_text = text_block.rich_text.is_some().then(|| text_block.runs());
+            },
+            BlockContent::ImageBlock(image_block) => {
+                revision_block.url = image_block.url.clone();
+                revision_block.alt = image_block.alt.clone();
+                revision_block.caption = image_block.caption.clone();
+            },
+            BlockContent::ListItemBlock(list_item_block) => {
+                revision_block.list_style = Some(list_item_block.style);
+                revision_block.indent = Some(list_item_block.indent);
+            },
+            BlockContent::TodoBlock(todo_block) => {
+                revision_block.checked = Some(todo_block.checked);
+                revision_block.indent = Some(todo_block.indent);
+            },
+            BlockContent::CodeBlock(code_block) => revision_block.language = code_block.language.clone(),
+            BlockContent::CalloutBlock(callout_block) => revision_block.variant = Some(callout_block.variant),
+            BlockContent::EmbedBlock(embed_block) => revision_block.source_block_id = embed_block.source_block_id.clone(),
+            BlockContent::TableBlock(table_block) => {
+                revision_block.columns = Some(table_block.column_list());
+                revision_block.rows = Some(table_block.row_list());
+            },
+            BlockContent::QuoteBlock(_) | BlockContent::MathBlock(_) | BlockContent::ToggleBlock(_) | BlockContent::DividerBlock(_) => {},
+        }
+
+        Some(revision_block)
+    }
+}
+
+#[derive(Queryable)]
+pub struct DocumentRevision {
+    pub id: String,
+    pub document_id: String,
+    pub user_id: String,
+    pub blocks: serde_json::Value,
+    pub created_at: NaiveDateTime,
+}
+
+impl DocumentRevision {
+    pub fn block_list(&self) -> Vec<RevisionBlock> {
+        serde_json::from_value(self.blocks.clone()).unwrap_or_default()
+    }
+}
+
+#[graphql_object(
+    name = "DocumentRevision",
+    description = "Snapshot of a documents blocks at a point in time",
+    context = Context
+)]
+impl DocumentRevision {
+    #[graphql(description = "The revisions ID in base64 format")]
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[graphql(description = "The document the revision was taken of")]
+    fn document(&self, context: &Context) -> DocumentResult {
+        let mut conn = context.db_pool.get().unwrap();
+        DocumentOperation::find(&mut conn, &self.document_id)
+    }
+
+    #[graphql(description = "The user who saved the revision")]
+    fn user(&self, context: &Context) -> UserResult {
+        let mut conn = context.db_pool.get().unwrap();
+        UserOperation::find(&mut conn, &self.user_id)
+    }
+
+    #[graphql(description = "The blocks of the document when the revision was saved")]
+    fn blocks(&self) -> Vec<RevisionBlock> {
+        self.block_list()
+    }
+
+    #[graphql(description = "DateTime for when the revision was saved")]
+    fn created_at(&self) -> &NaiveDateTime {
+        &self.created_at
+    }
+}
+
+validation_result!(DocumentRevisionResult, DocumentRevision);
+
+#[derive(Insertable)]
+#[diesel(table_name = document_revisions)]
+pub struct NewDocumentRevision {
+    pub id: String,
+    pub document_id: String,
+    pub user_id: String,
+    pub blocks: serde_json::Value,
+}
+
+impl NewDocumentRevision {
+    pub fn new(document_id: &str, user_id: &str, blocks: &[RevisionBlock]) -> NewDocumentRevision {
+        NewDocumentRevision {
+            id: nanoid!(),
+            document_id: document_id.into(),
+            user_id: user_id.into(),
+            blocks: serde_json::to_value(blocks).unwrap(),
+        }
+    }
+}
+
+pub struct RevisionOperation;
+
+impl RevisionOperation {
+    pub fn create(conn: &mut DBPooledConnection, document_id: &str, user_id: &str) -> DocumentRevisionResult {
+        let block_list = match BlockOperation::find_by_document_with_content(conn, document_id) {
+            Ok(block_list) => block_list,
+            Err(_) => return DocumentRevisionResult::server(),
+        };
+
+        let revision_blocks = block_list
+            .iter()
+            .filter_map(|(block, content)| RevisionBlock::new(block, content.as_ref()))
+            .collect::<Vec<_>>();
+
+        let new_revision = NewDocumentRevision::new(document_id, user_id, &revision_blocks);
+
+        let result = diesel::insert_into(document_revisions::table)
+            .values(&new_revision)
+            .get_result::<DocumentRevision>(conn);
+
+        match result {
+            Ok(revision) => DocumentRevisionResult::DocumentRevision(revision),
+            Err(DatabaseError(ForeignKeyViolation, _)) =>
+                DocumentRevisionResult::not_found("document not found"),
+            Err(_) => DocumentRevisionResult::server(),
+        }
+    }
+
+    pub fn find(conn: &mut DBPooledConnection, id: &str) -> DocumentRevisionResult {
+        use crate::schema::document_revisions::dsl::{document_revisions, id as revision_id};
+
+        let revision = document_revisions
+            .filter(revision_id.eq(id))
+            .get_result::<DocumentRevision>(conn);
+
+        match revision {
+            Ok(revision) => DocumentRevisionResult::DocumentRevision(revision),
+            Err(NotFound) => DocumentRevisionResult::not_found("revision not found"),
+            Err(_) => DocumentRevisionResult::server(),
+        }
+    }
+
+    pub fn find_by_document(conn: &mut DBPooledConnection, document_id: &str) -> Vec<DocumentRevision> {
+        use crate::schema::document_revisions::dsl::{document_revisions, document_id as revision_document_id, created_at};
+
+        let revision_list = document_revisions
+            .filter(revision_document_id.eq(document_id))
+            .order(created_at.desc())
+            .get_results::<DocumentRevision>(conn);
+
+        revision_list.unwrap_or_default()
+    }
+}
@@ -0,0 +1,160 @@
+use juniper::{GraphQLInputObject, GraphQLObject};
+use serde::{Deserialize, Serialize};
+
+use crate::{schemas::root::Context, helpers::{errors::{FieldErrors, FieldError}, html::safe_url, math}};
+
+pub const MAX_RUNS: usize = 1000;
+pub const MAX_LINK_LENGTH: usize = 2048;
+
+/// A piece of text whose characters all have the same marks. Stored as
+/// json in `text_blocks.rich_text`.
+#[derive(Debug, Clone, Default, PartialEq, GraphQLObject, Serialize, Deserialize)]
+#[graphql(Context = Context)]
+pub struct TextRun {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub bold: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub italic: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub code: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub strike: bool,
+    #[graphql(description = "Whether the text is LaTeX math written in with the text around it")]
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub math: bool,
+    #[graphql(description = "Where the text links to")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(Context = Context)]
+pub struct TextRunInput {
+    pub text: String,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub code: Option<bool>,
+    pub strike: Option<bool>,
+    #[graphql(description = "Treats the text as LaTeX math, which has to be valid")]
+    pub math: Option<bool>,
+    #[graphql(description = "Http(s), mailto, root relative or #fragment url, other urls are dropped")]
+    pub link: Option<String>,
+}
+
+impl From<TextRunInput> for TextRun {
+    fn from(input: TextRunInput) -> TextRun {
+        TextRun {
+            text: input.text,
+            bold: input.bold.unwrap_or(false),
+            italic: input.italic.unwrap_or(false),
+            code: input.code.unwrap_or(false),
+            strike: input.strike.unwrap_or(false),
+            math: input.math.unwrap_or(false),
+            link: input.link,
+        }
+    }
+}
+
+impl TextRun {
+    pub fn plain(text: &str) -> TextRun {
+        TextRun { text: text.into(), ..TextRun::default() }
+    }
+
+    pub fn is_plain(&self) -> bool {
+        !self.bold && !self.italic && !self.code && !self.strike && !self.math && self.link.is_none()
+    }
+
+    /// Math runs are never joined as that could change what the LaTeX
+    /// means, `\alpha` followed by `b` isn't `\alphab`.
+    fn same_marks(&self, other: &TextRun) -> bool {
+        !self.math
+            && !other.math
+            && self.bold == other.bold
+            && self.italic == other.italic
+            && self.code == other.code
+            && self.strike == other.strike
+            && self.link == other.link
+    }
+}
+
+/// Link targets that can't run scripts, anything else is dropped.
+pub fn safe_link(link: &str) -> Option<String> {
+    let link = link.trim();
+
+    let allowed = link.to_lowercase().starts_with("mailto:")
+        || link.starts_with('#')
+        || safe_url(link).is_some();
+
+    allowed.then(|| link.to_string())
+}
+
+pub fn validate(field: &str, runs: &[TextRunInput], errors: &mut FieldErrors) {
+    if runs.len() > MAX_RUNS {
+        errors.push(FieldError::new(field, &format!("can't have more than {} runs", MAX_RUNS)));
+    }
+
+    if runs.iter().any(|run| run.link.as_ref().is_some_and(|link| link.len() > MAX_LINK_LENGTH)) {
+        errors.push(FieldError::new(field, &format!("links can't be longer than {} characters", MAX_LINK_LENGTH)));
+    }
+
+    for run in runs.iter().filter(|run| run.math == Some(true)) {
+        math::validate(field, &run.text, errors);
+    }
+}
+
+/// Sanitises the links, drops empty runs and joins neighbours that have
+/// the same marks so equal text always has the same runs.
+pub fn normalize(runs: Vec<TextRun>) -> Vec<TextRun> {
+    let mut normalized: Vec<TextRun> = Vec::with_capacity(runs.len());
+
+    for mut run in runs {
+        run.link = run.link.as_deref().and_then(safe_link);
+
+        if run.text.is_empty() {
+            continue;
+        }
+
+        match normalized.last_mut() {
+            Some(last) if last.same_marks(&run) => last.text.push_str(&run.text),
+            _ => normalized.push(run),
+        }
+    }
+
+    normalized
+}
+
+/// Removes whitespace from the start and end of the text.
+pub fn trim(runs: Vec<TextRun>) -> Vec<TextRun> {
+    let mut runs = normalize(runs);
+
+    while let Some(first) = runs.first_mut() {
+        first.text = first.text.trim_start().to_string();
+
+        if !first.text.is_empty() {
+            break;
+        }
+
+        runs.remove(0);
+    }
+
+    while let Some(last) = runs.last_mut() {
+        last.text = last.text.trim_end().to_string();
+
+        if !last.text.is_empty() {
+            break;
+        }
+
+        runs.pop();
+    }
+
+    runs
+}
+
+pub fn plain_text(runs: &[TextRun]) -> String {
+    runs.iter().map(|run| run.text.as_str()).collect()
+}
@@ -0,0 +1,158 @@
+use diesel::{prelude::*, sql_query, sql_types::{Array, BigInt, Bpchar, Float4, Nullable, Text}};
+use juniper::graphql_object;
+
+use crate::{schemas::root::Context, validation_result, db::DBPooledConnection, helpers::permission::Permission};
+
+use super::{block::{BlockContent, BlockOperation}, document::{DocumentOperation, DocumentResult}};
+
+const MAX_HITS: i64 = 50;
+
+const SEARCH_QUERY: &str = "
+    with q as (select websearch_to_tsquery('english', $1) as query)
+    select document_id, block_id, rank, snippet from (
+        select
+            d.id as document_id,
+            null::char(21) as block_id,
+            ts_rank(
+                setweight(to_tsvector('english', d.name), 'A') ||
+                setweight(to_tsvector('english', coalesce(d.description, '')), 'B'),
+                q.query
+            ) as rank,
+            ts_headline(
+                'english',
+                html_escape(d.name || coalesce(' - ' || d.description, '')),
+                q.query,
+                'StartSel=<mark>, StopSel=</mark>, MaxFragments=1'
+            ) as snippet,
+            d.updated_at as updated_at
+        from documents d, q
+        where
+            (
+                setweight(to_tsvector('english', d.name), 'A') ||
+                setweight(to_tsvector('english', coalesce(d.description, '')), 'B')
+            ) @@ q.query
+            and d.repository_id = any($2)
+            and ($3::char(21) is null or d.repository_id = $3)
+
+        union all
+
+        select
+            b.document_id as document_id,
+            b.id as block_id,
+            ts_rank(to_tsvector('english', coalesce(t.content, '')), q.query) as rank,
+            ts_headline(
+                'english',
+                html_escape(coalesce(t.content, '')),
+                q.query,
+                'StartSel=<mark>, StopSel=</mark>, MaxFragments=2'
+            ) as snippet,
+            t.updated_at as updated_at
+        from (
+            select block_id, content, updated_at from text_blocks
+            union all select block_id, content, updated_at from list_item_blocks
+            union all select block_id, content, updated_at from todo_blocks
+            union all select block_id, content, updated_at from quote_blocks
+            union all select block_id, content, updated_at from code_blocks
+            union all select block_id, content, updated_at from callout_blocks
+            union all select block_id, content, updated_at from toggle_blocks
+        ) t
+        join blocks b on b.id = t.block_id
+        join documents d on d.id = b.document_id, q
+        where
+            to_tsvector('english', coalesce(t.content, '')) @@ q.query
+            and d.repository_id = any($2)
+            and ($3::char(21) is null or d.repository_id = $3)
+    ) hits
+    order by rank desc, updated_at desc
+    limit $4
+";
+
+#[derive(QueryableByName)]
+pub struct SearchHit {
+    #[diesel(sql_type = Bpchar)]
+    pub document_id: String,
+    #[diesel(sql_type = Nullable<Bpchar>)]
+    pub block_id: Option<String>,
+    #[diesel(sql_type = Float4)]
+    pub rank: f32,
+    #[diesel(sql_type = Text)]
+    pub snippet: String,
+}
+
+#[graphql_object(
+    name = "SearchHit",
+    description = "A document or block matching a search query",
+    context = Context
+)]
+impl SearchHit {
+    #[graphql(description = "The matching document")]
+    fn document(&self, context: &Context) -> DocumentResult {
+        let mut conn = context.db_pool.get().unwrap();
+        DocumentOperation::find(&mut conn, &self.document_id)
+    }
+
+    #[graphql(description = "The ID of the matching block, null when the document name or description matched")]
+    fn block_id(&self) -> Option<&str> {
+        self.block_id.as_deref()
+    }
+
+    #[graphql(description = "The content of the matching block")]
+    fn block(&self, context: &Context) -> Option<BlockContent> {
+        let mut conn = context.db_pool.get().unwrap();
+        BlockOperation::content(&mut conn, self.block_id.as_deref()?)
+    }
+
+    #[graphql(description = "Relevance of the hit, higher is better")]
+    fn rank(&self) -> f64 {
+        self.rank as f64
+    }
+
+    #[graphql(description = "Text around the match with matches wrapped in <mark> tags")]
+    fn snippet(&self) -> &str {
+        &self.snippet
+    }
+}
+
+pub struct Search {
+    pub query: String,
+    pub hits: Vec<SearchHit>,
+}
+
+#[graphql_object(
+    name = "Search",
+    description = "Ranked full text search results",
+    context = Context
+)]
+impl Search {
+    #[graphql(description = "The query that was searched for")]
+    fn query(&self) -> &str {
+        &self.query
+    }
+
+    #[graphql(description = "Matches ordered by rank")]
+    fn hits(&self) -> &[SearchHit] {
+        &self.hits
+    }
+}
+
+validation_result!(SearchResult, Search);
+
+pub struct SearchOperation;
+
+impl SearchOperation {
+    pub fn search(conn: &mut DBPooledConnection, user_id: &str, query: &str, repository_id: Option<&str>) -> SearchResult {
+        let repository_ids = Permission::readable_repository_ids(conn, user_id);
+
+        let hits = sql_query(SEARCH_QUERY)
+            .bind::<Text, _>(query)
+            .bind::<Array<Bpchar>, _>(repository_ids)
+            .bind::<Nullable<Bpchar>, _>(repository_id)
+            .bind::<BigInt, _>(MAX_HITS)
+            .load::<SearchHit>(conn);
+
+        match hits {
+            Ok(hits) => SearchResult::Search(Search { query: query.into(), hits }),
+            Err(_) => SearchResult::server(),
+        }
+    }
+}
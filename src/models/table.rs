@@ -0,0 +1,420 @@
+use std::collections::HashSet;
+
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use juniper::{GraphQLEnum, GraphQLInputObject, GraphQLObject};
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+
+use crate::{schemas::root::Context, db::DBPooledConnection, schema::table_blocks, helpers::errors::{FieldErrors, FieldError}};
+
+use super::block::{Block, BlockOperation, BlockResult, TableBlock};
+
+pub const MAX_COLUMNS: usize = 50;
+pub const MAX_ROWS: usize = 1000;
+pub const MAX_OPTIONS: usize = 100;
+pub const MAX_NAME_LENGTH: usize = 256;
+pub const MAX_CELL_LENGTH: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, GraphQLEnum, Serialize, Deserialize)]
+pub enum ColumnType {
+    Text,
+    Number,
+    Date,
+    Checkbox,
+    Select,
+}
+
+#[derive(Debug, Clone, PartialEq, GraphQLObject, Serialize, Deserialize)]
+#[graphql(Context = Context)]
+pub struct TableColumn {
+    pub id: String,
+    #[graphql(description = "Shown in the header row")]
+    pub name: String,
+    #[graphql(name = "type")]
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+    #[graphql(description = "Values the cells of a select column can have")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, GraphQLObject, Serialize, Deserialize)]
+#[graphql(Context = Context)]
+pub struct TableCell {
+    pub column_id: String,
+    #[graphql(description = "Numbers as decimals, dates as YYYY-MM-DD and checkboxes as true or false")]
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, GraphQLObject, Serialize, Deserialize)]
+#[graphql(Context = Context)]
+pub struct TableRow {
+    pub id: String,
+    pub cells: Vec<TableCell>,
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(Context = Context)]
+pub struct TableColumnInput {
+    pub name: String,
+    #[graphql(name = "type")]
+    pub column_type: ColumnType,
+    pub options: Option<Vec<String>>,
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(Context = Context)]
+pub struct TableCellInput {
+    pub column_id: String,
+    pub value: Option<String>,
+}
+
+impl TableColumn {
+    pub fn new(input: TableColumnInput) -> TableColumn {
+        TableColumn {
+            id: nanoid!(),
+            name: input.name.trim().into(),
+            column_type: input.column_type,
+            options: input.options
+                .unwrap_or_default()
+                .iter()
+                .map(|option| option.trim().to_string())
+                .collect(),
+        }
+    }
+
+    /// Checks `value` fits the column and returns it in its canonical form,
+    /// blank values are stored as null.
+    pub fn cell_value(&self, value: Option<&str>) -> Result<Option<String>, String> {
+        let Some(value) = value.map(str::trim).filter(|value| !value.is_empty()) else {
+            return Ok(None);
+        };
+
+        let value = match self.column_type {
+            ColumnType::Text if value.len() > MAX_CELL_LENGTH => {
+                return Err(format!("can't have more than {} characters", MAX_CELL_LENGTH));
+            },
+            ColumnType::Text => value.to_string(),
+            ColumnType::Number => match value.parse::<f64>() {
+                Ok(number) if number.is_finite() => number.to_string(),
+                _ => return Err(format!("{} isn't a number", value)),
+            },
+            ColumnType::Date => match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+                Ok(date) => date.format("%Y-%m-%d").to_string(),
+                Err(_) => return Err(format!("{} isn't a date in the YYYY-MM-DD format", value)),
+            },
+            ColumnType::Checkbox => match value.to_lowercase().as_str() {
+                "true" => String::from("true"),
+                "false" => String::from("false"),
+                _ => return Err(String::from("checkboxes are true or false")),
+            },
+            ColumnType::Select if self.options.iter().any(|option| option == value) => value.to_string(),
+            ColumnType::Select => return Err(format!("{} isn't one of the options", value)),
+        };
+
+        Ok(Some(value))
+    }
+}
+
+impl TableRow {
+    pub fn new(cells: Vec<TableCell>) -> TableRow {
+        TableRow { id: nanoid!(), cells }
+    }
+
+    pub fn value(&self, column_id: &str) -> Option<&str> {
+        self.cells
+            .iter()
+            .find(|cell| cell.column_id == column_id)
+            .and_then(|cell| cell.value.as_deref())
+    }
+
+    /// Cells in the order of `columns`, null for the ones without a value.
+    pub fn ordered_cells(&self, columns: &[TableColumn]) -> Vec<TableCell> {
+        columns
+            .iter()
+            .map(|column| TableCell {
+                column_id: column.id.clone(),
+                value: self.value(&column.id).map(String::from),
+            })
+            .collect()
+    }
+
+    fn set(&mut self, column_id: &str, value: Option<String>) {
+        match self.cells.iter_mut().find(|cell| cell.column_id == column_id) {
+            Some(cell) if value.is_some() => cell.value = value,
+            Some(_) => self.cells.retain(|cell| cell.column_id != column_id),
+            None if value.is_some() => self.cells.push(TableCell { column_id: column_id.into(), value }),
+            None => {},
+        }
+    }
+}
+
+pub fn validate_column(field: &str, column: &TableColumn, errors: &mut FieldErrors) {
+    if column.name.len() > MAX_NAME_LENGTH {
+        errors.push(FieldError::new(field, &format!("names can't have more than {} characters", MAX_NAME_LENGTH)));
+    }
+
+    match column.column_type {
+        ColumnType::Select if column.options.is_empty() => {
+            errors.push(FieldError::new(field, "select columns need at least one option"));
+        },
+        ColumnType::Select => {
+            let unique = column.options.iter().collect::<HashSet<_>>();
+
+            if column.options.len() > MAX_OPTIONS {
+                errors.push(FieldError::new(field, &format!("can't have more than {} options", MAX_OPTIONS)));
+            }
+
+            if unique.len() != column.options.len() || unique.iter().any(|option| option.is_empty() || option.len() > MAX_NAME_LENGTH) {
+                errors.push(FieldError::new(field, &format!("options have to be unique and between 1 and {} characters", MAX_NAME_LENGTH)));
+            }
+        },
+        _ if !column.options.is_empty() => {
+            errors.push(FieldError::new(field, "only select columns can have options"));
+        },
+        _ => {},
+    }
+}
+
+/// Checks a whole table, used for every write so nothing invalid is ever
+/// stored in the json columns.
+pub fn validate(columns: &[TableColumn], rows: &[TableRow], errors: &mut FieldErrors) {
+    if columns.len() > MAX_COLUMNS {
+        errors.push(FieldError::new("columns", &format!("can't have more than {} columns", MAX_COLUMNS)));
+    }
+
+    if rows.len() > MAX_ROWS {
+        errors.push(FieldError::new("rows", &format!("can't have more than {} rows", MAX_ROWS)));
+    }
+
+    for column in columns {
+        validate_column("columns", column, errors);
+    }
+
+    for row in rows {
+        for cell in &row.cells {
+            let Some(column) = columns.iter().find(|column| column.id == cell.column_id) else {
+                errors.push(FieldError::new("rows", &format!("column {} doesn't exist", cell.column_id)));
+                continue;
+            };
+
+            if let Err(message) = column.cell_value(cell.value.as_deref()) {
+                errors.push(FieldError::new("rows", &message));
+            }
+        }
+    }
+}
+
+/// The values of an imported column decide its type, so a column of
+/// numbers becomes a number column. Checkboxes are written as `[x]` and
+/// `[ ]`.
+fn infer_column(name: &str, values: &[&str]) -> TableColumn {
+    let values = values
+        .iter()
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>();
+
+    let all = |column_type: ColumnType| {
+        let column = TableColumn { id: String::new(), name: String::new(), column_type, options: Vec::new() };
+        values.iter().all(|value| column.cell_value(Some(*value)).is_ok())
+    };
+
+    let column_type = if values.is_empty() {
+        ColumnType::Text
+    } else if values.iter().all(|value| checkbox_from_markdown(value).is_some()) {
+        ColumnType::Checkbox
+    } else if all(ColumnType::Number) {
+        ColumnType::Number
+    } else if all(ColumnType::Date) {
+        ColumnType::Date
+    } else {
+        ColumnType::Text
+    };
+
+    TableColumn { id: nanoid!(), name: name.trim().into(), column_type, options: Vec::new() }
+}
+
+fn checkbox_from_markdown(value: &str) -> Option<&'static str> {
+    match value {
+        "[x]" | "[X]" => Some("true"),
+        "[ ]" => Some("false"),
+        _ => None,
+    }
+}
+
+/// Builds a table from the text of its header cells and rows, as found in
+/// an imported markdown table.
+pub fn from_markdown(head: &[String], body: &[Vec<String>]) -> (Vec<TableColumn>, Vec<TableRow>) {
+    let columns = head
+        .iter()
+        .enumerate()
+        .map(|(index, name)| {
+            let values = body
+                .iter()
+                .filter_map(|cells| cells.get(index).map(String::as_str))
+                .collect::<Vec<_>>();
+
+            infer_column(name, &values)
+        })
+        .collect::<Vec<_>>();
+
+    let rows = body
+        .iter()
+        .map(|cells| {
+            let mut row = TableRow::new(Vec::new());
+
+            for (column, text) in columns.iter().zip(cells) {
+                let value = match column.column_type {
+                    ColumnType::Checkbox => checkbox_from_markdown(text.trim()).map(String::from),
+                    // values that don't fit are kept so validation turns
+                    // the table down
+                    _ => column.cell_value(Some(text)).unwrap_or_else(|_| Some(text.trim().to_string())),
+                };
+
+                row.set(&column.id, value);
+            }
+
+            row
+        })
+        .collect();
+
+    (columns, rows)
+}
+
+/// Why a change to a table was turned down.
+enum TableError {
+    Invalid(FieldErrors),
+    NotFound(&'static str),
+    Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for TableError {
+    fn from(err: diesel::result::Error) -> TableError {
+        TableError::Database(err)
+    }
+}
+
+fn invalid(field: &str, message: &str) -> TableError {
+    let mut errors = FieldErrors::new();
+    errors.push(FieldError::new(field, message));
+
+    TableError::Invalid(errors)
+}
+
+pub struct TableOperation;
+
+impl TableOperation {
+    /// Runs `change` on the table with its row locked, then validates and
+    /// stores the result.
+    fn edit(conn: &mut DBPooledConnection, user_id: &str, block_id: &str, change: impl FnOnce(&mut Vec<TableColumn>, &mut Vec<TableRow>) -> Result<(), TableError>) -> BlockResult {
+        let result = conn.transaction(|conn| {
+            let table_block = table_blocks::table
+                .filter(table_blocks::block_id.eq(block_id))
+                .for_update()
+                .get_result::<TableBlock>(conn)?;
+
+            let mut columns = table_block.column_list();
+            let mut rows = table_block.row_list();
+
+            change(&mut columns, &mut rows)?;
+
+            let mut errors = FieldErrors::new();
+            validate(&columns, &rows, &mut errors);
+
+            if !errors.empty() {
+                return Err(TableError::Invalid(errors));
+            }
+
+            diesel::update(table_blocks::table)
+                .filter(table_blocks::block_id.eq(block_id))
+                .set((
+                    table_blocks::table_columns.eq(serde_json::to_value(&columns).unwrap_or_default()),
+                    table_blocks::table_rows.eq(serde_json::to_value(&rows).unwrap_or_default()),
+                ))
+                .execute(conn)?;
+
+            Ok::<Block, TableError>(BlockOperation::touch(conn, block_id, user_id)?)
+        });
+
+        match result {
+            Ok(block) => BlockResult::Block(block),
+            Err(TableError::Invalid(errors)) => BlockResult::FieldErrors(errors),
+            Err(TableError::NotFound(message)) => BlockResult::not_found(message),
+            Err(TableError::Database(diesel::result::Error::NotFound)) => BlockResult::not_found("block not found"),
+            Err(TableError::Database(_)) => BlockResult::server(),
+        }
+    }
+
+    /// Adds a column at `position`, or at the end when there is none.
+    pub fn add_column(conn: &mut DBPooledConnection, user_id: &str, block_id: &str, column: TableColumn, position: Option<i32>) -> BlockResult {
+        Self::edit(conn, user_id, block_id, |columns, _| {
+            let position = position.map_or(columns.len(), |position| (position.max(0) as usize).min(columns.len()));
+            columns.insert(position, column);
+
+            Ok(())
+        })
+    }
+
+    pub fn reorder_columns(conn: &mut DBPooledConnection, user_id: &str, block_id: &str, column_ids: &[String]) -> BlockResult {
+        Self::edit(conn, user_id, block_id, |columns, _| {
+            let mut reordered = Vec::with_capacity(columns.len());
+
+            for column_id in column_ids {
+                match columns.iter().position(|column| &column.id == column_id) {
+                    Some(index) => reordered.push(columns.remove(index)),
+                    None => return Err(invalid("columnIds", "must list every column of the table once")),
+                }
+            }
+
+            if !columns.is_empty() {
+                return Err(invalid("columnIds", "must list every column of the table once"));
+            }
+
+            *columns = reordered;
+
+            Ok(())
+        })
+    }
+
+    /// Adds a row at `position`, or at the end when there is none.
+    pub fn add_row(conn: &mut DBPooledConnection, user_id: &str, block_id: &str, cells: Vec<TableCellInput>, position: Option<i32>) -> BlockResult {
+        Self::edit(conn, user_id, block_id, |columns, rows| {
+            let mut row = TableRow::new(Vec::new());
+
+            for cell in cells {
+                let Some(column) = columns.iter().find(|column| column.id == cell.column_id) else {
+                    return Err(invalid("cells", &format!("column {} doesn't exist", cell.column_id)));
+                };
+
+                let value = column.cell_value(cell.value.as_deref()).map_err(|message| invalid("cells", &message))?;
+                row.set(&column.id, value);
+            }
+
+            let position = position.map_or(rows.len(), |position| (position.max(0) as usize).min(rows.len()));
+            rows.insert(position, row);
+
+            Ok(())
+        })
+    }
+
+    pub fn update_cell(conn: &mut DBPooledConnection, user_id: &str, block_id: &str, row_id: &str, column_id: &str, value: Option<&str>) -> BlockResult {
+        Self::edit(conn, user_id, block_id, |columns, rows| {
+            let column = columns
+                .iter()
+                .find(|column| column.id == column_id)
+                .ok_or(TableError::NotFound("column not found"))?;
+
+            let row = rows
+                .iter_mut()
+                .find(|row| row.id == row_id)
+                .ok_or(TableError::NotFound("row not found"))?;
+
+            let value = column.cell_value(value).map_err(|message| invalid("value", &message))?;
+            row.set(column_id, value);
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,451 @@
+use std::io;
+
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Insertable, prelude::*, dsl::{sql, now, IntervalDsl}, sql_types::BigInt};
+use juniper::graphql_object;
+use nanoid::nanoid;
+
+use crate::{schemas::root::Context, validation_result, schema::{uploads, upload_variants, users, image_blocks}, db::DBPooledConnection, helpers::{blob_store::{self, BlobStore}, image::{self, ImageFormat, ProcessedImage}}};
+
+pub const MAX_UPLOAD_SIZE: usize = 10 * 1024 * 1024;
+pub const UPLOAD_QUOTA: i64 = 100 * 1024 * 1024;
+pub const CLAIM_DURATION: i32 = 10;     // minutes
+
+#[derive(Queryable)]
+pub struct Upload {
+    pub id: String,
+    pub user_id: String,
+    pub key: String,
+    pub content_type: String,
+    pub size: i64,
+    pub created_at: NaiveDateTime,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub processed_at: Option<NaiveDateTime>,
+    pub claimed_at: Option<NaiveDateTime>,
+}
+
+#[graphql_object(
+    name = "Upload",
+    description = "An image uploaded to the server",
+    context = Context
+)]
+impl Upload {
+    #[graphql(description = "The uploads ID in base64 format")]
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[graphql(description = "Server managed url to use for image blocks")]
+    fn url(&self) -> String {
+        self.url()
+    }
+
+    #[graphql(description = "Content type sniffed from the uploaded bytes")]
+    fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    #[graphql(description = "Size in bytes")]
+    fn size(&self) -> i32 {
+        self.size as i32
+    }
+
+    #[graphql(description = "Width in pixels, null until processed or when the image couldn't be decoded")]
+    fn width(&self) -> Option<i32> {
+        self.width
+    }
+
+    #[graphql(description = "Height in pixels, null until processed or when the image couldn't be decoded")]
+    fn height(&self) -> Option<i32> {
+        self.height
+    }
+
+    #[graphql(description = "Whether the image has been processed and can be served")]
+    fn ready(&self) -> bool {
+        self.ready()
+    }
+
+    #[graphql(description = "DateTime for when the image was uploaded")]
+    fn created_at(&self) -> &NaiveDateTime {
+        &self.created_at
+    }
+}
+
+impl Upload {
+    pub fn url(&self) -> String {
+        blob_store::url(&self.key)
+    }
+
+    /// Processed without errors, so its metadata is gone and it is safe to
+    /// serve.
+    pub fn ready(&self) -> bool {
+        self.processed_at.is_some() && self.width.is_some()
+    }
+}
+
+#[derive(Queryable)]
+pub struct UploadVariant {
+    pub key: String,
+    pub upload_id: String,
+    pub width: i32,
+    pub height: i32,
+    pub size: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = upload_variants)]
+pub struct NewUploadVariant {
+    pub key: String,
+    pub upload_id: String,
+    pub width: i32,
+    pub height: i32,
+    pub size: i64,
+}
+
+/// What image blocks showing an upload record about it.
+pub struct ImageMetadata {
+    pub width: i32,
+    pub height: i32,
+    pub format: String,
+    pub srcset: Option<String>,
+}
+
+validation_result!(UploadResult, Upload);
+
+#[derive(Insertable)]
+#[diesel(table_name = uploads)]
+pub struct NewUpload {
+    pub id: String,
+    pub user_id: String,
+    pub key: String,
+    pub content_type: String,
+    pub size: i64,
+}
+
+impl NewUpload {
+    pub fn new(user_id: &str, format: &ImageFormat, size: usize) -> NewUpload {
+        let id = nanoid!();
+
+        NewUpload {
+            key: format!("{}.{}", id, format.extension),
+            id,
+            user_id: user_id.into(),
+            content_type: format.content_type.into(),
+            size: size as i64,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum UploadError {
+    TooLarge,
+    UnsupportedType,
+    QuotaExceeded,
+    Storage(io::Error),
+    Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for UploadError {
+    fn from(err: diesel::result::Error) -> UploadError {
+        UploadError::Database(err)
+    }
+}
+
+fn srcset(variants: &[UploadVariant]) -> Option<String> {
+    if variants.is_empty() {
+        return None;
+    }
+
+    let candidates = variants
+        .iter()
+        .map(|variant| format!("{} {}w", blob_store::url(&variant.key), variant.width))
+        .collect::<Vec<_>>();
+
+    Some(candidates.join(", "))
+}
+
+pub struct UploadOperation;
+
+impl UploadOperation {
+    /// Stores an image for the user. The users row is locked while the
+    /// quota is checked so concurrent uploads can't both squeeze in.
+    pub fn create(conn: &mut DBPooledConnection, store: &dyn BlobStore, user_id: &str, data: &[u8]) -> Result<Upload, UploadError> {
+        if data.len() > MAX_UPLOAD_SIZE {
+            return Err(UploadError::TooLarge);
+        }
+
+        let format = image::sniff(data).ok_or(UploadError::UnsupportedType)?;
+
+        conn.transaction(|conn| {
+            users::table
+                .filter(users::id.eq(user_id))
+                .select(users::id)
+                .for_update()
+                .get_result::<String>(conn)?;
+
+            if Self::storage_used(conn, user_id)? + data.len() as i64 > UPLOAD_QUOTA {
+                return Err(UploadError::QuotaExceeded);
+            }
+
+            let upload = diesel::insert_into(uploads::table)
+                .values(&NewUpload::new(user_id, &format, data.len()))
+                .get_result::<Upload>(conn)?;
+
+            store
+                .put(&upload.key, &upload.content_type, data)
+                .map_err(UploadError::Storage)?;
+
+            Ok(upload)
+        })
+    }
+
+    pub fn find(conn: &mut DBPooledConnection, id: &str) -> Option<Upload> {
+        uploads::table
+            .filter(uploads::id.eq(id))
+            .get_result::<Upload>(conn)
+            .ok()
+    }
+
+    pub fn find_by_key(conn: &mut DBPooledConnection, key: &str) -> Option<Upload> {
+        uploads::table
+            .filter(uploads::key.eq(key))
+            .get_result::<Upload>(conn)
+            .ok()
+    }
+
+    /// Content type of a processed upload or variant stored under `key`.
+    pub fn servable_content_type(conn: &mut DBPooledConnection, key: &str) -> Option<String> {
+        if let Some(upload) = Self::find_by_key(conn, key) {
+            return upload.ready().then_some(upload.content_type);
+        }
+
+        upload_variants::table
+            .filter(upload_variants::key.eq(key))
+            .select(upload_variants::key)
+            .get_result::<String>(conn)
+            .ok()
+            .map(|_| image::WEBP.content_type.to_string())
+    }
+
+    pub fn variants(conn: &mut DBPooledConnection, upload_id: &str) -> QueryResult<Vec<UploadVariant>> {
+        upload_variants::table
+            .filter(upload_variants::upload_id.eq(upload_id))
+            .order(upload_variants::width.asc())
+            .get_results::<UploadVariant>(conn)
+    }
+
+    /// Metadata for an image block pointing at `url`, `None` unless it is a
+    /// processed upload.
+    pub fn image_metadata(conn: &mut DBPooledConnection, url: &str) -> QueryResult<Option<ImageMetadata>> {
+        let Some(upload) = blob_store::key_from_url(url).and_then(|key| Self::find_by_key(conn, key)) else {
+            return Ok(None);
+        };
+
+        let (Some(width), Some(height), Some(format)) = (upload.width, upload.height, image::format_from_content_type(&upload.content_type)) else {
+            return Ok(None);
+        };
+
+        Ok(Some(ImageMetadata {
+            width,
+            height,
+            format: format.name.into(),
+            srcset: srcset(&Self::variants(conn, &upload.id)?),
+        }))
+    }
+
+    /// Processes the oldest unprocessed upload, returns false when there
+    /// was nothing to do. The upload is claimed in a short transaction and
+    /// processed outside of it, so several workers can run at once and
+    /// nothing is locked while images are decoded and stored.
+    pub fn process_next(conn: &mut DBPooledConnection, store: &dyn BlobStore) -> Result<bool, UploadError> {
+        let Some(upload) = Self::claim_next(conn)? else {
+            return Ok(false);
+        };
+
+        let data = store
+            .get(&upload.key)
+            .map_err(UploadError::Storage)?
+            .unwrap_or_default();
+
+        let processed = match image::format_from_content_type(&upload.content_type) {
+            Some(format) => image::process(&data, &format).map_err(|err| err.to_string()),
+            None => Err(format!("unknown content type {}", upload.content_type)),
+        };
+
+        match processed {
+            Ok(processed) => Self::store_processed(conn, store, &upload, processed)?,
+            Err(err) => {
+                log::warn!("failed to process upload {}: {}", upload.id, err);
+
+                diesel::update(uploads::table)
+                    .filter(uploads::id.eq(&upload.id))
+                    .set(uploads::processed_at.eq(now))
+                    .execute(conn)?;
+            },
+        }
+
+        Ok(true)
+    }
+
+    /// The oldest unprocessed upload that isn't claimed by another worker,
+    /// or was claimed longer than `CLAIM_DURATION` ago.
+    fn claim_next(conn: &mut DBPooledConnection) -> QueryResult<Option<Upload>> {
+        conn.transaction(|conn| {
+            let upload = uploads::table
+                .filter(uploads::processed_at.is_null())
+                .filter(uploads::claimed_at.is_null().or(uploads::claimed_at.lt((now - CLAIM_DURATION.minutes()).nullable())))
+                .order(uploads::created_at.asc())
+                .for_update()
+                .skip_locked()
+                .first::<Upload>(conn)
+                .optional()?;
+
+            let Some(upload) = upload else {
+                return Ok(None);
+            };
+
+            diesel::update(uploads::table)
+                .filter(uploads::id.eq(&upload.id))
+                .set(uploads::claimed_at.eq(now))
+                .execute(conn)?;
+
+            Ok(Some(upload))
+        })
+    }
+
+    fn store_processed(conn: &mut DBPooledConnection, store: &dyn BlobStore, upload: &Upload, processed: ProcessedImage) -> Result<(), UploadError> {
+        let mut size = upload.size;
+
+        if let Some(original) = &processed.original {
+            store
+                .put(&upload.key, &upload.content_type, original)
+                .map_err(UploadError::Storage)?;
+
+            size = original.len() as i64;
+        }
+
+        let mut new_variants = Vec::new();
+
+        for variant in &processed.variants {
+            let new_variant = NewUploadVariant {
+                key: format!("{}-{}.{}", upload.id, variant.width, image::WEBP.extension),
+                upload_id: upload.id.clone(),
+                width: variant.width as i32,
+                height: variant.height as i32,
+                size: variant.data.len() as i64,
+            };
+
+            store
+                .put(&new_variant.key, image::WEBP.content_type, &variant.data)
+                .map_err(UploadError::Storage)?;
+
+            new_variants.push(new_variant);
+        }
+
+        let recorded = conn.transaction(|conn| {
+            // the upload may have been deleted meanwhile, or processed by a
+            // worker that took over an expired claim
+            let processed_at = uploads::table
+                .filter(uploads::id.eq(&upload.id))
+                .select(uploads::processed_at)
+                .for_update()
+                .get_result::<Option<NaiveDateTime>>(conn)
+                .optional()?;
+
+            match processed_at {
+                None => return Ok(false),
+                Some(Some(_)) => return Ok(true),
+                Some(None) => (),
+            }
+
+            diesel::insert_into(upload_variants::table)
+                .values(&new_variants)
+                .execute(conn)?;
+
+            diesel::update(uploads::table)
+                .filter(uploads::id.eq(&upload.id))
+                .set((
+                    uploads::size.eq(size),
+                    uploads::width.eq(processed.width as i32),
+                    uploads::height.eq(processed.height as i32),
+                    uploads::processed_at.eq(now),
+                ))
+                .execute(conn)?;
+
+            // blocks may have been pointed at the upload before it was ready
+            if let Some(metadata) = Self::image_metadata(conn, &upload.url())? {
+                diesel::update(image_blocks::table)
+                    .filter(image_blocks::url.eq(upload.url()))
+                    .set((
+                        image_blocks::width.eq(metadata.width),
+                        image_blocks::height.eq(metadata.height),
+                        image_blocks::format.eq(metadata.format),
+                        image_blocks::srcset.eq(metadata.srcset),
+                    ))
+                    .execute(conn)?;
+            }
+
+            Ok::<_, UploadError>(true)
+        })?;
+
+        // what was stored for a deleted upload isn't removed by anyone else
+        if !recorded {
+            for key in new_variants.iter().map(|variant| &variant.key).chain([&upload.key]) {
+                store
+                    .delete(key)
+                    .map_err(UploadError::Storage)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn find_by_user(conn: &mut DBPooledConnection, user_id: &str) -> Vec<Upload> {
+        uploads::table
+            .filter(uploads::user_id.eq(user_id))
+            .order(uploads::created_at.desc())
+            .get_results::<Upload>(conn)
+            .unwrap_or_default()
+    }
+
+    /// Total size in bytes of everything the user has uploaded, with the
+    /// resized variants of their images.
+    pub fn storage_used(conn: &mut DBPooledConnection, user_id: &str) -> QueryResult<i64> {
+        let originals = uploads::table
+            .filter(uploads::user_id.eq(user_id))
+            .select(sql::<BigInt>("coalesce(sum(uploads.size), 0)::bigint"))
+            .get_result::<i64>(conn)?;
+
+        let variants = upload_variants::table
+            .inner_join(uploads::table)
+            .filter(uploads::user_id.eq(user_id))
+            .select(sql::<BigInt>("coalesce(sum(upload_variants.size), 0)::bigint"))
+            .get_result::<i64>(conn)?;
+
+        Ok(originals + variants)
+    }
+
+    pub fn delete(conn: &mut DBPooledConnection, store: &dyn BlobStore, id: &str) -> UploadResult {
+        let result = conn.transaction(|conn| {
+            let variants = Self::variants(conn, id)?;
+
+            let upload = diesel::delete(uploads::table.filter(uploads::id.eq(id)))
+                .get_result::<Upload>(conn)?;
+
+            for key in variants.iter().map(|variant| &variant.key).chain([&upload.key]) {
+                store
+                    .delete(key)
+                    .map_err(UploadError::Storage)?;
+            }
+
+            Ok::<_, UploadError>(upload)
+        });
+
+        match result {
+            Ok(upload) => UploadResult::Upload(upload),
+            Err(UploadError::Database(diesel::result::Error::NotFound)) => UploadResult::not_found("upload not found"),
+            Err(_) => UploadResult::server(),
+        }
+    }
+}
@@ -4,9 +4,9 @@ use diesel::{Queryable, Insertable, prelude::*, result::{Error::{NotFound, Datab
 use juniper::graphql_object;
 use nanoid::nanoid;
 
-use crate::{schema::users, db::DBPooledConnection, validation_result, schemas::root::Context, models::repository::Repository};
+use crate::{schema::users, db::DBPooledConnection, validation_result, schemas::root::Context, models::repository::Repository, helpers::permission::Permission};
 
-use super::repository::RepositoryOperation;
+use super::{repository::RepositoryOperation, upload::{Upload, UploadOperation, UPLOAD_QUOTA}};
 
 #[derive(Queryable)]
 pub struct User {
@@ -43,11 +43,69 @@ impl User {
         &self.updated_at
     }
 
-    #[graphql(description = "The repositories created by the user")]
+    #[graphql(description = "The repositories created by the user that the viewer can read")]
     fn repositories(&self, context: &Context) -> Vec<Repository> {
         let mut conn = context.db_pool.get().unwrap();
-        RepositoryOperation::find_by_user(&mut conn, &self.id)
+        let repository_list = RepositoryOperation::find_by_user(&mut conn, &self.id);
+
+        readable_by_viewer(context, &mut conn, repository_list)
     }
+
+    #[graphql(description = "The repositories others shared with the user that the viewer can read")]
+    fn shared_repositories(&self, context: &Context) -> Vec<Repository> {
+        let mut conn = context.db_pool.get().unwrap();
+        let repository_list = RepositoryOperation::find_shared_with_user(&mut conn, &self.id);
+
+        readable_by_viewer(context, &mut conn, repository_list)
+    }
+
+    #[graphql(description = "Images uploaded by the user, newest first, empty unless the user is the viewer")]
+    fn uploads(&self, context: &Context) -> Vec<Upload> {
+        if !self.is_viewer(context) {
+            return Vec::new();
+        }
+
+        let mut conn = context.db_pool.get().unwrap();
+        UploadOperation::find_by_user(&mut conn, &self.id)
+    }
+
+    #[graphql(description = "Bytes used by the users uploads, null unless the user is the viewer")]
+    fn storage_used(&self, context: &Context) -> Option<i32> {
+        if !self.is_viewer(context) {
+            return None;
+        }
+
+        let mut conn = context.db_pool.get().unwrap();
+        Some(UploadOperation::storage_used(&mut conn, &self.id).unwrap_or_default() as i32)
+    }
+
+    #[graphql(description = "Bytes the user is allowed to upload in total")]
+    fn storage_quota(&self) -> i32 {
+        UPLOAD_QUOTA as i32
+    }
+}
+
+impl User {
+    /// Whether the user is the one signed in, who is the only one to see
+    /// their uploads.
+    fn is_viewer(&self, context: &Context) -> bool {
+        context.authed_user().is_some_and(|viewer| viewer.id == self.id)
+    }
+}
+
+/// Leaves out the repositories the signed in user isn't a member of, as
+/// users can be reached from the members of other repositories.
+fn readable_by_viewer(context: &Context, conn: &mut DBPooledConnection, repository_list: Vec<Repository>) -> Vec<Repository> {
+    let Some(viewer) = context.authed_user() else {
+        return Vec::new();
+    };
+
+    let repository_ids = Permission::readable_repository_ids(conn, &viewer.id);
+
+    repository_list
+        .into_iter()
+        .filter(|repository| repository_ids.contains(&repository.id))
+        .collect()
 }
 
 validation_result!(UserResult, User);
@@ -111,7 +169,7 @@ impl UserOperation {
                     return UserResult::User(user);
                 }
 
-                return UserResult::unauthorized("auth failed");
+                UserResult::unauthorized("auth failed")
             },
             Err(NotFound) => UserResult::unauthorized("auth failed"),
             Err(_) => UserResult::server(),
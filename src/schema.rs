@@ -1,9 +1,29 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "callout_variant"))]
+    pub struct CalloutVariant;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "list_style"))]
+    pub struct ListStyle;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "member_role"))]
+    pub struct MemberRole;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "property_type"))]
+    pub struct PropertyType;
+
     #[derive(diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "tag"))]
     pub struct Tag;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "view_layout"))]
+    pub struct ViewLayout;
 }
 
 diesel::table! {
@@ -12,6 +32,90 @@ diesel::table! {
         document_id -> Bpchar,
         line_number -> Int4,
         created_at -> Timestamp,
+        last_edited_by -> Nullable<Bpchar>,
+        last_edited_at -> Timestamp,
+        parent_id -> Nullable<Bpchar>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::CalloutVariant;
+
+    callout_blocks (block_id) {
+        block_id -> Bpchar,
+        variant -> CalloutVariant,
+        content -> Nullable<Text>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    code_blocks (block_id) {
+        block_id -> Bpchar,
+        language -> Nullable<Text>,
+        content -> Nullable<Text>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::PropertyType;
+
+    database_properties (id) {
+        id -> Bpchar,
+        database_id -> Bpchar,
+        name -> Text,
+        property_type -> PropertyType,
+        options -> Jsonb,
+        position -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ViewLayout;
+
+    database_views (id) {
+        id -> Bpchar,
+        database_id -> Bpchar,
+        name -> Text,
+        layout -> ViewLayout,
+        property_id -> Nullable<Bpchar>,
+        filters -> Jsonb,
+        sorts -> Jsonb,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    divider_blocks (block_id) {
+        block_id -> Bpchar,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    document_links (block_id, label) {
+        block_id -> Bpchar,
+        source_document_id -> Bpchar,
+        target_document_id -> Nullable<Bpchar>,
+        label -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    document_revisions (id) {
+        id -> Bpchar,
+        document_id -> Bpchar,
+        user_id -> Bpchar,
+        blocks -> Jsonb,
+        created_at -> Timestamp,
     }
 }
 
@@ -24,6 +128,18 @@ diesel::table! {
         description -> Nullable<Text>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        is_database -> Bool,
+        database_id -> Nullable<Bpchar>,
+        parent_id -> Nullable<Bpchar>,
+        position -> Int4,
+    }
+}
+
+diesel::table! {
+    embed_blocks (block_id) {
+        block_id -> Bpchar,
+        source_block_id -> Nullable<Bpchar>,
+        updated_at -> Timestamp,
     }
 }
 
@@ -32,6 +148,54 @@ diesel::table! {
         block_id -> Bpchar,
         url -> Nullable<Text>,
         updated_at -> Timestamp,
+        width -> Nullable<Int4>,
+        height -> Nullable<Int4>,
+        format -> Nullable<Text>,
+        srcset -> Nullable<Text>,
+        alt -> Nullable<Text>,
+        caption -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ListStyle;
+
+    list_item_blocks (block_id) {
+        block_id -> Bpchar,
+        style -> ListStyle,
+        indent -> Int4,
+        content -> Nullable<Text>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    math_blocks (block_id) {
+        block_id -> Bpchar,
+        content -> Nullable<Text>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    property_values (document_id, property_id) {
+        document_id -> Bpchar,
+        property_id -> Bpchar,
+        text_value -> Nullable<Text>,
+        option_values -> Nullable<Array<Nullable<Text>>>,
+        number_value -> Nullable<Float8>,
+        date_value -> Nullable<Date>,
+        user_id -> Nullable<Bpchar>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    quote_blocks (block_id) {
+        block_id -> Bpchar,
+        content -> Nullable<Text>,
+        updated_at -> Timestamp,
     }
 }
 
@@ -44,6 +208,46 @@ diesel::table! {
         description -> Nullable<Text>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        published_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::MemberRole;
+
+    repository_invitations (id) {
+        id -> Bpchar,
+        repository_id -> Bpchar,
+        email -> Text,
+        role -> MemberRole,
+        token_hash -> Bpchar,
+        invited_by -> Bpchar,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::MemberRole;
+
+    repository_members (repository_id, user_id) {
+        repository_id -> Bpchar,
+        user_id -> Bpchar,
+        role -> MemberRole,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    table_blocks (block_id) {
+        block_id -> Bpchar,
+        table_columns -> Jsonb,
+        table_rows -> Jsonb,
+        updated_at -> Timestamp,
     }
 }
 
@@ -56,6 +260,50 @@ diesel::table! {
         tag -> Tag,
         content -> Nullable<Text>,
         updated_at -> Timestamp,
+        rich_text -> Nullable<Jsonb>,
+    }
+}
+
+diesel::table! {
+    todo_blocks (block_id) {
+        block_id -> Bpchar,
+        checked -> Bool,
+        indent -> Int4,
+        content -> Nullable<Text>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    toggle_blocks (block_id) {
+        block_id -> Bpchar,
+        content -> Nullable<Text>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    upload_variants (key) {
+        key -> Text,
+        upload_id -> Bpchar,
+        width -> Int4,
+        height -> Int4,
+        size -> Int8,
+    }
+}
+
+diesel::table! {
+    uploads (id) {
+        id -> Bpchar,
+        user_id -> Bpchar,
+        key -> Text,
+        content_type -> Text,
+        size -> Int8,
+        created_at -> Timestamp,
+        width -> Nullable<Int4>,
+        height -> Nullable<Int4>,
+        processed_at -> Nullable<Timestamp>,
+        claimed_at -> Nullable<Timestamp>,
     }
 }
 
@@ -70,16 +318,60 @@ diesel::table! {
 }
 
 diesel::joinable!(blocks -> documents (document_id));
+diesel::joinable!(blocks -> users (last_edited_by));
+diesel::joinable!(callout_blocks -> blocks (block_id));
+diesel::joinable!(code_blocks -> blocks (block_id));
+diesel::joinable!(database_properties -> documents (database_id));
+diesel::joinable!(database_views -> database_properties (property_id));
+diesel::joinable!(database_views -> documents (database_id));
+diesel::joinable!(divider_blocks -> blocks (block_id));
+diesel::joinable!(document_links -> blocks (block_id));
+diesel::joinable!(document_revisions -> documents (document_id));
+diesel::joinable!(document_revisions -> users (user_id));
 diesel::joinable!(documents -> repositories (repository_id));
 diesel::joinable!(image_blocks -> blocks (block_id));
+diesel::joinable!(list_item_blocks -> blocks (block_id));
+diesel::joinable!(math_blocks -> blocks (block_id));
+diesel::joinable!(property_values -> database_properties (property_id));
+diesel::joinable!(property_values -> documents (document_id));
+diesel::joinable!(property_values -> users (user_id));
+diesel::joinable!(quote_blocks -> blocks (block_id));
 diesel::joinable!(repositories -> users (user_id));
+diesel::joinable!(repository_invitations -> repositories (repository_id));
+diesel::joinable!(repository_invitations -> users (invited_by));
+diesel::joinable!(repository_members -> repositories (repository_id));
+diesel::joinable!(repository_members -> users (user_id));
+diesel::joinable!(table_blocks -> blocks (block_id));
 diesel::joinable!(text_blocks -> blocks (block_id));
+diesel::joinable!(todo_blocks -> blocks (block_id));
+diesel::joinable!(toggle_blocks -> blocks (block_id));
+diesel::joinable!(upload_variants -> uploads (upload_id));
+diesel::joinable!(uploads -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     blocks,
+    callout_blocks,
+    code_blocks,
+    database_properties,
+    database_views,
+    divider_blocks,
+    document_links,
+    document_revisions,
     documents,
+    embed_blocks,
     image_blocks,
+    list_item_blocks,
+    math_blocks,
+    property_values,
+    quote_blocks,
     repositories,
+    repository_invitations,
+    repository_members,
+    table_blocks,
     text_blocks,
+    todo_blocks,
+    toggle_blocks,
+    upload_variants,
+    uploads,
     users,
 );
@@ -1,17 +1,31 @@
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
 
 use actix_web::cookie::CookieJar;
 use juniper::{graphql_object, RootNode, EmptySubscription};
 
-use crate::{db::DBPool, models::{user::{UserResult, UserOperation}, repository::{RepositoryOperation, RepositoryResult}}, helpers::{validate::Validate, errors::FieldErrors, auth::{set_authed_user, get_authed_user}}};
+use crate::{db::{DBPool, DBPooledConnection}, models::{user::{User, UserResult, UserOperation}, block::{BlockOperation, BlockResult, MoveBlocksError, NewBlockContent, Tag, ListStyle, CalloutVariant}, repository::{RepositoryOperation, RepositoryResult}, document::{DocumentOperation, DocumentResult}, revision::{RevisionOperation, DocumentRevisionResult}, diff::{DiffOperation, DocumentDiffResult}, search::{SearchOperation, SearchResult}, quick_find::{QuickFindOperation, QuickFindResult, DEFAULT_LIMIT, MAX_LIMIT}, markdown::{MarkdownOperation, MarkdownImportResult, MAX_MARKDOWN_LENGTH}, upload::{UploadOperation, UploadResult}, accessibility::{AccessibilityOperation, AccessibilityReportResult}, rich_text::{self, TextRun, TextRunInput}, table::{self, TableOperation, TableColumn, TableColumnInput, TableCellInput}, database::{self, DatabaseOperation, DatabasePropertyResult, DatabaseViewResult, DatabaseRowsResult, PropertyType, ViewLayout, PropertyFilterInput, PropertySortInput}, link::{LinkOperation, RepositoryGraphResult}, embed::{EmbedOperation, EmbedStatus, Reader}, member::{MemberOperation, MemberRole, RepositoryMemberResult}, invitation::{InvitationOperation, RepositoryInvitationResult, RepositoryInvitationListResult}}, helpers::{blob_store::BlobStore, mail::Mailer, validate::Validate, errors::{FieldErrors, FieldError}, auth::{set_authed_user, get_authed_user}, permission::Permission}};
 
 pub struct Context {
     pub cookie_jar: RwLock<CookieJar>,
     pub db_pool: DBPool,
+    pub blob_store: Arc<dyn BlobStore>,
+    pub mailer: Arc<dyn Mailer>,
 }
 
 impl juniper::Context for Context {}
 
+impl Context {
+    pub fn authed_user(&self) -> Option<User> {
+        let mut conn = self.db_pool.get().unwrap();
+        let mut jar = self.cookie_jar.write().unwrap();
+
+        match get_authed_user(&mut conn, &mut jar) {
+            UserResult::User(user) => Some(user),
+            _ => None,
+        }
+    }
+}
+
 pub struct QueryRoot;
 
 #[graphql_object(Context = Context)]
@@ -40,16 +54,174 @@ impl QueryRoot {
     }
 
     fn repository(context: &Context, id: String) -> RepositoryResult {
+        let Some(user) = context.authed_user() else {
+            return RepositoryResult::unauthorized("must be signed in");
+        };
+
         let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_read_repository(&mut conn, &user.id, &id) {
+            return RepositoryResult::not_found("repository not found");
+        }
+
         RepositoryOperation::find(&mut conn, &id)
     }
+
+    fn document(context: &Context, id: String) -> DocumentResult {
+        let Some(user) = context.authed_user() else {
+            return DocumentResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_read_document(&mut conn, &user.id, &id) {
+            return DocumentResult::not_found("document not found");
+        }
+
+        DocumentOperation::find(&mut conn, &id)
+    }
+
+    fn repositoryGraph(context: &Context, repository_id: String) -> RepositoryGraphResult {
+        let Some(user) = context.authed_user() else {
+            return RepositoryGraphResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_read_repository(&mut conn, &user.id, &repository_id) {
+            return RepositoryGraphResult::not_found("repository not found");
+        }
+
+        LinkOperation::graph(&mut conn, &repository_id)
+    }
+
+    fn repositoryInvitations(context: &Context, repository_id: String) -> RepositoryInvitationListResult {
+        let Some(user) = context.authed_user() else {
+            return RepositoryInvitationListResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_manage_repository(&mut conn, &user.id, &repository_id) {
+            return RepositoryInvitationListResult::unauthorized("can't manage this repository");
+        }
+
+        InvitationOperation::find_by_repository(&mut conn, &repository_id)
+    }
+
+    fn documentDiff(context: &Context, from_revision: String, to_revision: String) -> DocumentDiffResult {
+        let Some(user) = context.authed_user() else {
+            return DocumentDiffResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        DiffOperation::diff(&mut conn, &user.id, &from_revision, &to_revision)
+    }
+
+    fn search(context: &Context, query: String, repository_id: Option<String>) -> SearchResult {
+        let Some(user) = context.authed_user() else {
+            return SearchResult::unauthorized("must be signed in");
+        };
+
+        let mut errors = FieldErrors::new();
+
+        if query.trim().is_empty() {
+            errors.push(FieldError::new("query", "can't be empty"));
+        }
+
+        if !errors.empty() {
+            return SearchResult::FieldErrors(errors);
+        }
+
+        let mut conn = context.db_pool.get().unwrap();
+        SearchOperation::search(&mut conn, &user.id, &query, repository_id.as_deref())
+    }
+
+    fn accessibilityReport(context: &Context, repository_id: String) -> AccessibilityReportResult {
+        let Some(user) = context.authed_user() else {
+            return AccessibilityReportResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_read_repository(&mut conn, &user.id, &repository_id) {
+            return AccessibilityReportResult::not_found("repository not found");
+        }
+
+        AccessibilityOperation::report(&mut conn, &repository_id)
+    }
+
+    fn quickFind(context: &Context, term: String, limit: Option<i32>) -> QuickFindResult {
+        let Some(user) = context.authed_user() else {
+            return QuickFindResult::unauthorized("must be signed in");
+        };
+
+        let mut errors = FieldErrors::new();
+        let limit = limit.unwrap_or(DEFAULT_LIMIT);
+
+        if term.trim().is_empty() {
+            errors.push(FieldError::new("term", "can't be empty"));
+        }
+
+        if !(1..=MAX_LIMIT).contains(&limit) {
+            errors.push(FieldError::new("limit", &format!("must be between 1 and {}", MAX_LIMIT)));
+        }
+
+        if !errors.empty() {
+            return QuickFindResult::FieldErrors(errors);
+        }
+
+        let mut conn = context.db_pool.get().unwrap();
+        QuickFindOperation::find(&mut conn, &user.id, term.trim(), limit)
+    }
+
+    fn queryDatabase(context: &Context, document_id: String, filter: Option<Vec<PropertyFilterInput>>, sort: Option<Vec<PropertySortInput>>, first: Option<i32>, after: Option<String>, view_id: Option<String>) -> DatabaseRowsResult {
+        let Some(user) = context.authed_user() else {
+            return DatabaseRowsResult::unauthorized("must be signed in");
+        };
+
+        let mut errors = FieldErrors::new();
+        let first = first.unwrap_or(database::DEFAULT_PAGE_SIZE);
+
+        if !(1..=database::MAX_PAGE_SIZE).contains(&first) {
+            errors.push(FieldError::new("first", &format!("must be between 1 and {}", database::MAX_PAGE_SIZE)));
+        }
+
+        if view_id.is_some() && (filter.is_some() || sort.is_some()) {
+            errors.push(FieldError::new("viewId", "can't be combined with filter or sort"));
+        }
+
+        if !errors.empty() {
+            return DatabaseRowsResult::FieldErrors(errors);
+        }
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_read_document(&mut conn, &user.id, &document_id) {
+            return DatabaseRowsResult::not_found("document not found");
+        }
+
+        let Some(view_id) = view_id else {
+            let filters = filter.unwrap_or_default().into_iter().map(Into::into).collect::<Vec<_>>();
+            let sorts = sort.unwrap_or_default().into_iter().map(Into::into).collect::<Vec<_>>();
+
+            return DatabaseOperation::query(&mut conn, &document_id, &filters, &sorts, first, after.as_deref());
+        };
+
+        match DatabaseOperation::find_view(&mut conn, &view_id) {
+            DatabaseViewResult::DatabaseView(view) if view.database_id == document_id =>
+                DatabaseOperation::query_view(&mut conn, &view, first, after.as_deref()),
+            _ => DatabaseRowsResult::not_found("view not found"),
+        }
+    }
 }
 
 pub struct MutationRoot;
 
 #[graphql_object(Context = Context)]
 impl MutationRoot {
-    fn createUser(context: &Context, email: String, password: String) -> UserResult {
+    fn createUser(context: &Context, email: String, password: String, invitation_token: Option<String>) -> UserResult {
         let mut errors = FieldErrors::new();
 
         Validate::email("email", &email, &mut errors);
@@ -60,13 +232,733 @@ impl MutationRoot {
         }
 
         let mut conn = context.db_pool.get().unwrap();
-        UserOperation::create(&mut conn, &email, &password)
+
+        if let Some(token) = &invitation_token {
+            match InvitationOperation::find_pending(&mut conn, token) {
+                Some(invitation) if invitation.email == email.to_lowercase() => {},
+                Some(_) => { errors.push(FieldError::new("invitationToken", "was sent to another email")); },
+                None => { errors.push(FieldError::new("invitationToken", "is invalid or has expired")); },
+            }
+
+            if !errors.empty() {
+                return UserResult::FieldErrors(errors);
+            }
+        }
+
+        let user = UserOperation::create(&mut conn, &email, &password);
+
+        // The invitation was checked above, it can only have been revoked
+        // since, which leaves the user as if they signed up without it.
+        if let (UserResult::User(user), Some(token)) = (&user, &invitation_token) {
+            InvitationOperation::accept(&mut conn, user, token, "invitationToken");
+        }
+
+        user
+    }
+
+    fn createRepository(context: &Context, slug: String, name: String, description: Option<String>) -> RepositoryResult {
+        let Some(user) = context.authed_user() else {
+            return RepositoryResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+        RepositoryOperation::create(&mut conn, &user.id, &slug, &name, description.as_deref())
+    }
+
+    fn publishRepository(context: &Context, id: String) -> RepositoryResult {
+        let Some(user) = context.authed_user() else {
+            return RepositoryResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_manage_repository(&mut conn, &user.id, &id) {
+            return RepositoryResult::unauthorized("can't manage this repository");
+        }
+
+        RepositoryOperation::publish(&mut conn, &id)
+    }
+
+    fn unpublishRepository(context: &Context, id: String) -> RepositoryResult {
+        let Some(user) = context.authed_user() else {
+            return RepositoryResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_manage_repository(&mut conn, &user.id, &id) {
+            return RepositoryResult::unauthorized("can't manage this repository");
+        }
+
+        RepositoryOperation::unpublish(&mut conn, &id)
     }
 
-    fn createRepository(context: &Context, user_id: String, slug: String, name: String, description: Option<String>) -> RepositoryResult {
+    fn createRevision(context: &Context, document_id: String) -> DocumentRevisionResult {
+        let Some(user) = context.authed_user() else {
+            return DocumentRevisionResult::unauthorized("must be signed in");
+        };
+
         let mut conn = context.db_pool.get().unwrap();
-        RepositoryOperation::create(&mut conn, &user_id, &slug, &name, description.as_deref())
+
+        if !Permission::can_write_document(&mut conn, &user.id, &document_id) {
+            return DocumentRevisionResult::unauthorized("can't edit this document");
+        }
+
+        RevisionOperation::create(&mut conn, &document_id, &user.id)
     }
+
+    fn addCollaborator(context: &Context, repository_id: String, email: String, role: MemberRole) -> RepositoryMemberResult {
+        let Some(user) = context.authed_user() else {
+            return RepositoryMemberResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::role(&mut conn, &user.id, &repository_id).is_some_and(|own| own.can_manage() && own.can_assign(role)) {
+            return RepositoryMemberResult::unauthorized("can't give this role in this repository");
+        }
+
+        MemberOperation::add(&mut conn, &repository_id, email.trim(), role)
+    }
+
+    fn updateCollaboratorRole(context: &Context, repository_id: String, user_id: String, role: MemberRole) -> RepositoryMemberResult {
+        let Some(user) = context.authed_user() else {
+            return RepositoryMemberResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        let Some(current) = Permission::role(&mut conn, &user_id, &repository_id) else {
+            return RepositoryMemberResult::not_found("collaborator not found");
+        };
+
+        if !Permission::role(&mut conn, &user.id, &repository_id).is_some_and(|own| own.can_manage() && own.can_assign(current) && own.can_assign(role)) {
+            return RepositoryMemberResult::unauthorized("can't give this role in this repository");
+        }
+
+        MemberOperation::update_role(&mut conn, &repository_id, &user_id, role)
+    }
+
+    fn inviteToRepository(context: &Context, repository_id: String, email: String, role: MemberRole) -> RepositoryInvitationResult {
+        let Some(user) = context.authed_user() else {
+            return RepositoryInvitationResult::unauthorized("must be signed in");
+        };
+
+        let mut errors = FieldErrors::new();
+
+        Validate::email("email", email.trim(), &mut errors);
+
+        if !errors.empty() {
+            return RepositoryInvitationResult::FieldErrors(errors);
+        }
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::role(&mut conn, &user.id, &repository_id).is_some_and(|own| own.can_manage() && own.can_assign(role)) {
+            return RepositoryInvitationResult::unauthorized("can't give this role in this repository");
+        }
+
+        InvitationOperation::invite(&mut conn, context.mailer.as_ref(), &user, &repository_id, &email, role)
+    }
+
+    fn revokeInvitation(context: &Context, id: String) -> RepositoryInvitationResult {
+        let Some(user) = context.authed_user() else {
+            return RepositoryInvitationResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        let invitation = match InvitationOperation::find(&mut conn, &id) {
+            RepositoryInvitationResult::RepositoryInvitation(invitation) => invitation,
+            result => return result,
+        };
+
+        if !Permission::role(&mut conn, &user.id, &invitation.repository_id).is_some_and(|own| own.can_manage() && own.can_assign(invitation.role)) {
+            return RepositoryInvitationResult::unauthorized("can't revoke this invitation");
+        }
+
+        InvitationOperation::revoke(&mut conn, &id)
+    }
+
+    fn acceptInvitation(context: &Context, token: String) -> RepositoryMemberResult {
+        let Some(user) = context.authed_user() else {
+            return RepositoryMemberResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+        InvitationOperation::accept(&mut conn, &user, &token, "token")
+    }
+
+    fn removeCollaborator(context: &Context, repository_id: String, user_id: String) -> RepositoryMemberResult {
+        let Some(user) = context.authed_user() else {
+            return RepositoryMemberResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        let Some(current) = Permission::role(&mut conn, &user_id, &repository_id) else {
+            return RepositoryMemberResult::not_found("collaborator not found");
+        };
+
+        // Anyone can leave a repository, removing others takes being able to give their role.
+        let can_remove = user_id == user.id
+            || Permission::role(&mut conn, &user.id, &repository_id).is_some_and(|own| own.can_manage() && own.can_assign(current));
+
+        if !can_remove {
+            return RepositoryMemberResult::unauthorized("can't remove this collaborator");
+        }
+
+        MemberOperation::remove(&mut conn, &repository_id, &user_id)
+    }
+
+    fn createTextBlock(context: &Context, document_id: String, parent_id: Option<String>, line_number: i32, tag: Tag, content: Option<String>, rich_text: Option<Vec<TextRunInput>>) -> BlockResult {
+        match text_content(tag, content, rich_text) {
+            Ok(content) => create_block(context, &document_id, parent_id.as_deref(), line_number, content),
+            Err(errors) => BlockResult::FieldErrors(errors),
+        }
+    }
+
+    fn createImageBlock(context: &Context, document_id: String, parent_id: Option<String>, line_number: i32, url: Option<String>, alt: Option<String>, caption: Option<String>) -> BlockResult {
+        create_block(context, &document_id, parent_id.as_deref(), line_number, NewBlockContent::Image { url, alt, caption })
+    }
+
+    fn createListItemBlock(context: &Context, document_id: String, parent_id: Option<String>, line_number: i32, style: ListStyle, indent: Option<i32>, content: Option<String>) -> BlockResult {
+        create_block(context, &document_id, parent_id.as_deref(), line_number, NewBlockContent::ListItem { style, indent: indent.unwrap_or(0), content })
+    }
+
+    fn createTodoBlock(context: &Context, document_id: String, parent_id: Option<String>, line_number: i32, checked: Option<bool>, indent: Option<i32>, content: Option<String>) -> BlockResult {
+        create_block(context, &document_id, parent_id.as_deref(), line_number, NewBlockContent::Todo { checked: checked.unwrap_or(false), indent: indent.unwrap_or(0), content })
+    }
+
+    fn createQuoteBlock(context: &Context, document_id: String, parent_id: Option<String>, line_number: i32, content: Option<String>) -> BlockResult {
+        create_block(context, &document_id, parent_id.as_deref(), line_number, NewBlockContent::Quote { content })
+    }
+
+    fn createCodeBlock(context: &Context, document_id: String, parent_id: Option<String>, line_number: i32, language: Option<String>, content: Option<String>) -> BlockResult {
+        create_block(context, &document_id, parent_id.as_deref(), line_number, NewBlockContent::Code { language, content })
+    }
+
+    fn createMathBlock(context: &Context, document_id: String, parent_id: Option<String>, line_number: i32, content: Option<String>) -> BlockResult {
+        create_block(context, &document_id, parent_id.as_deref(), line_number, NewBlockContent::Math { content })
+    }
+
+    fn createEmbedBlock(context: &Context, document_id: String, parent_id: Option<String>, line_number: i32, source_block_id: String) -> BlockResult {
+        match embed_content(context, None, source_block_id) {
+            Ok(content) => create_block(context, &document_id, parent_id.as_deref(), line_number, content),
+            Err(errors) => BlockResult::FieldErrors(errors),
+        }
+    }
+
+    fn createCalloutBlock(context: &Context, document_id: String, parent_id: Option<String>, line_number: i32, variant: CalloutVariant, content: Option<String>) -> BlockResult {
+        create_block(context, &document_id, parent_id.as_deref(), line_number, NewBlockContent::Callout { variant, content })
+    }
+
+    fn createToggleBlock(context: &Context, document_id: String, parent_id: Option<String>, line_number: i32, content: Option<String>) -> BlockResult {
+        create_block(context, &document_id, parent_id.as_deref(), line_number, NewBlockContent::Toggle { content })
+    }
+
+    fn createDividerBlock(context: &Context, document_id: String, parent_id: Option<String>, line_number: i32) -> BlockResult {
+        create_block(context, &document_id, parent_id.as_deref(), line_number, NewBlockContent::Divider)
+    }
+
+    fn createTableBlock(context: &Context, document_id: String, parent_id: Option<String>, line_number: i32, columns: Vec<TableColumnInput>) -> BlockResult {
+        let columns = columns.into_iter().map(TableColumn::new).collect();
+
+        create_block(context, &document_id, parent_id.as_deref(), line_number, NewBlockContent::Table { columns, rows: Vec::new() })
+    }
+
+    fn updateTextBlock(context: &Context, id: String, tag: Tag, content: Option<String>, rich_text: Option<Vec<TextRunInput>>) -> BlockResult {
+        match text_content(tag, content, rich_text) {
+            Ok(content) => update_block(context, &id, content),
+            Err(errors) => BlockResult::FieldErrors(errors),
+        }
+    }
+
+    fn updateImageBlock(context: &Context, id: String, url: Option<String>, alt: Option<String>, caption: Option<String>) -> BlockResult {
+        update_block(context, &id, NewBlockContent::Image { url, alt, caption })
+    }
+
+    fn updateListItemBlock(context: &Context, id: String, style: ListStyle, indent: i32, content: Option<String>) -> BlockResult {
+        update_block(context, &id, NewBlockContent::ListItem { style, indent, content })
+    }
+
+    fn updateTodoBlock(context: &Context, id: String, checked: bool, indent: i32, content: Option<String>) -> BlockResult {
+        update_block(context, &id, NewBlockContent::Todo { checked, indent, content })
+    }
+
+    fn updateQuoteBlock(context: &Context, id: String, content: Option<String>) -> BlockResult {
+        update_block(context, &id, NewBlockContent::Quote { content })
+    }
+
+    fn updateCodeBlock(context: &Context, id: String, language: Option<String>, content: Option<String>) -> BlockResult {
+        update_block(context, &id, NewBlockContent::Code { language, content })
+    }
+
+    fn updateMathBlock(context: &Context, id: String, content: Option<String>) -> BlockResult {
+        update_block(context, &id, NewBlockContent::Math { content })
+    }
+
+    fn updateEmbedBlock(context: &Context, id: String, source_block_id: String) -> BlockResult {
+        match embed_content(context, Some(&id), source_block_id) {
+            Ok(content) => update_block(context, &id, content),
+            Err(errors) => BlockResult::FieldErrors(errors),
+        }
+    }
+
+    fn updateCalloutBlock(context: &Context, id: String, variant: CalloutVariant, content: Option<String>) -> BlockResult {
+        update_block(context, &id, NewBlockContent::Callout { variant, content })
+    }
+
+    fn updateToggleBlock(context: &Context, id: String, content: Option<String>) -> BlockResult {
+        update_block(context, &id, NewBlockContent::Toggle { content })
+    }
+
+    fn addColumn(context: &Context, block_id: String, column: TableColumnInput, position: Option<i32>) -> BlockResult {
+        let column = TableColumn::new(column);
+        let mut errors = FieldErrors::new();
+
+        table::validate_column("column", &column, &mut errors);
+
+        if !errors.empty() {
+            return BlockResult::FieldErrors(errors);
+        }
+
+        edit_table(context, &block_id, |conn, user_id| TableOperation::add_column(conn, user_id, &block_id, column, position))
+    }
+
+    fn reorderColumns(context: &Context, block_id: String, column_ids: Vec<String>) -> BlockResult {
+        edit_table(context, &block_id, |conn, user_id| TableOperation::reorder_columns(conn, user_id, &block_id, &column_ids))
+    }
+
+    fn addRow(context: &Context, block_id: String, cells: Option<Vec<TableCellInput>>, position: Option<i32>) -> BlockResult {
+        edit_table(context, &block_id, |conn, user_id| TableOperation::add_row(conn, user_id, &block_id, cells.unwrap_or_default(), position))
+    }
+
+    fn updateCell(context: &Context, block_id: String, row_id: String, column_id: String, value: Option<String>) -> BlockResult {
+        edit_table(context, &block_id, |conn, user_id| TableOperation::update_cell(conn, user_id, &block_id, &row_id, &column_id, value.as_deref()))
+    }
+
+    fn moveBlock(context: &Context, id: String, parent_id: Option<String>, line_number: i32) -> BlockResult {
+        let Some(user) = context.authed_user() else {
+            return BlockResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_write_block(&mut conn, &user.id, &id) {
+            return BlockResult::unauthorized("can't edit this block");
+        }
+
+        BlockOperation::move_to(&mut conn, &user.id, &id, parent_id.as_deref(), line_number)
+    }
+
+    fn indentBlock(context: &Context, id: String) -> BlockResult {
+        let Some(user) = context.authed_user() else {
+            return BlockResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_write_block(&mut conn, &user.id, &id) {
+            return BlockResult::unauthorized("can't edit this block");
+        }
+
+        BlockOperation::indent(&mut conn, &user.id, &id)
+    }
+
+    fn outdentBlock(context: &Context, id: String) -> BlockResult {
+        let Some(user) = context.authed_user() else {
+            return BlockResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_write_block(&mut conn, &user.id, &id) {
+            return BlockResult::unauthorized("can't edit this block");
+        }
+
+        BlockOperation::outdent(&mut conn, &user.id, &id)
+    }
+
+    fn makeDatabase(context: &Context, document_id: String) -> DocumentResult {
+        let Some(user) = context.authed_user() else {
+            return DocumentResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_write_document(&mut conn, &user.id, &document_id) {
+            return DocumentResult::unauthorized("can't edit this document");
+        }
+
+        DatabaseOperation::make_database(&mut conn, &document_id)
+    }
+
+    #[graphql(arguments(property_type(name = "type")))]
+    fn addDatabaseProperty(context: &Context, document_id: String, name: String, property_type: PropertyType, options: Option<Vec<String>>) -> DatabasePropertyResult {
+        let Some(user) = context.authed_user() else {
+            return DatabasePropertyResult::unauthorized("must be signed in");
+        };
+
+        let mut errors = FieldErrors::new();
+
+        Validate::name("name", &name, &mut errors);
+
+        if !errors.empty() {
+            return DatabasePropertyResult::FieldErrors(errors);
+        }
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_write_document(&mut conn, &user.id, &document_id) {
+            return DatabasePropertyResult::unauthorized("can't edit this document");
+        }
+
+        DatabaseOperation::add_property(&mut conn, &document_id, name.trim(), property_type, options.unwrap_or_default())
+    }
+
+    fn deleteDatabaseProperty(context: &Context, id: String) -> DatabasePropertyResult {
+        let Some(user) = context.authed_user() else {
+            return DatabasePropertyResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        match DatabaseOperation::find_property(&mut conn, &id) {
+            DatabasePropertyResult::DatabaseProperty(property) if Permission::can_write_document(&mut conn, &user.id, &property.database_id) => {},
+            DatabasePropertyResult::DatabaseProperty(_) => return DatabasePropertyResult::unauthorized("can't edit this document"),
+            result => return result,
+        }
+
+        DatabaseOperation::delete_property(&mut conn, &id)
+    }
+
+    fn createDatabaseRow(context: &Context, database_id: String, name: String) -> DocumentResult {
+        let Some(user) = context.authed_user() else {
+            return DocumentResult::unauthorized("must be signed in");
+        };
+
+        let mut errors = FieldErrors::new();
+
+        Validate::name("name", &name, &mut errors);
+
+        if !errors.empty() {
+            return DocumentResult::FieldErrors(errors);
+        }
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_write_document(&mut conn, &user.id, &database_id) {
+            return DocumentResult::unauthorized("can't edit this document");
+        }
+
+        DatabaseOperation::create_row(&mut conn, &database_id, name.trim())
+    }
+
+    fn setPropertyValue(context: &Context, document_id: String, property_id: String, value: Option<String>, values: Option<Vec<String>>) -> DocumentResult {
+        let Some(user) = context.authed_user() else {
+            return DocumentResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_write_document(&mut conn, &user.id, &document_id) {
+            return DocumentResult::unauthorized("can't edit this document");
+        }
+
+        DatabaseOperation::set_value(&mut conn, &document_id, &property_id, value.as_deref(), values)
+    }
+
+    fn createDatabaseView(context: &Context, document_id: String, name: String, layout: ViewLayout, property_id: Option<String>, filter: Option<Vec<PropertyFilterInput>>, sort: Option<Vec<PropertySortInput>>) -> DatabaseViewResult {
+        let Some(user) = context.authed_user() else {
+            return DatabaseViewResult::unauthorized("must be signed in");
+        };
+
+        let mut errors = FieldErrors::new();
+
+        Validate::name("name", &name, &mut errors);
+
+        if !errors.empty() {
+            return DatabaseViewResult::FieldErrors(errors);
+        }
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_write_document(&mut conn, &user.id, &document_id) {
+            return DatabaseViewResult::unauthorized("can't edit this document");
+        }
+
+        let filters = filter.unwrap_or_default().into_iter().map(Into::into).collect();
+        let sorts = sort.unwrap_or_default().into_iter().map(Into::into).collect();
+
+        DatabaseOperation::create_view(&mut conn, &document_id, name.trim(), layout, property_id.as_deref(), filters, sorts)
+    }
+
+    fn deleteDatabaseView(context: &Context, id: String) -> DatabaseViewResult {
+        let Some(user) = context.authed_user() else {
+            return DatabaseViewResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        match DatabaseOperation::find_view(&mut conn, &id) {
+            DatabaseViewResult::DatabaseView(view) if Permission::can_write_document(&mut conn, &user.id, &view.database_id) => {},
+            DatabaseViewResult::DatabaseView(_) => return DatabaseViewResult::unauthorized("can't edit this document"),
+            result => return result,
+        }
+
+        DatabaseOperation::delete_view(&mut conn, &id)
+    }
+
+    fn importMarkdown(context: &Context, repository_id: String, parent_id: Option<String>, name: String, markdown: String) -> MarkdownImportResult {
+        let Some(user) = context.authed_user() else {
+            return MarkdownImportResult::unauthorized("must be signed in");
+        };
+
+        let mut errors = FieldErrors::new();
+
+        Validate::name("name", &name, &mut errors);
+
+        if markdown.len() > MAX_MARKDOWN_LENGTH {
+            errors.push(FieldError::new("markdown", &format!("can't be more than {} bytes", MAX_MARKDOWN_LENGTH)));
+        }
+
+        if !errors.empty() {
+            return MarkdownImportResult::FieldErrors(errors);
+        }
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_write_repository(&mut conn, &user.id, &repository_id) {
+            return MarkdownImportResult::unauthorized("can't edit this repository");
+        }
+
+        if let Some(parent_id) = &parent_id {
+            if !matches!(DocumentOperation::find(&mut conn, parent_id), DocumentResult::Document(parent) if parent.repository_id == repository_id) {
+                let mut errors = FieldErrors::new();
+                errors.push(FieldError::new("parentId", "isn't a document of this repository"));
+                return MarkdownImportResult::FieldErrors(errors);
+            }
+        }
+
+        MarkdownOperation::import(&mut conn, &user.id, &repository_id, parent_id.as_deref(), name.trim(), &markdown)
+    }
+
+    #[graphql(description = "Moves a document and the documents under it, to another repository with targetRepositoryId. They keep their IDs and slugs, they aren't copied")]
+    fn moveDocument(context: &Context, id: String, new_parent_id: Option<String>, position: Option<i32>, target_repository_id: Option<String>) -> DocumentResult {
+        let Some(user) = context.authed_user() else {
+            return DocumentResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        let document = match DocumentOperation::find(&mut conn, &id) {
+            DocumentResult::Document(document) if Permission::can_write_document(&mut conn, &user.id, &id) => document,
+            DocumentResult::Document(_) => return DocumentResult::unauthorized("can't edit this document"),
+            result => return result,
+        };
+
+        match target_repository_id.filter(|target_repository_id| *target_repository_id != document.repository_id) {
+            Some(target_repository_id) => {
+                if !Permission::can_write_repository(&mut conn, &user.id, &target_repository_id) {
+                    return DocumentResult::unauthorized("can't edit the target repository");
+                }
+
+                DocumentOperation::move_to_repository(&mut conn, &id, &target_repository_id, new_parent_id.as_deref(), position)
+            },
+            None => DocumentOperation::move_to(&mut conn, &id, new_parent_id.as_deref(), position),
+        }
+    }
+
+    fn duplicateDocument(context: &Context, document_id: String, target_repository_id: Option<String>) -> DocumentResult {
+        let Some(user) = context.authed_user() else {
+            return DocumentResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        let document = match DocumentOperation::find(&mut conn, &document_id) {
+            DocumentResult::Document(document) if Permission::can_write_document(&mut conn, &user.id, &document_id) => document,
+            DocumentResult::Document(_) => return DocumentResult::unauthorized("can't edit this document"),
+            result => return result,
+        };
+
+        let target_repository_id = target_repository_id.unwrap_or(document.repository_id);
+
+        if !Permission::can_write_repository(&mut conn, &user.id, &target_repository_id) {
+            return DocumentResult::unauthorized("can't edit the target repository");
+        }
+
+        DocumentOperation::duplicate(&mut conn, &user.id, &document_id, &target_repository_id)
+    }
+
+    #[graphql(description = "Moves blocks and the blocks nested under them to another document as copies with new IDs, embeds of them embed the copies")]
+    fn moveBlocks(context: &Context, block_ids: Vec<String>, target_document_id: String, after_block_id: Option<String>) -> DocumentResult {
+        let Some(user) = context.authed_user() else {
+            return DocumentResult::unauthorized("must be signed in");
+        };
+
+        if block_ids.is_empty() {
+            let mut errors = FieldErrors::new();
+            errors.push(FieldError::new("blockIds", "can't be empty"));
+            return DocumentResult::FieldErrors(errors);
+        }
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !block_ids.iter().all(|id| Permission::can_write_block(&mut conn, &user.id, id)) {
+            return DocumentResult::unauthorized("can't edit these blocks");
+        }
+
+        if !Permission::can_write_document(&mut conn, &user.id, &target_document_id) {
+            return DocumentResult::unauthorized("can't edit the target document");
+        }
+
+        let result = BlockOperation::move_to_document(&mut conn, &user.id, &block_ids, &target_document_id, after_block_id.as_deref());
+
+        let message = match result {
+            Ok(()) => return DocumentOperation::find(&mut conn, &target_document_id),
+            Err(MoveBlocksError::Database(diesel::result::Error::NotFound)) => return DocumentResult::not_found("block not found"),
+            Err(MoveBlocksError::Database(_)) => return DocumentResult::server(),
+            Err(MoveBlocksError::AfterNotFound) => "isn't a block of the target document",
+            Err(MoveBlocksError::AfterMoved) => "can't be one of the moved blocks or nested under them",
+        };
+
+        let mut errors = FieldErrors::new();
+        errors.push(FieldError::new("afterBlockId", message));
+
+        DocumentResult::FieldErrors(errors)
+    }
+
+    fn deleteBlock(context: &Context, id: String) -> BlockResult {
+        let Some(user) = context.authed_user() else {
+            return BlockResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        if !Permission::can_write_block(&mut conn, &user.id, &id) {
+            return BlockResult::unauthorized("can't edit this block");
+        }
+
+        BlockOperation::delete(&mut conn, &id)
+    }
+
+    fn deleteUpload(context: &Context, id: String) -> UploadResult {
+        let Some(user) = context.authed_user() else {
+            return UploadResult::unauthorized("must be signed in");
+        };
+
+        let mut conn = context.db_pool.get().unwrap();
+
+        match UploadOperation::find(&mut conn, &id) {
+            Some(upload) if upload.user_id == user.id =>
+                UploadOperation::delete(&mut conn, context.blob_store.as_ref(), &id),
+            _ => UploadResult::not_found("upload not found"),
+        }
+    }
+}
+
+/// Text block content from either plain `content` or `rich_text` runs.
+fn text_content(tag: Tag, content: Option<String>, rich_text: Option<Vec<TextRunInput>>) -> Result<NewBlockContent, FieldErrors> {
+    let Some(rich_text) = rich_text else {
+        return Ok(NewBlockContent::Text { tag, content, rich_text: None });
+    };
+
+    let mut errors = FieldErrors::new();
+
+    if content.is_some() {
+        errors.push(FieldError::new("content", "can't be given together with richText"));
+    }
+
+    rich_text::validate("richText", &rich_text, &mut errors);
+
+    if !errors.empty() {
+        return Err(errors);
+    }
+
+    Ok(NewBlockContent::text(tag, rich_text.into_iter().map(TextRun::from).collect()))
+}
+
+/// Embed block content, as long as the signed in user can read the block
+/// to embed and embedding it doesn't lead back to block `id`.
+fn embed_content(context: &Context, id: Option<&str>, source_block_id: String) -> Result<NewBlockContent, FieldErrors> {
+    let Some(user) = context.authed_user() else {
+        return Ok(NewBlockContent::Embed { source_block_id: Some(source_block_id) });
+    };
+
+    let mut conn = context.db_pool.get().unwrap();
+    let mut errors = FieldErrors::new();
+
+    match EmbedOperation::resolve(&mut conn, Reader::User(&user.id), id, Some(&source_block_id)) {
+        Ok(_) => {},
+        Err(EmbedStatus::Cycle) => {
+            errors.push(FieldError::new("sourceBlockId", "would embed the block in itself"));
+        },
+        Err(_) => {
+            errors.push(FieldError::new("sourceBlockId", "block not found"));
+        },
+    }
+
+    if !errors.empty() {
+        return Err(errors);
+    }
+
+    Ok(NewBlockContent::Embed { source_block_id: Some(source_block_id) })
+}
+
+fn create_block(context: &Context, document_id: &str, parent_id: Option<&str>, line_number: i32, content: NewBlockContent) -> BlockResult {
+    let Some(user) = context.authed_user() else {
+        return BlockResult::unauthorized("must be signed in");
+    };
+
+    let errors = content.validate();
+
+    if !errors.empty() {
+        return BlockResult::FieldErrors(errors);
+    }
+
+    let mut conn = context.db_pool.get().unwrap();
+
+    if !Permission::can_write_document(&mut conn, &user.id, document_id) {
+        return BlockResult::unauthorized("can't edit this document");
+    }
+
+    BlockOperation::create(&mut conn, &user.id, document_id, parent_id, line_number, &content)
+}
+
+fn update_block(context: &Context, id: &str, content: NewBlockContent) -> BlockResult {
+    let Some(user) = context.authed_user() else {
+        return BlockResult::unauthorized("must be signed in");
+    };
+
+    let errors = content.validate();
+
+    if !errors.empty() {
+        return BlockResult::FieldErrors(errors);
+    }
+
+    let mut conn = context.db_pool.get().unwrap();
+
+    if !Permission::can_write_block(&mut conn, &user.id, id) {
+        return BlockResult::unauthorized("can't edit this block");
+    }
+
+    BlockOperation::update(&mut conn, &user.id, id, &content)
+}
+
+fn edit_table(context: &Context, block_id: &str, edit: impl FnOnce(&mut DBPooledConnection, &str) -> BlockResult) -> BlockResult {
+    let Some(user) = context.authed_user() else {
+        return BlockResult::unauthorized("must be signed in");
+    };
+
+    let mut conn = context.db_pool.get().unwrap();
+
+    if !Permission::can_write_block(&mut conn, &user.id, block_id) {
+        return BlockResult::unauthorized("can't edit this block");
+    }
+
+    edit(&mut conn, &user.id)
 }
 
 pub type Schema = RootNode<'static, QueryRoot, MutationRoot, EmptySubscription<Context>>;